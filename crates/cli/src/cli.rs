@@ -1,22 +1,32 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use config::LEGACY_CONFIG_PATH;
+use orchestrator::persistence::LEGACY_STATE_PATH;
 use std::path::{Path, PathBuf};
 
 /// Command line interface for preload-rs.
 #[derive(Debug, Parser, Clone)]
 #[command(about, long_about, version)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to a configuration file.
-    #[arg(short, long, value_name = "FILE")]
+    #[arg(short, long, value_name = "FILE", global = true)]
     pub config: Option<PathBuf>,
 
     /// Path to a directory containing additional TOML config files.
-    #[arg(long, value_name = "DIR")]
+    #[arg(long, value_name = "DIR", global = true)]
     pub config_dir: Option<PathBuf>,
 
-    /// Path to the state database.
-    #[arg(short, long, value_name = "FILE")]
+    /// Path to the state database, or a backend URL such as `file:///path`
+    /// (overrides `persistence.state_path` and `persistence.url`).
+    #[arg(short, long, value_name = "FILE", global = true)]
     pub state: Option<PathBuf>,
 
+    /// Path of the control socket (overrides `system.control_socket`).
+    #[arg(long, value_name = "FILE", global = true)]
+    pub socket: Option<PathBuf>,
+
     /// Run a single tick and exit.
     #[arg(long)]
     pub once: bool,
@@ -25,15 +35,103 @@ pub struct Cli {
     #[arg(long)]
     pub no_persist: bool,
 
+    /// Run as an ordinary user: scan only your own processes, skip the
+    /// configuration in /etc, and admit exes and maps below your home
+    /// directory. The control socket moves to `$XDG_RUNTIME_DIR`.
+    #[arg(long, global = true)]
+    pub user: bool,
+
     /// Disable prefetch I/O (observe/predict only).
     #[arg(long)]
     pub no_prefetch: bool,
 
+    /// Record every observation to a trace file while running.
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay a recorded trace instead of scanning, as fast as possible,
+    /// then save and exit. Prefetch I/O is disabled.
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Replay a recorded trace from an empty model and print how well the
+    /// configured predictor anticipated each exe start, then exit.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record", "replay"])]
+    pub evaluate: Option<PathBuf>,
+
+    /// Read the state database instead of asking the running daemon
+    /// (`status`, `predict`, `explain`).
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Print machine-readable JSON instead of text.
+    #[arg(long, global = true)]
+    pub json: bool,
+
     /// Increase verbosity (-v, -vv, -vvv).
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     pub verbose: u8,
 }
 
+/// Client commands. Without one, preload-rs runs as a daemon.
+#[derive(Debug, Subcommand, Clone)]
+pub enum Command {
+    /// Show model size, admission cache stats and the last tick.
+    Status,
+    /// List the exes and maps most likely to be needed next.
+    Predict {
+        /// Number of exes and maps to list.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show which Markov edges contribute to an exe's score.
+    Explain {
+        /// Path of the executable.
+        path: PathBuf,
+    },
+    /// Write the state database as portable JSON.
+    Export {
+        /// Output file (default: stdout).
+        file: Option<PathBuf>,
+    },
+    /// Replace the state database with a model exported as JSON. The daemon
+    /// must not be running.
+    Import {
+        /// File written by `export`.
+        file: PathBuf,
+    },
+    /// Replace the state database with the model learned by the original C
+    /// preload daemon. The daemon must not be running.
+    ImportLegacy {
+        /// preload's state file.
+        #[arg(default_value = LEGACY_STATE_PATH)]
+        file: PathBuf,
+    },
+    /// Convert the original C preload's `preload.conf` to a TOML config.
+    /// Options with no equivalent are reported and skipped.
+    ConvertConfig {
+        /// preload's configuration file.
+        #[arg(default_value = LEGACY_CONFIG_PATH)]
+        file: PathBuf,
+        /// Output file (default: stdout).
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Summarize a state database. It is opened read-only and no daemon is
+    /// contacted.
+    Inspect {
+        /// Database or backend URL to read (default: `--state` or the
+        /// configured state).
+        file: Option<PathBuf>,
+        /// Rows listed per section.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Path components that make up a directory prefix.
+        #[arg(long, default_value_t = 2)]
+        depth: usize,
+    },
+}
+
 impl Cli {
     /// Resolve configuration paths in precedence order (earlier overridden by later).
     pub fn resolve_config_paths(&self) -> Result<Vec<PathBuf>, std::io::Error> {
@@ -50,16 +148,19 @@ impl Cli {
             return Ok(paths);
         }
 
-        if let Some(path) = system_config_path()
-            && path.exists()
-        {
-            paths.push(path);
-        }
+        // The system configuration is written for the system daemon.
+        if !self.user {
+            if let Some(path) = system_config_path()
+                && path.exists()
+            {
+                paths.push(path);
+            }
 
-        if let Some(dir) = system_config_dir()
-            && dir.is_dir()
-        {
-            paths.extend(collect_toml(&dir, false)?);
+            if let Some(dir) = system_config_dir()
+                && dir.is_dir()
+            {
+                paths.extend(collect_toml(&dir, false)?);
+            }
         }
 
         if let Some(path) = user_config_path()
@@ -81,6 +182,18 @@ impl Cli {
     }
 }
 
+/// Where the state lives when none is configured: `/var/lib/preload-rs` for
+/// root, the XDG state directory for everyone else.
+pub fn default_state_path() -> Option<PathBuf> {
+    if nix::unistd::geteuid().is_root() {
+        return Some(PathBuf::from("/var/lib/preload-rs/state.db"));
+    }
+    let xdg = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")));
+    xdg.map(|dir| dir.join("preload-rs").join("state.db"))
+}
+
 fn ensure_file_exists(path: &Path) -> Result<(), std::io::Error> {
     if path.exists() {
         Ok(())
@@ -0,0 +1,252 @@
+#![forbid(unsafe_code)]
+
+//! `status`, `predict` and `explain`: ask the running daemon over its control
+//! socket, or load the state database when no daemon answers.
+
+use crate::cli::{Cli, Command};
+use crate::{build_reload_bundle, control_socket_path, open_repository, use_default_state};
+use config::Config;
+use orchestrator::{
+    PreloadEngine, Services,
+    clock::SystemClock,
+    control::{ControlClient, ControlRequest, ControlResponse, StatusReport, TopPredictions},
+    engine::TickReport,
+    observation::ProcfsScanner,
+    prediction::Explanation,
+    prefetch::NoopMapValidator,
+};
+use std::io::ErrorKind;
+use tracing::info;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Where answers come from.
+enum Source {
+    Daemon(ControlClient),
+    /// A model loaded from the state database. Nothing is running in it, so
+    /// every known exe is scored.
+    Offline(Box<PreloadEngine>),
+}
+
+impl Source {
+    async fn open(cli: &Cli, config: Config) -> anyhow::Result<Self> {
+        let socket = control_socket_path(cli, &config);
+        if let (false, Some(socket)) = (cli.offline, socket) {
+            match ControlClient::connect(&socket).await {
+                Ok(client) => return Ok(Self::Daemon(client)),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::NotFound | ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    info!(socket = %socket.display(), "no daemon listening; reading state database");
+                }
+                Err(err) => {
+                    anyhow::bail!("failed to connect to {}: {err}", socket.display());
+                }
+            }
+        }
+
+        let mut config = config;
+        use_default_state(&mut config.persistence);
+        let Some(url) = config.persistence.state_url() else {
+            anyhow::bail!("no daemon is running and no state database is configured (use --state)");
+        };
+        let repo = open_repository(&config, &url, true).await?;
+        let bundle = build_reload_bundle(config.clone(), true);
+        let services = Services {
+            scanner: Box::new(ProcfsScanner::default()),
+            admission: bundle.admission,
+            updater: bundle.updater,
+            predictor: bundle.predictor,
+            planner: bundle.planner,
+            validator: Box::new(NoopMapValidator),
+            prefetcher: bundle.prefetcher,
+            repo,
+            clock: Box::new(SystemClock),
+        };
+        let mut engine = PreloadEngine::load(config, services).await?;
+        engine.refresh_prediction();
+        Ok(Self::Offline(Box::new(engine)))
+    }
+
+    async fn request(&mut self, request: ControlRequest) -> anyhow::Result<ControlResponse> {
+        let response = match self {
+            Self::Daemon(client) => client.request(&request).await?,
+            Self::Offline(engine) => {
+                let mut response = engine.handle_request(request).await;
+                // A freshly built admission policy has no history to report.
+                if let ControlResponse::Status(status) = &mut response {
+                    status.admission = None;
+                }
+                response
+            }
+        };
+        if let ControlResponse::Error { message } = response {
+            anyhow::bail!(message);
+        }
+        Ok(response)
+    }
+}
+
+/// Run a client command and print its result.
+pub async fn run(cli: &Cli, config: Config, command: &Command) -> anyhow::Result<()> {
+    let mut source = Source::open(cli, config).await?;
+    match command {
+        Command::Status => {
+            let ControlResponse::Status(status) = source.request(ControlRequest::Status).await?
+            else {
+                anyhow::bail!("unexpected reply to status");
+            };
+            let ControlResponse::LastTick { report } =
+                source.request(ControlRequest::LastTick).await?
+            else {
+                anyhow::bail!("unexpected reply to last_tick");
+            };
+            if cli.json {
+                let value = serde_json::json!({ "status": status, "last_tick": report });
+                print_json(&value)?;
+            } else {
+                print_status(&status, report.as_deref());
+            }
+        }
+        Command::Predict { limit } => {
+            let request = ControlRequest::Predictions { limit: *limit };
+            let ControlResponse::Predictions(top) = source.request(request).await? else {
+                anyhow::bail!("unexpected reply to predictions");
+            };
+            if cli.json {
+                print_json(&top)?;
+            } else {
+                print_predictions(&top);
+            }
+        }
+        Command::Inspect { .. }
+        | Command::Export { .. }
+        | Command::Import { .. }
+        | Command::ImportLegacy { .. }
+        | Command::ConvertConfig { .. } => {
+            unreachable!("handled in main without a daemon")
+        }
+        Command::Explain { path } => {
+            let request = ControlRequest::Explain { path: path.clone() };
+            let ControlResponse::Explanation(explanation) = source.request(request).await? else {
+                anyhow::bail!("unexpected reply to explain");
+            };
+            if cli.json {
+                print_json(&explanation)?;
+            } else {
+                print_explanation(&explanation);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::print_stdout)]
+fn print_json(value: &impl serde::Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[allow(clippy::print_stdout)]
+fn print_status(status: &StatusReport, last_tick: Option<&TickReport>) {
+    println!(
+        "model:     {} exes, {} maps, {} edges, {} active",
+        status.exes, status.maps, status.edges, status.active
+    );
+    println!(
+        "time:      {}s model time, scan {}{}",
+        status.model_time,
+        status.scan_id,
+        if status.paused { " (paused)" } else { "" }
+    );
+    match &status.admission {
+        Some(stats) if stats.cache_enabled => println!(
+            "admission: {}/{} cached rejections, {} hits, {} misses, {} invalidations",
+            stats.cache_entries,
+            stats.cache_capacity,
+            stats.cache_hits,
+            stats.cache_misses,
+            stats.cache_invalidations
+        ),
+        Some(_) => println!("admission: rejection cache disabled"),
+        None => println!("admission: n/a"),
+    }
+    let Some(report) = last_tick else {
+        println!("last tick: none");
+        return;
+    };
+    let delta = &report.model_delta;
+    println!(
+        "last tick: scan {}: {} new exes, {} new maps, {} started, {} stopped, {} rejected",
+        report.scan_id,
+        delta.new_exes.len(),
+        delta.new_maps.len(),
+        delta.running_now.len(),
+        delta.stopped_now.len(),
+        delta.rejected.len()
+    );
+    println!(
+        "           {} exes and {} maps scored, {} maps dropped as changed",
+        report.prediction.num_exes_scored,
+        report.prediction.num_maps_scored,
+        report.validation.dropped.len()
+    );
+    println!(
+        "           prefetched {} maps ({:.1} MiB), {} failures",
+        report.prefetch.num_maps,
+        report.prefetch.total_bytes as f64 / MIB,
+        report.prefetch.failures.len()
+    );
+}
+
+#[allow(clippy::print_stdout)]
+fn print_predictions(top: &TopPredictions) {
+    println!("exes:");
+    for exe in &top.exes {
+        let running = if exe.running { "  (running)" } else { "" };
+        println!("  {:.4}  {}{running}", exe.score, exe.path.display());
+    }
+    println!("maps:");
+    for scored in &top.maps {
+        println!(
+            "  {:.4}  {} [{}+{}]",
+            scored.score,
+            scored.map.path.display(),
+            scored.map.offset,
+            scored.map.length
+        );
+    }
+}
+
+#[allow(clippy::print_stdout)]
+fn print_explanation(explanation: &Explanation) {
+    let running = if explanation.running {
+        " (running, not predicted)"
+    } else {
+        ""
+    };
+    println!(
+        "{}: score {:.4}{running}",
+        explanation.path.display(),
+        explanation.score
+    );
+    if explanation.edges.is_empty() {
+        println!("  no Markov edges");
+        return;
+    }
+    println!("  p        transition  correlation  state    other exe");
+    for edge in &explanation.edges {
+        let running = if edge.other_running { " (running)" } else { "" };
+        println!(
+            "  {:.4}   {:.4}      {:.4}       {:<8} {}{running}",
+            edge.probability,
+            edge.transition,
+            edge.correlation,
+            format!("{:?}", edge.state),
+            edge.other.display()
+        );
+    }
+}
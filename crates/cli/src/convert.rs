@@ -0,0 +1,28 @@
+#![forbid(unsafe_code)]
+
+//! `convert-config`: translate the C preload's `preload.conf` to TOML.
+
+use config::Config;
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Convert `input` and write the TOML to `out`, or stdout.
+pub fn convert_config(input: &Path, out: Option<&Path>) -> anyhow::Result<()> {
+    let legacy = Config::load_legacy(input)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", input.display()))?;
+    for warning in &legacy.warnings {
+        warn!(file = %input.display(), "{warning}");
+    }
+
+    match out {
+        Some(out) => {
+            legacy.config.save(out)?;
+            info!(path = %out.display(), "config converted");
+        }
+        None => std::io::stdout()
+            .lock()
+            .write_all(legacy.config.to_toml()?.as_bytes())?,
+    }
+    Ok(())
+}
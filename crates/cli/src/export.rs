@@ -0,0 +1,80 @@
+#![forbid(unsafe_code)]
+
+//! `export` and `import`: move a learned model as portable JSON.
+//! `import-legacy`: take over the model learned by the C preload daemon.
+
+use crate::cli::Cli;
+use crate::{control_socket_path, open_repository};
+use config::Config;
+use orchestrator::{
+    control::ControlClient,
+    persistence::{StoresSnapshot, export_json, import_json, import_legacy_state},
+};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use tracing::info;
+
+fn state_url(config: &Config) -> anyhow::Result<String> {
+    config
+        .persistence
+        .state_url()
+        .ok_or_else(|| anyhow::anyhow!("no state database configured (use --state)"))
+}
+
+/// Write the configured state database as JSON to `out`, or stdout.
+pub async fn export(config: &Config, out: Option<&Path>) -> anyhow::Result<()> {
+    let url = state_url(config)?;
+    let snapshot = open_repository(config, &url, true).await?.load().await?;
+    match out {
+        Some(out) => {
+            export_json(&snapshot, BufWriter::new(File::create(out)?))?;
+            info!(path = %out.display(), "model exported");
+        }
+        None => export_json(&snapshot, io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+/// Replace the configured state database with the model in `input`.
+pub async fn import(cli: &Cli, config: &Config, input: &Path) -> anyhow::Result<()> {
+    let snapshot = import_json(BufReader::new(File::open(input)?))?;
+    replace_state(cli, config, &snapshot).await
+}
+
+/// Replace the configured state database with a C preload state file.
+pub async fn import_legacy(cli: &Cli, config: &Config, input: &Path) -> anyhow::Result<()> {
+    let file = File::open(input)
+        .map_err(|err| anyhow::anyhow!("failed to open {}: {err}", input.display()))?;
+    let snapshot = import_legacy_state(BufReader::new(file))?;
+    replace_state(cli, config, &snapshot).await
+}
+
+async fn replace_state(
+    cli: &Cli,
+    config: &Config,
+    snapshot: &StoresSnapshot,
+) -> anyhow::Result<()> {
+    // A running daemon would overwrite the import with its own model on the
+    // next save.
+    if let Some(socket) = control_socket_path(cli, config)
+        && ControlClient::connect(&socket).await.is_ok()
+    {
+        anyhow::bail!(
+            "a daemon is listening on {}; stop it before importing",
+            socket.display()
+        );
+    }
+
+    let url = state_url(config)?;
+    let repo = open_repository(config, &url, false).await?;
+    repo.save(snapshot).await?;
+    info!(
+        %url,
+        exes = snapshot.state.exes.len(),
+        maps = snapshot.state.maps.len(),
+        edges = snapshot.state.markov_edges.len(),
+        "model imported"
+    );
+    Ok(())
+}
@@ -0,0 +1,38 @@
+#![forbid(unsafe_code)]
+
+//! `inspect`: summarize a state database without a daemon.
+
+use crate::{open_repository, set_state};
+use config::Config;
+use orchestrator::persistence::{InspectOptions, StateReport};
+use std::path::PathBuf;
+
+/// Print a [`StateReport`] for the database at `path` (a path or backend
+/// URL), or the configured one.
+///
+/// The database is opened read-only, so this is safe while a daemon is
+/// writing to it.
+#[allow(clippy::print_stdout)]
+pub async fn run(
+    config: &Config,
+    path: Option<PathBuf>,
+    options: InspectOptions,
+    json: bool,
+) -> anyhow::Result<()> {
+    let mut persistence = config.persistence.clone();
+    if let Some(path) = &path {
+        set_state(&mut persistence, path);
+    }
+    let Some(url) = persistence.state_url() else {
+        anyhow::bail!("no state database given or configured");
+    };
+
+    let snapshot = open_repository(config, &url, true).await?.load().await?;
+    let report = StateReport::new(&snapshot, options);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+    Ok(())
+}
@@ -1,19 +1,36 @@
 #![forbid(unsafe_code)]
 
 mod cli;
+mod client;
+mod convert;
+mod export;
+mod inspect;
 mod signals;
 
 use clap::Parser;
-use cli::Cli;
-use config::Config;
+use cli::{Cli, Command};
+use config::{Config, Persistence, ScannerBackend};
 use orchestrator::{
     ControlEvent, PreloadEngine, ReloadBundle, Services,
     clock::SystemClock,
-    observation::{DefaultAdmissionPolicy, DefaultModelUpdater, ProcfsScanner},
-    persistence::{NoopRepository, SqliteRepository},
+    control::ControlServer,
+    error::Error,
+    evaluation::{EvaluationOptions, Evaluator},
+    observation::{
+        DefaultAdmissionPolicy, DefaultModelUpdater, NetlinkScanner, ProcfsScanner,
+        RecordingScanner, ReplayScanner, Scanner,
+    },
+    persistence::{
+        InspectOptions, NoopRepository, RepositoryOptions, RepositoryRegistry, StateRepository,
+    },
     prediction::MarkovPredictor,
-    prefetch::{GreedyPrefetchPlanner, NoopPrefetcher, PosixFadvisePrefetcher, Prefetcher},
+    prefetch::{
+        FileMapValidator, GreedyPrefetchPlanner, MapValidator, NoopMapValidator, NoopPrefetcher,
+        PosixFadvisePrefetcher, Prefetcher,
+    },
 };
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
@@ -23,32 +40,81 @@ use tracing_subscriber::EnvFilter;
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     init_tracing(cli.verbose);
+    if let Some(Command::ConvertConfig { file, output }) = &cli.command {
+        return convert::convert_config(file, output.as_deref());
+    }
     let config = load_config_from_cli(&cli)?;
 
-    let repo = if cli.no_persist {
-        Box::new(NoopRepository) as Box<dyn orchestrator::persistence::StateRepository>
-    } else if let Some(path) = &config.persistence.state_path {
-        let repo = SqliteRepository::new(path.clone()).await?;
-        Box::new(repo) as Box<dyn orchestrator::persistence::StateRepository>
-    } else {
-        warn!("no persistence path provided; using in-memory state only");
-        Box::new(NoopRepository) as Box<dyn orchestrator::persistence::StateRepository>
+    match &cli.command {
+        Some(Command::Inspect { file, limit, depth }) => {
+            let options = InspectOptions {
+                limit: *limit,
+                prefix_depth: *depth,
+            };
+            return inspect::run(&config, file.clone(), options, cli.json).await;
+        }
+        Some(Command::Export { file }) => return export::export(&config, file.as_deref()).await,
+        Some(Command::Import { file }) => return export::import(&cli, &config, file).await,
+        Some(Command::ImportLegacy { file }) => {
+            return export::import_legacy(&cli, &config, file).await;
+        }
+        Some(Command::ConvertConfig { .. }) => unreachable!("handled before loading config"),
+        Some(command) => return client::run(&cli, config, command).await,
+        None => {}
+    }
+
+    if let Some(path) = &cli.evaluate {
+        return evaluate_trace(config, path).await;
+    }
+
+    let repo = match config.persistence.state_url() {
+        Some(url) if !cli.no_persist => open_repository(&config, &url, false).await?,
+        _ => Box::new(NoopRepository) as Box<dyn StateRepository>,
     };
 
-    let reload_bundle = build_reload_bundle(config.clone(), cli.no_prefetch);
+    if cli.replay.is_some() && !config.system.doscan {
+        anyhow::bail!("--replay requires system.doscan to be enabled");
+    }
+
+    let reload_bundle =
+        build_reload_bundle(config.clone(), cli.no_prefetch || cli.replay.is_some());
+
+    let scanner = match (&cli.replay, &cli.record) {
+        (Some(path), _) => Box::new(ReplayScanner::open(path)?) as Box<dyn Scanner>,
+        (None, Some(path)) => {
+            info!(path = %path.display(), "recording observations");
+            Box::new(RecordingScanner::create(build_scanner(&config), path)?)
+        }
+        (None, None) => build_scanner(&config),
+    };
+
+    // Traces may come from another machine, so their files are not checked.
+    let validator = if cli.replay.is_some() {
+        Box::new(NoopMapValidator) as Box<dyn MapValidator + Send + Sync>
+    } else {
+        Box::new(FileMapValidator)
+    };
 
     let services = Services {
-        scanner: Box::new(ProcfsScanner),
+        scanner,
         admission: reload_bundle.admission,
         updater: reload_bundle.updater,
         predictor: reload_bundle.predictor,
         planner: reload_bundle.planner,
+        validator,
         prefetcher: reload_bundle.prefetcher,
         repo,
         clock: Box::new(SystemClock),
     };
 
     let mut engine = PreloadEngine::load(config, services).await?;
+    if cli.no_persist {
+        engine = engine.without_persistence();
+    }
+
+    if cli.replay.is_some() {
+        return replay_trace(&mut engine).await;
+    }
 
     if cli.once {
         let report = engine.tick().await?;
@@ -60,12 +126,58 @@ async fn main() -> anyhow::Result<()> {
     signals::install_ctrl_c(cancel.clone());
 
     let (control_tx, control_rx) = mpsc::unbounded_channel();
+    start_control_server(&cli, engine.config(), control_tx.clone(), cancel.clone());
     install_signal_handlers(cli.clone(), control_tx);
 
     engine.run_until(cancel, control_rx).await?;
     Ok(())
 }
 
+/// Tick through a recorded trace without sleeping, then save the result.
+async fn replay_trace(engine: &mut PreloadEngine) -> anyhow::Result<()> {
+    let mut ticks = 0u64;
+    loop {
+        match engine.tick().await {
+            Ok(_) => ticks += 1,
+            Err(Error::TraceExhausted) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    engine.save().await?;
+    info!(ticks, "trace replayed");
+    Ok(())
+}
+
+/// Replay a trace through a fresh engine and print prediction quality metrics.
+async fn evaluate_trace(config: Config, path: &Path) -> anyhow::Result<()> {
+    if !config.system.doscan || !config.system.dopredict {
+        anyhow::bail!("--evaluate requires system.doscan and system.dopredict to be enabled");
+    }
+
+    let bundle = build_reload_bundle(config.clone(), true);
+    let services = Services {
+        scanner: Box::new(ReplayScanner::open(path)?),
+        admission: bundle.admission,
+        updater: bundle.updater,
+        predictor: bundle.predictor,
+        planner: bundle.planner,
+        validator: Box::new(NoopMapValidator),
+        prefetcher: bundle.prefetcher,
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config, services).await?;
+
+    let report = Evaluator::new(EvaluationOptions::default())
+        .run(&mut engine)
+        .await?;
+    #[allow(clippy::print_stdout)]
+    {
+        print!("{report}");
+    }
+    Ok(())
+}
+
 fn init_tracing(verbosity: u8) {
     let default_level = match verbosity {
         0 => "info",
@@ -81,21 +193,73 @@ fn init_tracing(verbosity: u8) {
 
 /// Load configuration files and apply CLI overrides.
 fn load_config_from_cli(cli: &Cli) -> anyhow::Result<Config> {
+    let base = if cli.user {
+        let Some(home) = std::env::var_os("HOME") else {
+            anyhow::bail!("--user needs $HOME to be set");
+        };
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+        Config::user_profile(Path::new(&home), runtime_dir.as_deref())
+    } else {
+        Config::default()
+    };
     let config_paths = cli.resolve_config_paths()?;
     let mut config = if config_paths.is_empty() {
         warn!("no config files found; falling back to defaults");
-        Config::default()
+        base
     } else {
-        Config::load_multiple(config_paths)?
+        Config::load_multiple_over(&base, config_paths)?
     };
 
-    if let Some(path) = cli.state.clone().or(config.persistence.state_path.clone()) {
-        config.persistence.state_path = Some(path);
+    if let Some(state) = &cli.state {
+        set_state(&mut config.persistence, state);
+    }
+    // Client commands ask the daemon first and find the state themselves
+    // when it does not answer.
+    let client = matches!(
+        cli.command,
+        Some(Command::Status | Command::Predict { .. } | Command::Explain { .. })
+    );
+    if !cli.no_persist && cli.evaluate.is_none() && !client {
+        use_default_state(&mut config.persistence);
     }
 
     Ok(config)
 }
 
+/// Point `persistence` at [`cli::default_state_path`] if it names no state.
+fn use_default_state(persistence: &mut Persistence) {
+    if persistence.state_url().is_none() {
+        persistence.state_path = cli::default_state_path();
+    }
+}
+
+/// Open the state at `url` with the built-in backends.
+async fn open_repository(
+    config: &Config,
+    url: &str,
+    read_only: bool,
+) -> anyhow::Result<Box<dyn StateRepository>> {
+    let options = RepositoryOptions {
+        backups: config.persistence.backups,
+        backup_interval: config.persistence.backup_interval,
+        repair: config.persistence.repair,
+        read_only,
+    };
+    Ok(RepositoryRegistry::default().open(url, &options).await?)
+}
+
+/// Point `persistence` at `state`, a backend URL or a path opened with
+/// `persistence.backend`.
+fn set_state(persistence: &mut Persistence, state: &Path) {
+    match state.to_str().filter(|state| state.contains("://")) {
+        Some(url) => persistence.url = Some(url.to_string()),
+        None => {
+            persistence.state_path = Some(state.to_path_buf());
+            persistence.url = None;
+        }
+    }
+}
+
 /// Construct runtime services for a new configuration snapshot.
 fn build_reload_bundle(config: Config, no_prefetch: bool) -> ReloadBundle {
     ReloadBundle {
@@ -108,6 +272,28 @@ fn build_reload_bundle(config: Config, no_prefetch: bool) -> ReloadBundle {
     }
 }
 
+/// Select the scanner implementation, falling back to procfs when the proc
+/// connector cannot be used or processes are filtered by owner.
+fn build_scanner(config: &Config) -> Box<dyn Scanner> {
+    if config.system.own_processes_only {
+        let uid = nix::unistd::geteuid().as_raw();
+        if config.system.scanner == ScannerBackend::Netlink {
+            warn!("the netlink scanner cannot filter by owner; using the procfs scanner");
+        }
+        return Box::new(ProcfsScanner::default().with_owner(uid));
+    }
+    match config.system.scanner {
+        ScannerBackend::Procfs => Box::new(ProcfsScanner::default()),
+        ScannerBackend::Netlink => match NetlinkScanner::new() {
+            Ok(scanner) => Box::new(scanner),
+            Err(err) => {
+                warn!(%err, "proc connector unavailable; falling back to procfs scanner");
+                Box::new(ProcfsScanner::default())
+            }
+        },
+    }
+}
+
 /// Select the prefetcher implementation based on configuration and CLI flags.
 fn build_prefetcher(config: &Config, no_prefetch: bool) -> Box<dyn Prefetcher> {
     if no_prefetch {
@@ -125,6 +311,40 @@ fn build_prefetcher(config: &Config, no_prefetch: bool) -> Box<dyn Prefetcher> {
     }
 }
 
+/// The control socket from `--socket` or the config; `None` if disabled.
+fn control_socket_path(cli: &Cli, config: &Config) -> Option<PathBuf> {
+    cli.socket
+        .clone()
+        .or_else(|| config.system.control_socket.path().map(Path::to_path_buf))
+}
+
+/// Serve the control socket in the background. Failing to bind is not fatal:
+/// the daemon still runs, controlled by signals only.
+fn start_control_server(
+    cli: &Cli,
+    config: &Config,
+    control_tx: mpsc::UnboundedSender<ControlEvent>,
+    cancel: CancellationToken,
+) {
+    let Some(path) = control_socket_path(cli, config) else {
+        return;
+    };
+
+    let server = match ControlServer::bind(&path, control_tx) {
+        Ok(server) => server,
+        Err(err) => {
+            warn!(path = %path.display(), %err, "control socket disabled");
+            return;
+        }
+    };
+    let reload_cli = cli.clone();
+    let server = server.with_reload(Arc::new(move || {
+        let config = load_config_from_cli(&reload_cli).map_err(|err| err.to_string())?;
+        Ok(build_reload_bundle(config, reload_cli.no_prefetch))
+    }));
+    tokio::spawn(server.serve(cancel));
+}
+
 /// Install signal handlers for runtime control (reload, dump, save).
 fn install_signal_handlers(cli: Cli, control_tx: mpsc::UnboundedSender<ControlEvent>) {
     #[cfg(unix)]
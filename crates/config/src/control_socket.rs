@@ -0,0 +1,59 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+
+/// Where the daemon serves its control API. Written in TOML as the socket
+/// path, or `false` to disable it; `""` also disables it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlSocket {
+    /// No socket; the daemon is controlled by signals only.
+    Disabled,
+    /// A Unix socket at this path.
+    Path(PathBuf),
+}
+
+impl ControlSocket {
+    /// The socket path, `None` if disabled.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ControlSocket::Disabled => None,
+            ControlSocket::Path(path) => Some(path),
+        }
+    }
+}
+
+impl Default for ControlSocket {
+    fn default() -> Self {
+        ControlSocket::Path(PathBuf::from("/run/preload-rs/control.sock"))
+    }
+}
+
+impl Serialize for ControlSocket {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ControlSocket::Disabled => serializer.serialize_bool(false),
+            ControlSocket::Path(path) => path.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ControlSocket {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Enabled(bool),
+            Path(PathBuf),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Enabled(false) => Ok(ControlSocket::Disabled),
+            Raw::Enabled(true) => Err(serde::de::Error::custom(
+                "control_socket takes a path, or false to disable it",
+            )),
+            Raw::Path(path) if path.as_os_str().is_empty() => Ok(ControlSocket::Disabled),
+            Raw::Path(path) => Ok(ControlSocket::Path(path)),
+        }
+    }
+}
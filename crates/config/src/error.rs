@@ -16,6 +16,9 @@ pub enum Error {
     #[error("failed to serialize TOML: {0}")]
     TomlSer(#[from] toml_edit::ser::Error),
 
+    #[error("invalid preload.conf at line {line}: {message}")]
+    Legacy { line: usize, message: String },
+
     #[error("invalid path: {0}")]
     InvalidPath(PathBuf),
 }
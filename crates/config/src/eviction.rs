@@ -0,0 +1,28 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::time::Duration;
+
+/// Limits on how much model state is kept. Unset limits are not enforced.
+///
+/// Ages are measured in model time, which only advances while the daemon runs.
+/// Running exes, and maps they use, are never evicted.
+#[serde_as]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Eviction {
+    /// Maximum number of tracked exes; the least recently seen go first.
+    pub max_exes: Option<usize>,
+
+    /// Maximum number of tracked maps; the least recently updated go first.
+    pub max_maps: Option<usize>,
+
+    /// Evict exes not seen for longer than this.
+    #[serde_as(as = "Option<serde_with::DurationSeconds>")]
+    pub max_exe_age: Option<Duration>,
+
+    /// Evict maps not seen for longer than this.
+    #[serde_as(as = "Option<serde_with::DurationSeconds>")]
+    pub max_map_age: Option<Duration>,
+}
@@ -0,0 +1,18 @@
+#![forbid(unsafe_code)]
+
+use crate::identity_mode::IdentityMode;
+use crate::path_rewrite::PathRewrite;
+use serde::{Deserialize, Serialize};
+
+/// Exe identity used to carry history across upgrades and renames.
+///
+/// When a new exe path turns up with the same identity as a known exe that is
+/// not running, the known exe's history is merged into it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Identity {
+    pub mode: IdentityMode,
+
+    /// Rewrites applied in order to produce the normalized path.
+    pub normalize: Vec<PathRewrite>,
+}
@@ -0,0 +1,17 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Serialize};
+
+/// How exes are recognized across paths.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityMode {
+    /// Each path is a separate exe.
+    #[default]
+    Path,
+    /// Exes sharing a normalized path (see `normalize`) are the same exe.
+    Normalize,
+    /// Exes sharing an ELF build-id are the same exe; binaries without one
+    /// fall back to `normalize`.
+    BuildId,
+}
@@ -0,0 +1,202 @@
+#![forbid(unsafe_code)]
+
+use crate::Config;
+use crate::error::Error;
+use crate::sort_strategy::SortStrategy;
+use std::path::Path;
+use std::time::Duration;
+
+/// Where the original C preload reads its configuration.
+pub const LEGACY_CONFIG_PATH: &str = "/etc/preload.conf";
+
+/// A configuration converted from a C preload `preload.conf`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyConfig {
+    pub config: Config,
+    /// Options that were ignored, each naming the line it came from.
+    pub warnings: Vec<String>,
+}
+
+impl Config {
+    /// Read a C preload `preload.conf` (a GLib key file with `[model]` and
+    /// `[system]` groups). Options not set in the file keep this crate's
+    /// defaults.
+    pub fn load_legacy(path: impl AsRef<Path>) -> Result<LegacyConfig, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Self::from_legacy_str(&text)
+    }
+
+    /// Parse the text of a C preload `preload.conf`.
+    ///
+    /// Lines that are not comments, groups or `key = value` pairs are errors.
+    /// Unknown keys and values this crate cannot represent are skipped with a
+    /// warning instead, as preload itself does.
+    pub fn from_legacy_str(text: &str) -> Result<LegacyConfig, Error> {
+        let mut config = Config::default();
+        let mut warnings = Vec::new();
+        let mut group = String::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let trimmed = raw.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = trimmed
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                group = name.trim().to_string();
+                continue;
+            }
+            let Some((key, value)) = trimmed.split_once('=') else {
+                return Err(Error::Legacy {
+                    line,
+                    message: format!("expected `key = value`, found {trimmed:?}"),
+                });
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if let Err(message) = apply(&mut config, &group, key, value) {
+                warnings.push(format!("line {line}: {message}"));
+            }
+        }
+
+        config.apply_defaults();
+        Ok(LegacyConfig { config, warnings })
+    }
+}
+
+/// Set one option. The error describes why it was ignored.
+fn apply(config: &mut Config, group: &str, key: &str, value: &str) -> Result<(), String> {
+    let model = &mut config.model;
+    let system = &mut config.system;
+    match (group, key) {
+        ("model", "cycle") => model.cycle = Duration::from_secs(parse(key, value)?),
+        ("model", "usecorrelation") => model.use_correlation = boolean(key, value)?,
+        ("model", "minsize") => model.minsize = parse(key, value)?,
+        ("model", "memtotal") => model.memory.memtotal = parse(key, value)?,
+        ("model", "memfree") => model.memory.memfree = parse(key, value)?,
+        ("model", "memcached") => model.memory.memcached = parse(key, value)?,
+        ("system", "doscan") => system.doscan = boolean(key, value)?,
+        ("system", "dopredict") => system.dopredict = boolean(key, value)?,
+        ("system", "autosave") => system.autosave = Duration::from_secs(parse(key, value)?),
+        ("system", "mapprefix") => system.mapprefix = list(value),
+        ("system", "exeprefix") => system.exeprefix = list(value),
+        // preload reads in-process when maxprocs is 0; here 0 would disable
+        // prefetching altogether.
+        ("system", "maxprocs") => {
+            system.prefetch_concurrency = Some(parse::<usize>(key, value)?.max(1));
+        }
+        ("system", "sortstrategy") => {
+            system.sortstrategy = match parse::<i64>(key, value)? {
+                0 => SortStrategy::None,
+                1 => SortStrategy::Path,
+                2 => SortStrategy::Inode,
+                3 => SortStrategy::Block,
+                other => return Err(format!("unknown sortstrategy {other}")),
+            };
+        }
+        _ if group.is_empty() => return Err(format!("{key} is outside any group")),
+        _ => return Err(format!("[{group}] {key} has no equivalent")),
+    }
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {key}: {value:?}"))
+}
+
+/// GLib accepts `true`/`false` and `1`/`0`.
+fn boolean(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("invalid value for {key}: {value:?}")),
+    }
+}
+
+/// GLib lists are `;`-separated, optionally with a trailing `;`.
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRELOAD_CONF: &str = "\
+# preload.conf as shipped with preload 0.6.4
+[model]
+
+# cycle: the quantum of time for preload, in seconds.
+cycle = 30
+usecorrelation = false
+minsize = 1000000
+memtotal = -10
+memfree = 70
+memcached = 10
+
+[system]
+doscan = true
+dopredict = 0
+autosave = 1800
+mapprefix = /usr/;/lib;/var/cache/;!/
+exeprefix = !/usr/sbin/;!/usr/local/sbin/;/usr/;!/
+maxprocs = 0
+sortstrategy = 2
+prefix = /opt/
+";
+
+    #[test]
+    fn converts_every_preload_option() {
+        let legacy = Config::from_legacy_str(PRELOAD_CONF).unwrap();
+        let config = &legacy.config;
+        assert_eq!(config.model.cycle, Duration::from_secs(30));
+        assert!(!config.model.use_correlation);
+        assert_eq!(config.model.minsize, 1_000_000);
+        assert_eq!(config.model.memory.memfree, 70);
+        assert_eq!(config.model.memory.memcached, 10);
+        assert!(config.system.doscan);
+        assert!(!config.system.dopredict);
+        assert_eq!(config.system.autosave, Duration::from_secs(1800));
+        assert_eq!(
+            config.system.mapprefix,
+            ["!/", "/lib", "/usr/", "/var/cache/"]
+        );
+        assert_eq!(config.system.exeprefix.len(), 4);
+        assert_eq!(config.system.prefetch_concurrency, Some(1));
+        assert_eq!(config.system.sortstrategy, SortStrategy::Inode);
+        // Options preload does not know keep this crate's defaults.
+        assert_eq!(config.persistence, Default::default());
+
+        assert_eq!(
+            legacy.warnings,
+            ["line 20: [system] prefix has no equivalent"]
+        );
+    }
+
+    #[test]
+    fn bad_values_warn_and_bad_lines_fail() {
+        let legacy =
+            Config::from_legacy_str("[model]\ncycle = soon\n[system]\nsortstrategy = 7\n").unwrap();
+        assert_eq!(legacy.config.model.cycle, Config::default().model.cycle);
+        assert_eq!(
+            legacy.warnings,
+            [
+                "line 2: invalid value for cycle: \"soon\"",
+                "line 4: unknown sortstrategy 7"
+            ]
+        );
+
+        let err = Config::from_legacy_str("[model]\ncycle 20\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+}
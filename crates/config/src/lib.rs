@@ -1,17 +1,35 @@
 #![forbid(unsafe_code)]
 
+mod control_socket;
 mod error;
+mod eviction;
+mod identity;
+mod identity_mode;
+mod legacy;
 mod memory_policy;
 mod model;
+mod path_rewrite;
+mod path_text;
 mod persistence;
+mod scanner_backend;
 mod sort_strategy;
+mod state_backend;
 mod system;
 
+pub use control_socket::ControlSocket;
 pub use error::Error;
+pub use eviction::Eviction;
+pub use identity::Identity;
+pub use identity_mode::IdentityMode;
+pub use legacy::{LEGACY_CONFIG_PATH, LegacyConfig};
 pub use memory_policy::MemoryPolicy;
 pub use model::Model;
+pub use path_rewrite::PathRewrite;
+pub use path_text::{decode_path, encode_path, path_as_text};
 pub use persistence::Persistence;
+pub use scanner_backend::ScannerBackend;
 pub use sort_strategy::SortStrategy;
+pub use state_backend::StateBackend;
 pub use system::System;
 
 use serde::{Deserialize, Serialize};
@@ -36,18 +54,67 @@ impl Config {
 
     /// Save configuration to a TOML file.
     pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
-        let toml = toml_edit::ser::to_string_pretty(self)?;
-        std::fs::write(path, toml)?;
+        std::fs::write(path, self.to_toml()?)?;
         Ok(())
     }
 
+    /// Render configuration in the TOML layout written by [`Config::save`].
+    pub fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml_edit::ser::to_string_pretty(self)?)
+    }
+
+    /// Defaults for a daemon run by an ordinary user from `home`: only the
+    /// user's own processes are scanned, exes and maps below `home` and
+    /// `/opt/` are admitted along with the system ones, and the control
+    /// socket lives in `runtime_dir` (disabled without one). Prefixes are
+    /// text, so a `home` that is not valid UTF-8 is left out of them.
+    pub fn user_profile(home: &Path, runtime_dir: Option<&Path>) -> Self {
+        let mut config = Config::default();
+        let home = home.join("");
+        let home = home.to_str().map(str::to_string);
+        let system = &mut config.system;
+        system.own_processes_only = true;
+        system.exeprefix = vec![
+            "!/usr/sbin/".into(),
+            "!/usr/local/sbin/".into(),
+            "/usr/".into(),
+            "/opt/".into(),
+            "!/".into(),
+        ];
+        system.exeprefix.extend(home.clone());
+        system.mapprefix = vec![
+            "/usr/".into(),
+            "/lib/".into(),
+            "/var/cache/".into(),
+            "/opt/".into(),
+            "!/".into(),
+        ];
+        system.mapprefix.extend(home);
+        system.control_socket = match runtime_dir {
+            Some(dir) => ControlSocket::Path(dir.join("preload-rs").join("control.sock")),
+            None => ControlSocket::Disabled,
+        };
+        config.apply_defaults();
+        config
+    }
+
     /// Load configuration from multiple TOML files. Later files override earlier ones.
     pub fn load_multiple<T, U>(paths: U) -> Result<Self, Error>
     where
         T: AsRef<Path>,
         U: IntoIterator<Item = T>,
     {
-        let mut merged = toml_edit::DocumentMut::new();
+        Self::load_multiple_over(&Config::default(), paths)
+    }
+
+    /// Like [`Config::load_multiple`], with `base` instead of the defaults
+    /// under the files.
+    pub fn load_multiple_over<T, U>(base: &Config, paths: U) -> Result<Self, Error>
+    where
+        T: AsRef<Path>,
+        U: IntoIterator<Item = T>,
+    {
+        let mut merged: toml_edit::DocumentMut = base.to_toml()?.parse()?;
         for path in paths {
             let path = path.as_ref();
             if !path.exists() {
@@ -100,6 +167,7 @@ fn merge_item(target: &mut toml_edit::Item, source: toml_edit::Item) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
     use std::time::Duration;
     use tempfile::tempdir;
 
@@ -133,4 +201,94 @@ mod tests {
             Some(Duration::from_secs(120))
         );
     }
+
+    #[test]
+    fn files_override_the_user_profile() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[system]\nmapprefix = [\"/srv/\"]\n").unwrap();
+
+        let base = Config::user_profile(Path::new("/home/ada"), Some(Path::new("/run/user/1000")));
+        let cfg = Config::load_multiple_over(&base, [&path]).unwrap();
+        assert!(cfg.system.own_processes_only);
+        assert!(cfg.system.exeprefix.contains(&"/home/ada/".to_string()));
+        assert_eq!(cfg.system.mapprefix, ["/srv/"]);
+        assert_eq!(
+            cfg.system.control_socket,
+            ControlSocket::Path(PathBuf::from("/run/user/1000/preload-rs/control.sock"))
+        );
+
+        let base = Config::user_profile(Path::new("/home/ada/"), None);
+        assert_eq!(base.system.control_socket, ControlSocket::Disabled);
+        assert!(base.system.mapprefix.contains(&"/home/ada/".to_string()));
+        let cfg = Config::load_multiple_over(&base, [&path]).unwrap();
+        assert_eq!(cfg.system.control_socket, ControlSocket::Disabled);
+    }
+
+    #[test]
+    fn control_socket_can_be_disabled() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        for disabled in ["false", "\"\""] {
+            std::fs::write(&path, format!("[system]\ncontrol_socket = {disabled}\n")).unwrap();
+            let cfg = Config::load(&path).unwrap();
+            assert_eq!(
+                cfg.system.control_socket,
+                ControlSocket::Disabled,
+                "{disabled}"
+            );
+        }
+
+        std::fs::write(&path, "[system]\ncontrol_socket = true\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+
+    #[test]
+    fn eviction_limits_are_optional() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[model.eviction]\nmax_exes = 500\nmax_exe_age = 2592000\n",
+        )
+        .unwrap();
+
+        let cfg = Config::load(&path).unwrap();
+        assert_eq!(cfg.model.eviction.max_exes, Some(500));
+        assert_eq!(cfg.model.eviction.max_maps, None);
+        assert_eq!(
+            cfg.model.eviction.max_exe_age,
+            Some(Duration::from_secs(2_592_000))
+        );
+        assert_eq!(cfg.model.eviction.max_map_age, None);
+    }
+
+    #[test]
+    fn identity_rewrites_load_in_order() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[model.identity]
+mode = "build_id"
+
+[[model.identity.normalize]]
+pattern = "^/opt/([^/]+)-[0-9.]+/"
+replace = "/opt/$1/"
+
+[[model.identity.normalize]]
+pattern = "^/usr/libexec/"
+replace = "/usr/bin/"
+"#,
+        )
+        .unwrap();
+
+        let cfg = Config::load(&path).unwrap();
+        assert_eq!(cfg.model.identity.mode, IdentityMode::BuildId);
+        assert_eq!(cfg.model.identity.normalize.len(), 2);
+        assert_eq!(cfg.model.identity.normalize[0].replace, "/opt/$1/");
+        assert_eq!(cfg.model.identity.normalize[1].pattern, "^/usr/libexec/");
+        assert_eq!(Config::default().model.identity.mode, IdentityMode::Path);
+    }
 }
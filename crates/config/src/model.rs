@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+use crate::eviction::Eviction;
+use crate::identity::Identity;
 use crate::memory_policy::MemoryPolicy;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -31,6 +33,10 @@ pub struct Model {
     pub decay: f32,
 
     pub memory: MemoryPolicy,
+
+    pub eviction: Eviction,
+
+    pub identity: Identity,
 }
 
 impl Default for Model {
@@ -43,6 +49,8 @@ impl Default for Model {
             half_life: None,
             decay: 0.01,
             memory: MemoryPolicy::default(),
+            eviction: Eviction::default(),
+            identity: Identity::default(),
         }
     }
 }
@@ -0,0 +1,13 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Serialize};
+
+/// A regex substitution applied to exe paths before comparing them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PathRewrite {
+    /// Regular expression matched against the whole path.
+    pub pattern: String,
+    /// Replacement text; `$1`, `${name}` refer to capture groups.
+    #[serde(default)]
+    pub replace: String,
+}
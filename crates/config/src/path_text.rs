@@ -0,0 +1,86 @@
+#![forbid(unsafe_code)]
+
+//! Paths written as text without losing bytes.
+//!
+//! File names on Linux are arbitrary bytes. Where a path has to live in a
+//! string (URLs, stored identities, JSON), UTF-8 runs are kept as they are,
+//! `%` becomes `%25` and every byte that is not valid UTF-8 becomes `%XX`.
+
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Write `path` as text that [`decode_path`] turns back into the same bytes.
+pub fn encode_path(path: &Path) -> String {
+    let mut text = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        text.push_str(&chunk.valid().replace('%', "%25"));
+        for byte in chunk.invalid() {
+            let _ = write!(text, "%{byte:02X}");
+        }
+    }
+    text
+}
+
+/// Read a path written by [`encode_path`]. A `%` not followed by two hex
+/// digits is taken literally.
+pub fn decode_path(text: &str) -> PathBuf {
+    let text = text.as_bytes();
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut index = 0;
+    while index < text.len() {
+        let escaped = text
+            .get(index + 1..index + 3)
+            .filter(|_| text[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                index += 3;
+            }
+            None => {
+                bytes.push(text[index]);
+                index += 1;
+            }
+        }
+    }
+    PathBuf::from(OsStr::from_bytes(&bytes))
+}
+
+/// Serde adapter for a `PathBuf` field written as [`encode_path`] text:
+/// `#[serde(with = "config::path_as_text")]`. JSON strings must be UTF-8,
+/// so a plain `PathBuf` field fails to serialize file names that are not.
+pub mod path_as_text {
+    use super::{decode_path, encode_path};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::path::{Path, PathBuf};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_path(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        String::deserialize(deserializer).map(|text| decode_path(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_bytes_roundtrip() {
+        let raw = |bytes: &[u8]| PathBuf::from(OsStr::from_bytes(bytes));
+        for (path, text) in [
+            (raw(b"/usr/bin/app"), "/usr/bin/app"),
+            (raw(b"/opt/caf\xe9/100%"), "/opt/caf%E9/100%25"),
+            (raw("/home/zoë/a b".as_bytes()), "/home/zoë/a b"),
+        ] {
+            assert_eq!(encode_path(&path), text);
+            assert_eq!(decode_path(text), path);
+        }
+        assert_eq!(decode_path("/tmp/50%-off%zz"), raw(b"/tmp/50%-off%zz"));
+    }
+}
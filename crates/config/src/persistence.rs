@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+use crate::path_text::encode_path;
+use crate::state_backend::StateBackend;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::{path::PathBuf, time::Duration};
@@ -11,21 +13,103 @@ pub struct Persistence {
     /// Optional path to the state database.
     pub state_path: Option<PathBuf>,
 
+    /// Storage format of the state at `state_path`.
+    pub backend: StateBackend,
+
+    /// Backend URL of the state, such as `sqlite:///var/lib/preload-rs/state.db`,
+    /// `file:///var/lib/preload-rs/state.bin` or `memory://`. Overrides
+    /// `state_path` and `backend`.
+    pub url: Option<String>,
+
     /// Autosave interval (overrides System.autosave when set).
     #[serde_as(as = "Option<serde_with::DurationSeconds>")]
     pub autosave_interval: Option<Duration>,
 
     pub save_on_shutdown: bool,
+
+    /// Write only the rows that changed since the previous save.
+    pub incremental: bool,
+
+    /// How often an incremental save is replaced by a full rewrite of the
+    /// state.
+    #[serde_as(as = "serde_with::DurationSeconds")]
+    pub compaction_interval: Duration,
+
+    /// Copies of the state kept next to it. A state file that fails its
+    /// integrity check is replaced by the newest copy that passes.
+    pub backups: usize,
+
+    /// How often saves refresh the backups, full and incremental saves
+    /// alike. Zero refreshes them at every save.
+    #[serde_as(as = "serde_with::DurationSeconds")]
+    pub backup_interval: Duration,
+
+    /// Drop rows that refer to missing exes or maps, or hold invalid
+    /// statistics, instead of refusing to load the state.
+    pub repair: bool,
+
+    /// When a reload moves the state to a new location, delete the state
+    /// and its backups at the old one.
+    pub remove_old_state: bool,
 }
 
 impl Default for Persistence {
     fn default() -> Self {
         Self {
             state_path: None,
+            backend: StateBackend::Sqlite,
+            url: None,
             autosave_interval: None,
             save_on_shutdown: true,
+            incremental: true,
+            compaction_interval: Duration::from_secs(24 * 60 * 60),
+            backups: 3,
+            backup_interval: Duration::from_secs(60 * 60),
+            repair: true,
+            remove_old_state: false,
+        }
+    }
+}
+
+impl Persistence {
+    /// The backend URL of the state: `url`, or `state_path` opened with
+    /// `backend`. `None` if neither is set. The path is escaped with
+    /// [`encode_path`], so `%` and bytes that are not UTF-8 survive the trip
+    /// through the URL.
+    pub fn state_url(&self) -> Option<String> {
+        if let Some(url) = &self.url {
+            return Some(url.clone());
         }
+        let path = self.state_path.as_ref()?;
+        Some(format!("{}://{}", self.backend.scheme(), encode_path(path)))
     }
 }
 
-impl Persistence {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn state_url_prefers_url() {
+        let mut persistence = Persistence::default();
+        assert_eq!(persistence.state_url(), None);
+
+        persistence.state_path = Some(PathBuf::from("/var/lib/preload-rs/state.bin"));
+        persistence.backend = StateBackend::File;
+        assert_eq!(
+            persistence.state_url().as_deref(),
+            Some("file:///var/lib/preload-rs/state.bin")
+        );
+
+        persistence.state_path = Some(PathBuf::from(OsStr::from_bytes(b"/var/lib/100%/\xff.bin")));
+        assert_eq!(
+            persistence.state_url().as_deref(),
+            Some("file:///var/lib/100%25/%FF.bin")
+        );
+
+        persistence.url = Some("memory://".into());
+        assert_eq!(persistence.state_url().as_deref(), Some("memory://"));
+    }
+}
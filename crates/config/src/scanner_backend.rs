@@ -0,0 +1,13 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScannerBackend {
+    /// Walk `/proc` once per cycle.
+    #[default]
+    Procfs,
+    /// Follow exec/exit events from the kernel proc connector (needs `CAP_NET_ADMIN`).
+    Netlink,
+}
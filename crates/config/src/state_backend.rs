@@ -0,0 +1,23 @@
+#![forbid(unsafe_code)]
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackend {
+    /// SQLite database, updated in place.
+    #[default]
+    Sqlite,
+    /// Single checksummed rkyv archive, rewritten whole at every save.
+    File,
+}
+
+impl StateBackend {
+    /// URL scheme of the backend, as in `sqlite:///var/lib/preload-rs/state.db`.
+    pub fn scheme(self) -> &'static str {
+        match self {
+            StateBackend::Sqlite => "sqlite",
+            StateBackend::File => "file",
+        }
+    }
+}
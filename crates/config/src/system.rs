@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+use crate::control_socket::ControlSocket;
+use crate::scanner_backend::ScannerBackend;
 use crate::sort_strategy::SortStrategy;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -12,6 +14,12 @@ pub struct System {
     pub doscan: bool,
     pub dopredict: bool,
 
+    /// Process scanner implementation.
+    pub scanner: ScannerBackend,
+
+    /// Scan only processes owned by the user preload-rs runs as.
+    pub own_processes_only: bool,
+
     /// Autosave interval for state persistence.
     #[serde_as(as = "serde_with::DurationSeconds")]
     pub autosave: Duration,
@@ -35,6 +43,9 @@ pub struct System {
 
     /// Maximum number of cached admission rejections. 0 disables caching.
     pub policy_cache_capacity: usize,
+
+    /// Unix socket for the control API.
+    pub control_socket: ControlSocket,
 }
 
 impl Default for System {
@@ -42,6 +53,8 @@ impl Default for System {
         Self {
             doscan: true,
             dopredict: true,
+            scanner: ScannerBackend::Procfs,
+            own_processes_only: false,
             autosave: Duration::from_secs(3600),
             mapprefix: vec![
                 "/usr/".into(),
@@ -59,6 +72,7 @@ impl Default for System {
             prefetch_concurrency: None,
             policy_cache_ttl: Duration::from_secs(300),
             policy_cache_capacity: 1024,
+            control_socket: ControlSocket::default(),
         }
     }
 }
@@ -0,0 +1,3 @@
+ALTER TABLE maps ADD COLUMN device INTEGER;
+ALTER TABLE maps ADD COLUMN inode INTEGER;
+ALTER TABLE maps ADD COLUMN mtime_ns INTEGER;
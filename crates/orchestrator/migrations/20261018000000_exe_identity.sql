@@ -0,0 +1 @@
+ALTER TABLE exes ADD COLUMN identity TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE exes ADD COLUMN running INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE exes ADD COLUMN change_time INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE markovs ADD COLUMN state INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE markovs ADD COLUMN last_change_time INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE markovs ADD COLUMN state_last_left BLOB;
@@ -0,0 +1,65 @@
+-- Paths are stored as their raw bytes so non-UTF-8 names survive a restart.
+-- Existing text paths convert to their UTF-8 encoding.
+
+CREATE TABLE exes_new (
+    path BLOB NOT NULL PRIMARY KEY,
+    total_running_time INTEGER NOT NULL,
+    last_seen_time INTEGER,
+    identity TEXT,
+    running INTEGER NOT NULL DEFAULT 0,
+    change_time INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO exes_new (path, total_running_time, last_seen_time, identity, running, change_time)
+    SELECT CAST(path AS BLOB), total_running_time, last_seen_time, identity, running, change_time
+    FROM exes;
+DROP TABLE exes;
+ALTER TABLE exes_new RENAME TO exes;
+
+CREATE TABLE maps_new (
+    path BLOB NOT NULL,
+    offset INTEGER NOT NULL,
+    length INTEGER NOT NULL,
+    update_time INTEGER NOT NULL,
+    device INTEGER,
+    inode INTEGER,
+    mtime_ns INTEGER,
+    PRIMARY KEY (path, offset, length)
+);
+INSERT INTO maps_new (path, offset, length, update_time, device, inode, mtime_ns)
+    SELECT CAST(path AS BLOB), offset, length, update_time, device, inode, mtime_ns
+    FROM maps;
+DROP TABLE maps;
+ALTER TABLE maps_new RENAME TO maps;
+
+CREATE TABLE exe_maps_new (
+    exe_path BLOB NOT NULL,
+    map_path BLOB NOT NULL,
+    map_offset INTEGER NOT NULL,
+    map_length INTEGER NOT NULL,
+    prob REAL NOT NULL,
+    PRIMARY KEY (exe_path, map_path, map_offset, map_length)
+);
+INSERT INTO exe_maps_new (exe_path, map_path, map_offset, map_length, prob)
+    SELECT CAST(exe_path AS BLOB), CAST(map_path AS BLOB), map_offset, map_length, prob
+    FROM exe_maps;
+DROP TABLE exe_maps;
+ALTER TABLE exe_maps_new RENAME TO exe_maps;
+
+CREATE TABLE markovs_new (
+    exe_a BLOB NOT NULL,
+    exe_b BLOB NOT NULL,
+    time_to_leave BLOB NOT NULL,
+    transition_prob BLOB NOT NULL,
+    both_running_time INTEGER NOT NULL,
+    state INTEGER NOT NULL DEFAULT 0,
+    last_change_time INTEGER NOT NULL DEFAULT 0,
+    state_last_left BLOB,
+    PRIMARY KEY (exe_a, exe_b)
+);
+INSERT INTO markovs_new (exe_a, exe_b, time_to_leave, transition_prob, both_running_time,
+                         state, last_change_time, state_last_left)
+    SELECT CAST(exe_a AS BLOB), CAST(exe_b AS BLOB), time_to_leave, transition_prob,
+           both_running_time, state, last_change_time, state_last_left
+    FROM markovs;
+DROP TABLE markovs;
+ALTER TABLE markovs_new RENAME TO markovs;
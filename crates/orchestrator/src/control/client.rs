@@ -0,0 +1,39 @@
+#![forbid(unsafe_code)]
+
+use crate::control::{ControlRequest, ControlResponse};
+use crate::error::Error;
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+
+/// A connection to a [`ControlServer`](crate::control::ControlServer).
+pub struct ControlClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+}
+
+impl ControlClient {
+    pub async fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (read, write) = UnixStream::connect(path).await?.into_split();
+        Ok(Self {
+            lines: BufReader::new(read).lines(),
+            write,
+        })
+    }
+
+    /// Send one request and wait for its reply.
+    pub async fn request(&mut self, request: &ControlRequest) -> Result<ControlResponse, Error> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.write.write_all(&line).await?;
+        let reply = self.lines.next_line().await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "control socket closed without a reply",
+            )
+        })?;
+        Ok(serde_json::from_str(&reply)?)
+    }
+}
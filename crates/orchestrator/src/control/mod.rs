@@ -0,0 +1,23 @@
+#![forbid(unsafe_code)]
+
+//! Local control API for a running engine.
+//!
+//! [`ControlServer`] listens on a Unix socket. Clients write one JSON
+//! [`ControlRequest`] per line and read one JSON [`ControlResponse`] per line
+//! back, for example:
+//!
+//! ```text
+//! {"command":"predictions","limit":5}
+//! {"kind":"predictions","exes":[...],"maps":[...]}
+//! ```
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::ControlClient;
+pub use protocol::{
+    ControlRequest, ControlResponse, DEFAULT_PREDICTION_LIMIT, ScoredExe, ScoredMap, StatusReport,
+    TopPredictions,
+};
+pub use server::{ControlServer, ReloadHook};
@@ -0,0 +1,182 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::MapKey;
+use crate::engine::TickReport;
+use crate::observation::AdmissionPolicyStats;
+use crate::prediction::Explanation;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Number of exes and maps returned by `predictions` when no limit is given.
+pub const DEFAULT_PREDICTION_LIMIT: usize = 20;
+
+/// A request sent to the control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Reload configuration, as SIGHUP does.
+    Reload,
+    /// Save state now, as SIGUSR2 does.
+    Save,
+    /// Stop ticking until `resume`; requests are still answered.
+    Pause,
+    Resume,
+    Status,
+    /// Highest scored exes and maps of the last tick.
+    Predictions {
+        #[serde(default = "default_limit")]
+        limit: usize,
+    },
+    /// The full report of the last tick.
+    LastTick,
+    /// Which Markov edges make up an exe's score.
+    Explain {
+        #[serde(with = "config::path_as_text")]
+        path: PathBuf,
+    },
+}
+
+fn default_limit() -> usize {
+    DEFAULT_PREDICTION_LIMIT
+}
+
+/// The reply to a [`ControlRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ControlResponse {
+    /// The request was carried out.
+    Done,
+    Status(StatusReport),
+    Predictions(TopPredictions),
+    Explanation(Explanation),
+    LastTick {
+        report: Option<Box<TickReport>>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Engine state summary, as also logged on SIGUSR1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub paused: bool,
+    /// Ticks run since the engine started.
+    pub scan_id: u64,
+    pub model_time: u64,
+    pub exes: usize,
+    pub maps: usize,
+    pub edges: usize,
+    pub active: usize,
+    pub admission: Option<AdmissionPolicyStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TopPredictions {
+    /// Highest score first.
+    pub exes: Vec<ScoredExe>,
+    /// Highest score first.
+    pub maps: Vec<ScoredMap>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredExe {
+    #[serde(with = "config::path_as_text")]
+    pub path: PathBuf,
+    pub score: f32,
+    pub running: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredMap {
+    pub map: MapKey,
+    pub score: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_use_the_documented_wire_format() {
+        let parse = |line: &str| serde_json::from_str::<ControlRequest>(line).unwrap();
+        assert_eq!(parse(r#"{"command":"status"}"#), ControlRequest::Status);
+        assert_eq!(
+            parse(r#"{"command":"predictions"}"#),
+            ControlRequest::Predictions {
+                limit: DEFAULT_PREDICTION_LIMIT
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command":"predictions","limit":3}"#),
+            ControlRequest::Predictions { limit: 3 }
+        );
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command":"explode"}"#).is_err());
+
+        let reply = serde_json::to_string(&ControlResponse::Error {
+            message: "nope".into(),
+        })
+        .unwrap();
+        assert_eq!(reply, r#"{"kind":"error","message":"nope"}"#);
+    }
+
+    #[test]
+    fn non_utf8_paths_survive_the_wire() {
+        use crate::domain::MarkovState;
+        use crate::prediction::EdgeContribution;
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let exe = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/app"));
+        let other = PathBuf::from(OsStr::from_bytes(b"/opt/100%/\xff"));
+        let lib = PathBuf::from(OsStr::from_bytes(b"/opt/lib\xfe.so"));
+        let round_trip = |response: &ControlResponse| {
+            let line = serde_json::to_string(response).unwrap();
+            serde_json::from_str::<ControlResponse>(&line).unwrap()
+        };
+
+        let request = ControlRequest::Explain { path: exe.clone() };
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(line, r#"{"command":"explain","path":"/opt/caf%E9/app"}"#);
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(&line).unwrap(),
+            request
+        );
+
+        let explanation = ControlResponse::Explanation(Explanation {
+            path: exe.clone(),
+            running: false,
+            score: 0.5,
+            edges: vec![EdgeContribution {
+                other: other.clone(),
+                other_running: true,
+                state: MarkovState::BOnly,
+                transition: 0.5,
+                correlation: 1.0,
+                probability: 0.5,
+            }],
+        });
+        let ControlResponse::Explanation(read) = round_trip(&explanation) else {
+            panic!("expected an explanation");
+        };
+        assert_eq!(read.path, exe);
+        assert_eq!(read.edges[0].other, other);
+
+        let predictions = ControlResponse::Predictions(TopPredictions {
+            exes: vec![ScoredExe {
+                path: exe.clone(),
+                score: 0.5,
+                running: false,
+            }],
+            maps: vec![ScoredMap {
+                map: MapKey::new(lib.clone(), 0, 4096),
+                score: 0.5,
+            }],
+        });
+        let ControlResponse::Predictions(read) = round_trip(&predictions) else {
+            panic!("expected predictions");
+        };
+        assert_eq!(read.exes[0].path, exe);
+        assert_eq!(read.maps[0].map.path, lib);
+    }
+}
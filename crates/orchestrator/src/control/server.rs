@@ -0,0 +1,187 @@
+#![forbid(unsafe_code)]
+
+use crate::control::{ControlRequest, ControlResponse};
+use crate::engine::{ControlEvent, ReloadBundle};
+use crate::error::Error;
+use std::fs::{self, Permissions};
+use std::io::ErrorKind;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Builds the components for a `reload` request, typically by re-reading the
+/// configuration files the daemon was started with.
+pub type ReloadHook = Arc<dyn Fn() -> Result<ReloadBundle, String> + Send + Sync>;
+
+/// Serves [`ControlRequest`]s on a Unix socket by forwarding them to the
+/// engine loop as [`ControlEvent`]s.
+pub struct ControlServer {
+    path: PathBuf,
+    listener: UnixListener,
+    control_tx: mpsc::UnboundedSender<ControlEvent>,
+    reload: Option<ReloadHook>,
+}
+
+impl ControlServer {
+    /// Listen at `path`, replacing a socket left behind by an earlier run.
+    /// Anything else at `path`, including the socket of a daemon that is
+    /// still running, is an error. The socket is only accessible to its
+    /// owner.
+    pub fn bind(
+        path: impl Into<PathBuf>,
+        control_tx: mpsc::UnboundedSender<ControlEvent>,
+    ) -> Result<Self, Error> {
+        let path = path.into();
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)?;
+        remove_stale_socket(&path)?;
+
+        // Bind in a private directory and move the socket into place once
+        // its permissions are tightened, so it is never reachable with the
+        // umask's.
+        let staging = tempfile::Builder::new()
+            .prefix(".control")
+            .tempdir_in(parent)?;
+        let staged = staging.path().join("control.sock");
+        let listener = UnixListener::bind(&staged)?;
+        fs::set_permissions(&staged, Permissions::from_mode(0o600))?;
+        fs::rename(&staged, &path)?;
+
+        Ok(Self {
+            path,
+            listener,
+            control_tx,
+            reload: None,
+        })
+    }
+
+    /// Enable the `reload` command.
+    pub fn with_reload(mut self, hook: ReloadHook) -> Self {
+        self.reload = Some(hook);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accept connections until `cancel` fires, then remove the socket.
+    pub async fn serve(self, cancel: CancellationToken) {
+        info!(path = %self.path.display(), "control socket listening");
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let control_tx = self.control_tx.clone();
+                        let reload = self.reload.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = serve_connection(stream, control_tx, reload).await {
+                                debug!(%err, "control connection closed");
+                            }
+                        });
+                    }
+                    Err(err) => warn!(%err, "failed to accept control connection"),
+                },
+            }
+        }
+        if let Err(err) = fs::remove_file(&self.path) {
+            debug!(%err, "failed to remove control socket");
+        }
+    }
+}
+
+/// Remove the socket at `path` if no daemon answers on it.
+fn remove_stale_socket(path: &Path) -> Result<(), Error> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )
+        .into());
+    }
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(std::io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("another daemon is listening on {}", path.display()),
+        )
+        .into()),
+        Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+            fs::remove_file(path)?;
+            debug!(path = %path.display(), "removed stale control socket");
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn serve_connection(
+    stream: UnixStream,
+    control_tx: mpsc::UnboundedSender<ControlEvent>,
+    reload: Option<ReloadHook>,
+) -> Result<(), Error> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => dispatch(request, &control_tx, reload.as_ref()).await,
+            Err(err) => ControlResponse::Error {
+                message: format!("bad request: {err}"),
+            },
+        };
+        let mut out = serde_json::to_vec(&response)?;
+        out.push(b'\n');
+        write.write_all(&out).await?;
+    }
+    Ok(())
+}
+
+async fn dispatch(
+    request: ControlRequest,
+    control_tx: &mpsc::UnboundedSender<ControlEvent>,
+    reload: Option<&ReloadHook>,
+) -> ControlResponse {
+    let stopped = || ControlResponse::Error {
+        message: "engine is not running".into(),
+    };
+
+    if request == ControlRequest::Reload {
+        let Some(reload) = reload else {
+            return ControlResponse::Error {
+                message: "reload is not supported by this daemon".into(),
+            };
+        };
+        return match reload() {
+            Ok(bundle) => match control_tx.send(ControlEvent::Reload(Box::new(bundle))) {
+                Ok(()) => ControlResponse::Done,
+                Err(_) => stopped(),
+            },
+            Err(message) => ControlResponse::Error { message },
+        };
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if control_tx
+        .send(ControlEvent::Request(request, reply_tx))
+        .is_err()
+    {
+        return stopped();
+    }
+    reply_rx.await.unwrap_or_else(|_| stopped())
+}
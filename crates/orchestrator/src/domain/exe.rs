@@ -1,6 +1,6 @@
 #![forbid(unsafe_code)]
 
-use super::ExeKey;
+use super::{ExeIdentity, ExeKey};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Exe {
@@ -9,6 +9,8 @@ pub struct Exe {
     pub last_seen_time: Option<u64>,
     pub running: bool,
     pub change_time: u64,
+    /// Set when an identity mode is configured and the exe could be resolved.
+    pub identity: Option<ExeIdentity>,
 }
 
 impl Exe {
@@ -19,6 +21,7 @@ impl Exe {
             last_seen_time: None,
             running: false,
             change_time: 0,
+            identity: None,
         }
     }
 }
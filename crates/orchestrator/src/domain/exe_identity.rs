@@ -0,0 +1,76 @@
+#![forbid(unsafe_code)]
+
+use config::{decode_path, encode_path};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// What makes two exe paths the same program for the model.
+///
+/// Stored as text (`build-id:<hex>` or `path:<normalized path>`) so history
+/// can be matched again after the original file is gone. Paths are written
+/// with [`config::encode_path`], so no bytes are lost.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExeIdentity {
+    /// ELF `NT_GNU_BUILD_ID` note, hex encoded.
+    BuildId(String),
+    /// Path after the configured normalization rewrites.
+    Path(PathBuf),
+}
+
+impl fmt::Display for ExeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExeIdentity::BuildId(id) => write!(f, "build-id:{id}"),
+            ExeIdentity::Path(path) => write!(f, "path:{}", encode_path(path)),
+        }
+    }
+}
+
+impl Serialize for ExeIdentity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExeIdentity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for ExeIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(id) = s.strip_prefix("build-id:") {
+            Ok(ExeIdentity::BuildId(id.to_string()))
+        } else if let Some(path) = s.strip_prefix("path:") {
+            Ok(ExeIdentity::Path(decode_path(path)))
+        } else {
+            Err(format!("unknown exe identity {s:?}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn text_form_roundtrips() {
+        for identity in [
+            ExeIdentity::BuildId("8c1f3a".into()),
+            ExeIdentity::Path("/opt/app/bin/app".into()),
+            ExeIdentity::Path(PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/100%"))),
+        ] {
+            assert_eq!(identity.to_string().parse::<ExeIdentity>(), Ok(identity));
+        }
+        assert!("inode:12".parse::<ExeIdentity>().is_err());
+    }
+}
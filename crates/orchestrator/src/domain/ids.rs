@@ -1,13 +1,15 @@
 #![forbid(unsafe_code)]
 
+use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
 use std::{fmt, path::PathBuf};
 
 new_key_type! { pub struct ExeId; }
 new_key_type! { pub struct MapId; }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct ExeKey(PathBuf);
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExeKey(#[serde(with = "config::path_as_text")] PathBuf);
 
 impl ExeKey {
     pub fn new(path: impl Into<PathBuf>) -> Self {
@@ -25,8 +27,9 @@ impl fmt::Debug for ExeKey {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct MapKey {
+    #[serde(with = "config::path_as_text")]
     pub path: PathBuf,
     pub offset: u64,
     pub length: u64,
@@ -1,14 +1,19 @@
 #![forbid(unsafe_code)]
 
-use super::MapKey;
+use super::{FileIdentity, MapKey};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MapSegment {
+    #[serde(with = "config::path_as_text")]
     pub path: PathBuf,
     pub offset: u64,
     pub length: u64,
     pub update_time: u64,
+    /// Backing file at the time the map was seen, if it could be stat'ed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<FileIdentity>,
 }
 
 impl MapSegment {
@@ -18,9 +23,15 @@ impl MapSegment {
             offset,
             length,
             update_time,
+            identity: None,
         }
     }
 
+    pub fn with_identity(mut self, identity: Option<FileIdentity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
     pub fn key(&self) -> MapKey {
         MapKey::new(self.path.clone(), self.offset, self.length)
     }
@@ -1,10 +1,13 @@
 #![forbid(unsafe_code)]
 
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MarkovState {
+    #[default]
     Neither = 0,
     AOnly = 1,
     BOnly = 2,
@@ -24,6 +27,17 @@ impl MarkovState {
     pub fn index(self) -> usize {
         self as usize
     }
+
+    /// Inverse of [`MarkovState::index`].
+    pub fn from_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(MarkovState::Neither),
+            1 => Some(MarkovState::AOnly),
+            2 => Some(MarkovState::BOnly),
+            3 => Some(MarkovState::Both),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Debug for MarkovState {
@@ -60,6 +74,27 @@ impl MarkovEdge {
         }
     }
 
+    /// The same statistics seen from the other end of the edge, with the
+    /// roles of A and B exchanged.
+    pub fn swapped(&self) -> Self {
+        // Neither and Both map to themselves; AOnly and BOnly trade places.
+        const SWAP: [usize; 4] = [0, 2, 1, 3];
+        let mut edge = self.clone();
+        edge.state = match self.state {
+            MarkovState::AOnly => MarkovState::BOnly,
+            MarkovState::BOnly => MarkovState::AOnly,
+            state => state,
+        };
+        for (i, &si) in SWAP.iter().enumerate() {
+            edge.state_last_left[si] = self.state_last_left[i];
+            edge.time_to_leave[si] = self.time_to_leave[i];
+            for (j, &sj) in SWAP.iter().enumerate() {
+                edge.transition_prob[si][sj] = self.transition_prob[i][j];
+            }
+        }
+        edge
+    }
+
     /// Update the edge state and statistics when a transition occurs.
     pub fn update_state(&mut self, new_state: MarkovState, now: u64, decay: f32) {
         if new_state == self.state {
@@ -1,12 +1,16 @@
 #![forbid(unsafe_code)]
 
 mod exe;
+mod exe_identity;
+mod file_identity;
 mod ids;
 mod map_segment;
 mod markov;
 mod memstat;
 
 pub use exe::Exe;
+pub use exe_identity::ExeIdentity;
+pub use file_identity::FileIdentity;
 pub use ids::{ExeId, ExeKey, MapId, MapKey};
 pub use map_segment::MapSegment;
 pub use markov::{MarkovEdge, MarkovState};
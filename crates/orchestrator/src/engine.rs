@@ -1,19 +1,28 @@
 #![forbid(unsafe_code)]
 
 use crate::clock::Clock;
-use crate::domain::{ExeKey, MapSegment, MarkovState, MemStat};
+use crate::control::{
+    ControlRequest, ControlResponse, ScoredExe, ScoredMap, StatusReport, TopPredictions,
+};
+use crate::domain::{Exe, ExeId, ExeKey, MapId, MapSegment, MarkovEdge, MemStat};
 use crate::error::Error;
 use crate::observation::{AdmissionPolicy, ModelDelta, ModelUpdater, ObservationEvent, Scanner};
 use crate::persistence::{
-    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
-    StateRepository, StateSnapshot, StoresSnapshot,
+    APP_VERSION, ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, RepositoryOptions,
+    RepositoryRegistry, SNAPSHOT_SCHEMA_VERSION, SnapshotDelta, SnapshotMeta, StateRepository,
+    StateSnapshot, StoresSnapshot,
+};
+use crate::prediction::{Explanation, Prediction, Predictor};
+use crate::prefetch::{
+    MapValidator, PrefetchPlanner, PrefetchReport, Prefetcher, ValidationReport,
 };
-use crate::prediction::{Prediction, Predictor};
-use crate::prefetch::{PrefetchPlanner, PrefetchReport, Prefetcher};
-use crate::stores::Stores;
-use config::Config;
+use crate::stores::{EdgeKey, Stores};
+use config::{Config, Persistence};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::Path;
 use std::time::{Instant, SystemTime};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
 
@@ -23,6 +32,7 @@ pub struct Services {
     pub updater: Box<dyn ModelUpdater + Send + Sync>,
     pub predictor: Box<dyn Predictor + Send + Sync>,
     pub planner: Box<dyn PrefetchPlanner + Send + Sync>,
+    pub validator: Box<dyn MapValidator + Send + Sync>,
     pub prefetcher: Box<dyn Prefetcher + Send + Sync>,
     pub repo: Box<dyn StateRepository + Send + Sync>,
     pub clock: Box<dyn Clock + Send + Sync>,
@@ -41,13 +51,16 @@ pub enum ControlEvent {
     Reload(Box<ReloadBundle>),
     DumpStatus,
     SaveNow,
+    /// A control socket request; the reply goes back through the channel.
+    Request(ControlRequest, oneshot::Sender<ControlResponse>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TickReport {
     pub scan_id: u64,
     pub model_delta: ModelDelta,
     pub prediction: crate::prediction::PredictionSummary,
+    pub validation: ValidationReport,
     pub prefetch: PrefetchReport,
     pub memstat: Option<MemStat>,
 }
@@ -55,9 +68,20 @@ pub struct TickReport {
 pub struct PreloadEngine {
     config: Config,
     services: Services,
+    /// Opens the new repository when a reload moves the state.
+    repositories: RepositoryRegistry,
+    /// `false` when the model is kept in memory only; reloads then never
+    /// move the state.
+    persistent: bool,
     stores: Stores,
     scan_id: u64,
     last_save: Instant,
+    /// When the state was last saved in full. `None` until the first save,
+    /// since the repository may hold anything before then.
+    last_compaction: Option<Instant>,
+    last_prediction: Prediction,
+    last_report: Option<TickReport>,
+    paused: bool,
 }
 
 impl PreloadEngine {
@@ -66,25 +90,71 @@ impl PreloadEngine {
         Ok(Self {
             config,
             services,
+            repositories: RepositoryRegistry::default(),
+            persistent: true,
             stores: Stores::default(),
             scan_id: 0,
             last_save: Instant::now(),
+            last_compaction: None,
+            last_prediction: Prediction::default(),
+            last_report: None,
+            paused: false,
         })
     }
 
     /// Load state from the configured repository and build the engine.
+    /// Inconsistent state is repaired when `persistence.repair` is set.
     pub async fn load(config: Config, services: Services) -> Result<Self, Error> {
-        let snapshot = services.repo.load().await?;
+        let mut snapshot = match services.repo.load().await {
+            Ok(snapshot) => snapshot,
+            Err(err @ Error::UnsupportedSnapshotVersion { .. }) => return Err(err),
+            // Rather than never starting, relearn; the first save replaces
+            // the unreadable state.
+            Err(err) if config.persistence.repair => {
+                warn!(%err, "saved state is unreadable; starting with an empty model");
+                return Self::new(config, services).await;
+            }
+            Err(err) => return Err(err),
+        };
+        if let Err(err) = snapshot.validate() {
+            if !config.persistence.repair {
+                return Err(err);
+            }
+            warn!(%err, "saved state is inconsistent; repairing");
+            for lost in snapshot.repair() {
+                warn!(%lost, "dropped from saved state");
+            }
+        }
         let stores = Self::stores_from_snapshot(snapshot, config.model.active_window.as_secs())?;
         Ok(Self {
             config,
             services,
+            repositories: RepositoryRegistry::default(),
+            persistent: true,
             stores,
             scan_id: 0,
             last_save: Instant::now(),
+            last_compaction: None,
+            last_prediction: Prediction::default(),
+            last_report: None,
+            paused: false,
         })
     }
 
+    /// Use `registry` instead of the built-in backends to open the state when
+    /// a reload moves it.
+    pub fn with_repositories(mut self, registry: RepositoryRegistry) -> Self {
+        self.repositories = registry;
+        self
+    }
+
+    /// Keep the model in memory only: reloads leave the repository alone,
+    /// whatever state location their config names.
+    pub fn without_persistence(mut self) -> Self {
+        self.persistent = false;
+        self
+    }
+
     /// Execute a single scan/update/predict/prefetch cycle without sleeping.
     pub async fn tick(&mut self) -> Result<TickReport, Error> {
         self.scan_id = self.scan_id.saturating_add(1);
@@ -121,12 +191,18 @@ impl PreloadEngine {
             ModelDelta::default()
         };
 
-        let prediction = if self.config.system.dopredict {
+        let mut prediction = if self.config.system.dopredict {
             self.services.predictor.predict(&self.stores)
         } else {
             Prediction::default()
         };
 
+        // Drop maps whose backing file changed before the planner sees them.
+        let validation = self
+            .services
+            .validator
+            .validate(&mut prediction, &mut self.stores);
+
         let plan = if self.config.system.dopredict {
             if let Some(mem) = memstat {
                 self.services.planner.plan(&prediction, &self.stores, &mem)
@@ -146,6 +222,8 @@ impl PreloadEngine {
         };
 
         let prefetch = self.services.prefetcher.execute(&plan, &self.stores).await;
+        let summary = prediction.summarize();
+        self.last_prediction = prediction;
 
         // Advance model time by one cycle.
         self.stores.model_time = self
@@ -153,24 +231,30 @@ impl PreloadEngine {
             .model_time
             .saturating_add(self.config.model.cycle.as_secs());
 
-        Ok(TickReport {
+        let report = TickReport {
             scan_id: self.scan_id,
             model_delta,
-            prediction: prediction.summarize(),
+            prediction: summary,
+            validation,
             prefetch,
             memstat,
-        })
+        };
+        self.last_report = Some(report.clone());
+        Ok(report)
     }
 
     /// Run ticks until the cancellation token is triggered. Handles autosave.
+    ///
+    /// Control events are handled between ticks, so a tick always runs to
+    /// completion; while paused only control events are processed.
     pub async fn run_until(
         &mut self,
         cancel: CancellationToken,
         mut control_rx: mpsc::UnboundedReceiver<ControlEvent>,
     ) -> Result<(), Error> {
+        let mut next_tick = self.services.clock.now();
         loop {
-            let tick_start = self.services.clock.now();
-            let mut did_tick = false;
+            let sleep_for = next_tick.saturating_duration_since(self.services.clock.now());
             tokio::select! {
                 _ = cancel.cancelled() => {
                     if self.config.persistence.save_on_shutdown {
@@ -182,9 +266,10 @@ impl PreloadEngine {
                 Some(event) = control_rx.recv() => {
                     self.handle_control(event).await?;
                 }
-                result = self.tick() => {
-                    result?;
-                    did_tick = true;
+                _ = self.services.clock.sleep(sleep_for), if !self.paused => {
+                    let tick_start = self.services.clock.now();
+                    self.tick().await?;
+                    next_tick = tick_start + self.config.model.cycle;
                 }
             }
 
@@ -201,23 +286,44 @@ impl PreloadEngine {
                     self.last_save = Instant::now();
                 }
             }
-
-            if did_tick {
-                let elapsed = tick_start.elapsed();
-                if elapsed < self.config.model.cycle {
-                    let sleep_for = self.config.model.cycle - elapsed;
-                    self.services.clock.sleep(sleep_for).await;
-                }
-            }
         }
 
         Ok(())
     }
 
     /// Persist current state via the configured repository.
-    pub async fn save(&self) -> Result<(), Error> {
+    ///
+    /// With `persistence.incremental`, only what changed since the previous
+    /// save is written. The first save, the first after a failure, and one
+    /// every `persistence.compaction_interval` rewrite the whole state.
+    pub async fn save(&mut self) -> Result<(), Error> {
+        let persistence = &self.config.persistence;
+        let compact = !persistence.incremental
+            || self
+                .last_compaction
+                .is_none_or(|at| at.elapsed() >= persistence.compaction_interval);
+        // Whatever happens below, tracked changes are gone until a full save
+        // succeeds.
+        let last_compaction = self.last_compaction.take();
+
+        if !compact {
+            let delta = Self::delta_from_stores(&mut self.stores);
+            if self.services.repo.save_delta(&delta).await? {
+                self.last_compaction = last_compaction;
+                return Ok(());
+            }
+        }
+
         let snapshot = Self::snapshot_from_stores(&self.stores);
-        self.services.repo.save(&snapshot).await
+        self.services.repo.save(&snapshot).await?;
+        let _ = self.stores.take_changes();
+        self.last_compaction = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Configuration currently in effect, including reloads.
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
     /// Read-only access to in-memory stores (useful for tests).
@@ -225,10 +331,82 @@ impl PreloadEngine {
         &self.stores
     }
 
+    /// Scores produced by the most recent tick (empty before the first one).
+    pub fn last_prediction(&self) -> &Prediction {
+        &self.last_prediction
+    }
+
+    /// Score the current model without scanning, replacing
+    /// [`Self::last_prediction`]. Used to inspect a model loaded from disk.
+    pub fn refresh_prediction(&mut self) -> &Prediction {
+        self.last_prediction = self.services.predictor.predict(&self.stores);
+        &self.last_prediction
+    }
+
+    /// Break down the current score of the exe at `path`.
+    pub fn explain(&self, path: &Path) -> Option<Explanation> {
+        let exe = self.stores.exes.id_by_key(&ExeKey::new(path))?;
+        self.services.predictor.explain(&self.stores, exe)
+    }
+
+    /// Report of the most recent tick, if any.
+    pub fn last_report(&self) -> Option<&TickReport> {
+        self.last_report.as_ref()
+    }
+
+    pub fn status(&self) -> StatusReport {
+        StatusReport {
+            paused: self.paused,
+            scan_id: self.scan_id,
+            model_time: self.stores.model_time,
+            exes: self.stores.exes.len(),
+            maps: self.stores.maps.len(),
+            edges: self.stores.markov.iter().count(),
+            active: self.stores.active.exes().len(),
+            admission: self.services.admission.stats(),
+        }
+    }
+
+    /// The `limit` highest scored exes and maps of the most recent tick.
+    pub fn top_predictions(&self, limit: usize) -> TopPredictions {
+        let mut exes: Vec<ScoredExe> = self
+            .last_prediction
+            .exe_scores
+            .iter()
+            .filter_map(|(id, score)| {
+                let exe = self.stores.exes.get(*id)?;
+                Some(ScoredExe {
+                    path: exe.key.path().clone(),
+                    score: *score,
+                    running: exe.running,
+                })
+            })
+            .collect();
+        exes.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        exes.truncate(limit);
+
+        let mut maps: Vec<ScoredMap> = self
+            .last_prediction
+            .map_scores
+            .iter()
+            .filter_map(|(id, score)| {
+                let map = self.stores.maps.get(*id)?;
+                Some(ScoredMap {
+                    map: map.key(),
+                    score: *score,
+                })
+            })
+            .collect();
+        maps.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        maps.truncate(limit);
+
+        TopPredictions { exes, maps }
+    }
+
     async fn handle_control(&mut self, event: ControlEvent) -> Result<(), Error> {
         match event {
             ControlEvent::Reload(bundle) => {
-                self.apply_reload(*bundle);
+                self.apply_reload(*bundle).await;
                 info!("config reloaded");
             }
             ControlEvent::DumpStatus => {
@@ -239,18 +417,82 @@ impl PreloadEngine {
                 self.last_save = Instant::now();
                 info!("state saved");
             }
+            ControlEvent::Request(request, reply) => {
+                let response = self.handle_request(request).await;
+                // The client may have hung up; nothing to do then.
+                let _ = reply.send(response);
+            }
         }
         Ok(())
     }
 
-    fn apply_reload(&mut self, mut bundle: ReloadBundle) {
-        if bundle.config.persistence.state_path != self.config.persistence.state_path {
-            warn!(
-                current = ?self.config.persistence.state_path,
-                requested = ?bundle.config.persistence.state_path,
-                "ignoring state_path change during reload"
-            );
-            bundle.config.persistence.state_path = self.config.persistence.state_path.clone();
+    /// Answer a control request against the current state.
+    pub async fn handle_request(&mut self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Reload => ControlResponse::Error {
+                message: "reload must be resolved by the control server".into(),
+            },
+            ControlRequest::Save => match self.save().await {
+                Ok(()) => {
+                    self.last_save = Instant::now();
+                    info!("state saved");
+                    ControlResponse::Done
+                }
+                Err(err) => ControlResponse::Error {
+                    message: err.to_string(),
+                },
+            },
+            ControlRequest::Pause => {
+                self.paused = true;
+                info!("engine paused");
+                ControlResponse::Done
+            }
+            ControlRequest::Resume => {
+                self.paused = false;
+                info!("engine resumed");
+                ControlResponse::Done
+            }
+            ControlRequest::Status => ControlResponse::Status(self.status()),
+            ControlRequest::Predictions { limit } => {
+                ControlResponse::Predictions(self.top_predictions(limit))
+            }
+            ControlRequest::Explain { path } => match self.explain(&path) {
+                Some(explanation) => ControlResponse::Explanation(explanation),
+                None => ControlResponse::Error {
+                    message: format!("no explanation for {}", path.display()),
+                },
+            },
+            ControlRequest::LastTick => ControlResponse::LastTick {
+                report: self.last_report.clone().map(Box::new),
+            },
+        }
+    }
+
+    async fn apply_reload(&mut self, mut bundle: ReloadBundle) {
+        let current = self.config.persistence.state_url();
+        let requested = bundle.config.persistence.state_url();
+        if self.persistent && requested != current {
+            let moved = match &requested {
+                Some(url) => self.move_state(url, &bundle.config.persistence).await,
+                None => Err(Error::InvalidStateUrl(
+                    "reload sets no state location".into(),
+                )),
+            };
+            match moved {
+                Ok(()) => info!(from = ?current, to = ?requested, "state moved"),
+                Err(err) => {
+                    warn!(
+                        %err,
+                        current = ?current,
+                        requested = ?requested,
+                        "failed to move state; keeping the current location"
+                    );
+                    let persistence = &mut bundle.config.persistence;
+                    persistence.state_path = self.config.persistence.state_path.clone();
+                    persistence.backend = self.config.persistence.backend;
+                    persistence.url = self.config.persistence.url.clone();
+                }
+            }
         }
 
         self.config = bundle.config;
@@ -261,82 +503,70 @@ impl PreloadEngine {
         self.services.prefetcher = bundle.prefetcher;
     }
 
+    /// Save the model to the repository at `url` and use it from then on.
+    /// On failure the current repository stays in use.
+    async fn move_state(&mut self, url: &str, persistence: &Persistence) -> Result<(), Error> {
+        let options = RepositoryOptions {
+            backups: persistence.backups,
+            backup_interval: persistence.backup_interval,
+            repair: persistence.repair,
+            read_only: false,
+        };
+        let repo = self.repositories.open(url, &options).await?;
+        let old = std::mem::replace(&mut self.services.repo, repo);
+        // The new repository holds nothing of ours yet, so write it in full.
+        self.last_compaction = None;
+        if let Err(err) = self.save().await {
+            self.services.repo = old;
+            return Err(err);
+        }
+        self.last_save = Instant::now();
+
+        if persistence.remove_old_state
+            && let Err(err) = old.remove().await
+        {
+            warn!(%err, "failed to remove the old state");
+        }
+        Ok(())
+    }
+
     fn dump_status(&self) {
-        let exe_count = self.stores.exes.iter().count();
-        let map_count = self.stores.maps.iter().count();
-        let edge_count = self.stores.markov.iter().count();
-        let active_count = self.stores.active.exes().len();
+        let status = self.status();
 
         info!(?self.config, "current config");
         info!(
-            exe_count,
-            map_count,
-            edge_count,
-            active_count,
-            model_time = self.stores.model_time,
+            exe_count = status.exes,
+            map_count = status.maps,
+            edge_count = status.edges,
+            active_count = status.active,
+            model_time = status.model_time,
+            paused = status.paused,
             "state summary"
         );
-        if let Some(stats) = self.services.admission.stats() {
+        if let Some(stats) = status.admission {
             info!(?stats, "admission policy stats");
         }
     }
 
     fn snapshot_from_stores(stores: &Stores) -> StoresSnapshot {
-        let mut exes = Vec::new();
-        for (_, exe) in stores.exes.iter() {
-            exes.push(ExeRecord {
-                path: exe.key.path().clone(),
-                total_running_time: exe.total_running_time,
-                last_seen_time: exe.last_seen_time,
-            });
-        }
-
-        let mut maps = Vec::new();
-        for (_, map) in stores.maps.iter() {
-            maps.push(MapRecord {
-                path: map.path.clone(),
-                offset: map.offset,
-                length: map.length,
-                update_time: map.update_time,
-            });
-        }
+        let exes = stores.exes.iter().map(|(_, exe)| exe_record(exe)).collect();
+        let maps = stores.maps.iter().map(|(_, map)| map_record(map)).collect();
 
         let mut exe_maps = Vec::new();
-        for (exe_id, exe) in stores.exes.iter() {
+        for (exe_id, _) in stores.exes.iter() {
             for map_id in stores.exe_maps.maps_for_exe(exe_id) {
-                if let Some(map) = stores.maps.get(map_id) {
-                    exe_maps.push(ExeMapRecord {
-                        exe_path: exe.key.path().clone(),
-                        map_key: map.key(),
-                        prob: 1.0,
-                    });
-                }
+                exe_maps.extend(exe_map_record(stores, exe_id, map_id));
             }
         }
 
-        let mut markov_edges = Vec::new();
-        for (key, edge) in stores.markov.iter() {
-            let Some(exe_a) = stores.exes.get(key.a()) else {
-                continue;
-            };
-            let Some(exe_b) = stores.exes.get(key.b()) else {
-                continue;
-            };
-            markov_edges.push(MarkovRecord {
-                exe_a: exe_a.key.path().clone(),
-                exe_b: exe_b.key.path().clone(),
-                time_to_leave: edge.time_to_leave,
-                transition_prob: edge.transition_prob,
-                both_running_time: edge.both_running_time,
-            });
-        }
+        let markov_edges = stores
+            .markov
+            .iter()
+            .filter_map(|(key, _)| markov_record(stores, key))
+            .collect();
 
         StoresSnapshot {
-            meta: SnapshotMeta {
-                schema_version: SNAPSHOT_SCHEMA_VERSION,
-                app_version: None,
-                created_at: Some(SystemTime::now()),
-            },
+            meta: snapshot_meta(),
             state: StateSnapshot {
                 model_time: stores.model_time,
                 last_accounting_time: stores.last_accounting_time,
@@ -348,6 +578,53 @@ impl PreloadEngine {
         }
     }
 
+    /// Rows for everything that changed since the last call, resetting the
+    /// change tracking.
+    fn delta_from_stores(stores: &mut Stores) -> SnapshotDelta {
+        let changes = stores.take_changes();
+        let exe_path = |id| stores.exes.get(id).map(|exe| exe.key.path().clone());
+
+        SnapshotDelta {
+            meta: snapshot_meta(),
+            changed: StateSnapshot {
+                model_time: stores.model_time,
+                last_accounting_time: stores.last_accounting_time,
+                exes: changes
+                    .exes
+                    .iter()
+                    .filter_map(|id| stores.exes.get(*id).map(exe_record))
+                    .collect(),
+                maps: changes
+                    .maps
+                    .iter()
+                    .filter_map(|id| stores.maps.get(*id).map(map_record))
+                    .collect(),
+                exe_maps: changes
+                    .exe_maps
+                    .iter()
+                    .filter_map(|(exe_id, map_id)| exe_map_record(stores, *exe_id, *map_id))
+                    .collect(),
+                markov_edges: changes
+                    .edges
+                    .iter()
+                    .filter_map(|key| markov_record(stores, *key))
+                    .collect(),
+            },
+            removed_exes: changes
+                .removed_exes
+                .into_iter()
+                .map(|key| key.path().clone())
+                .collect(),
+            removed_maps: changes.removed_maps.into_iter().collect(),
+            // Edges of exes removed since are dropped along with the exe.
+            removed_edges: changes
+                .removed_edges
+                .iter()
+                .filter_map(|key| Some((exe_path(key.a())?, exe_path(key.b())?)))
+                .collect(),
+        }
+    }
+
     fn stores_from_snapshot(snapshot: StoresSnapshot, active_window: u64) -> Result<Stores, Error> {
         let mut stores = Stores {
             model_time: snapshot.state.model_time,
@@ -356,7 +633,8 @@ impl PreloadEngine {
         };
 
         for map in snapshot.state.maps {
-            let segment = MapSegment::new(map.path, map.offset, map.length, map.update_time);
+            let segment = MapSegment::new(map.path, map.offset, map.length, map.update_time)
+                .with_identity(map.identity);
             stores.ensure_map(segment);
         }
 
@@ -366,6 +644,9 @@ impl PreloadEngine {
             if let Some(exe_mut) = stores.exes.get_mut(exe_id) {
                 exe_mut.total_running_time = exe.total_running_time;
                 exe_mut.last_seen_time = exe.last_seen_time;
+                exe_mut.running = exe.running;
+                exe_mut.change_time = exe.change_time;
+                exe_mut.identity = exe.identity;
             }
         }
 
@@ -389,7 +670,9 @@ impl PreloadEngine {
                 .maps
                 .id_by_key(&map_key)
                 .ok_or_else(|| Error::MapMissing(map_key.path.clone()))?;
-            stores.attach_map(exe_id, map_id);
+            stores
+                .exe_maps
+                .attach_with_prob(exe_id, map_id, record.prob);
         }
 
         for record in snapshot.state.markov_edges {
@@ -403,17 +686,34 @@ impl PreloadEngine {
                 .exes
                 .id_by_key(&exe_b_key)
                 .ok_or_else(|| Error::ExeMissing(exe_b_key.path().clone()))?;
-            let state = MarkovState::Neither;
-            let key = crate::stores::EdgeKey::new(a, b);
-            if stores.ensure_markov_edge(a, b, stores.model_time, state)
+            let loaded = MarkovEdge {
+                state: record.state,
+                last_change_time: record.last_change_time,
+                state_last_left: record.state_last_left,
+                time_to_leave: record.time_to_leave,
+                transition_prob: record.transition_prob,
+                both_running_time: record.both_running_time,
+            };
+            let key = EdgeKey::new(a, b);
+            if stores.ensure_markov_edge(a, b, stores.model_time, record.state)
                 && let Some(edge) = stores.markov.get_mut(key)
             {
-                edge.time_to_leave = record.time_to_leave;
-                edge.transition_prob = record.transition_prob;
-                edge.both_running_time = record.both_running_time;
+                // Ids are assigned in load order, so the record's A may be
+                // the key's B.
+                *edge = if key.a() == a {
+                    loaded
+                } else {
+                    loaded.swapped()
+                };
             }
         }
 
+        // State saved before an identity mode was enabled, or by two copies
+        // running side by side, may hold the same program twice.
+        for (merged, kept) in stores.merge_same_identity() {
+            info!(?merged, ?kept, "merged exe history");
+        }
+
         let active = stores.active.exes();
         stores.markov.prune_inactive(&active);
 
@@ -421,10 +721,61 @@ impl PreloadEngine {
     }
 }
 
+fn snapshot_meta() -> SnapshotMeta {
+    SnapshotMeta {
+        schema_version: SNAPSHOT_SCHEMA_VERSION,
+        app_version: Some(APP_VERSION.to_string()),
+        created_at: Some(SystemTime::now()),
+    }
+}
+
+fn exe_record(exe: &Exe) -> ExeRecord {
+    ExeRecord {
+        path: exe.key.path().clone(),
+        total_running_time: exe.total_running_time,
+        last_seen_time: exe.last_seen_time,
+        running: exe.running,
+        change_time: exe.change_time,
+        identity: exe.identity.clone(),
+    }
+}
+
+fn map_record(map: &MapSegment) -> MapRecord {
+    MapRecord {
+        path: map.path.clone(),
+        offset: map.offset,
+        length: map.length,
+        update_time: map.update_time,
+        identity: map.identity,
+    }
+}
+
+fn exe_map_record(stores: &Stores, exe_id: ExeId, map_id: MapId) -> Option<ExeMapRecord> {
+    Some(ExeMapRecord {
+        exe_path: stores.exes.get(exe_id)?.key.path().clone(),
+        map_key: stores.maps.get(map_id)?.key(),
+        prob: stores.exe_maps.prob(exe_id, map_id)?,
+    })
+}
+
+fn markov_record(stores: &Stores, key: EdgeKey) -> Option<MarkovRecord> {
+    let edge = stores.markov.get(key)?;
+    Some(MarkovRecord {
+        exe_a: stores.exes.get(key.a())?.key.path().clone(),
+        exe_b: stores.exes.get(key.b())?.key.path().clone(),
+        state: edge.state,
+        last_change_time: edge.last_change_time,
+        state_last_left: edge.state_last_left,
+        time_to_leave: edge.time_to_leave,
+        transition_prob: edge.transition_prob,
+        both_running_time: edge.both_running_time,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{ExeKey, MapKey, MapSegment, MarkovState, MemStat};
+    use crate::domain::{ExeKey, FileIdentity, MapKey, MapSegment, MarkovState, MemStat};
     use crate::observation::{AdmissionDecision, AdmissionPolicy, CandidateExe, Completeness};
     use crate::observation::{ModelUpdater, Observation, ObservationEvent, Scanner};
     use crate::persistence::NoopRepository;
@@ -552,7 +903,7 @@ mod tests {
         fn snapshot_roundtrip_preserves_keys(
             exe_count in 0usize..8,
             map_count in 0usize..8,
-            attachments in prop::collection::vec((0u8..16, 0u8..16), 0..30),
+            attachments in prop::collection::vec((0u8..16, 0u8..16, 0f32..=1f32), 0..30),
             edges in prop::collection::vec(edge_strategy(), 0..20),
             model_time in 0u64..1_000,
         ) {
@@ -576,20 +927,23 @@ mod tests {
 
             let map_ids: Vec<_> = (0..map_count)
                 .map(|i| {
-                    stores.ensure_map(MapSegment::new(
-                        format!("/map/{i}"),
-                        (i as u64) * 4096,
-                        1024,
-                        model_time,
-                    ))
+                    let identity = (i % 2 == 0).then_some(FileIdentity {
+                        device: 2049,
+                        inode: i as u64,
+                        mtime_ns: model_time as i64 * 1_000_000_000,
+                    });
+                    stores.ensure_map(
+                        MapSegment::new(format!("/map/{i}"), (i as u64) * 4096, 1024, model_time)
+                            .with_identity(identity),
+                    )
                 })
                 .collect();
 
             if !exe_ids.is_empty() && !map_ids.is_empty() {
-                for (e, m) in attachments {
+                for (e, m, prob) in attachments {
                     let exe = exe_ids[e as usize % exe_ids.len()];
                     let map = map_ids[m as usize % map_ids.len()];
-                    stores.attach_map(exe, map);
+                    stores.exe_maps.attach_with_prob(exe, map, prob);
                 }
             }
 
@@ -624,7 +978,7 @@ mod tests {
                 .state
                 .maps
                 .iter()
-                .map(|map| MapKey::new(map.path.clone(), map.offset, map.length))
+                .map(|map| (MapKey::new(map.path.clone(), map.offset, map.length), map.identity))
                 .collect();
             let exe_map_set: HashSet<_> = snapshot
                 .state
@@ -654,7 +1008,7 @@ mod tests {
             let restored_maps: HashSet<_> = restored
                 .maps
                 .iter()
-                .map(|(_, map)| map.key())
+                .map(|(_, map)| (map.key(), map.identity))
                 .collect();
 
             prop_assert_eq!(restored_exes, exe_set);
@@ -674,6 +1028,15 @@ mod tests {
 
             prop_assert_eq!(restored_exe_maps, exe_map_set);
 
+            for record in &snapshot.state.exe_maps {
+                let exe_id = restored.exes.id_by_key(&ExeKey::new(record.exe_path.clone()));
+                let map_id = restored.maps.id_by_key(&record.map_key);
+                let prob = exe_id
+                    .zip(map_id)
+                    .and_then(|(exe_id, map_id)| restored.exe_maps.prob(exe_id, map_id));
+                prop_assert_eq!(prob, Some(record.prob));
+            }
+
             let restored_edges: HashMap<(std::path::PathBuf, std::path::PathBuf), EdgeData> =
                 restored
                     .markov
@@ -737,6 +1100,7 @@ mod tests {
                 id: 1,
                 hits: planner_hits.clone(),
             }),
+            validator: Box::new(crate::prefetch::NoopMapValidator),
             prefetcher: Box::new(Recording {
                 id: 1,
                 hits: prefetcher_hits.clone(),
@@ -780,7 +1144,7 @@ mod tests {
             }),
         };
 
-        engine.apply_reload(bundle);
+        engine.apply_reload(bundle).await;
         engine.tick().await.expect("tick");
 
         assert_eq!(admission_hits.load(Ordering::SeqCst), 2);
@@ -22,6 +22,18 @@ pub enum Error {
     #[error("deserialization error: {0}")]
     RkyvDeserialize(String),
 
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid trace: {0}")]
+    InvalidTrace(String),
+
+    #[error("unsupported trace version {found} (supported: {supported})")]
+    UnsupportedTraceVersion { found: u32, supported: u32 },
+
+    #[error("trace exhausted")]
+    TraceExhausted,
+
     #[error("invalid path: {0}")]
     InvalidPath(PathBuf),
 
@@ -30,4 +42,23 @@ pub enum Error {
 
     #[error("missing map: {0:?}")]
     MapMissing(PathBuf),
+
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    #[error("invalid state url: {0}")]
+    InvalidStateUrl(String),
+
+    #[error("no state backend for scheme {0:?}")]
+    UnknownStateBackend(String),
+
+    #[error(
+        "state {} is in use by another writer{}",
+        path.display(),
+        pid.map(|pid| format!(" (pid {pid})")).unwrap_or_default()
+    )]
+    StateLocked { path: PathBuf, pid: Option<u32> },
+
+    #[error("unsupported snapshot schema {found} (supported: {supported})")]
+    UnsupportedSnapshotVersion { found: u32, supported: u32 },
 }
@@ -0,0 +1,247 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{ExeId, MapId};
+use crate::engine::PreloadEngine;
+use crate::error::Error;
+use crate::evaluation::metrics::{BudgetMetrics, ScoreHistogram, ThresholdMetrics};
+use crate::prediction::Prediction;
+use crate::stores::Stores;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use tracing::debug;
+
+const MIB: u64 = 1024 * 1024;
+
+/// What to measure while evaluating a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationOptions {
+    /// Score thresholds at which precision and recall are reported.
+    pub thresholds: Vec<f32>,
+    /// Prefetch budgets, in bytes, at which useful/wasted bytes are reported.
+    pub budgets: Vec<u64>,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![0.1, 0.25, 0.5, 0.75],
+            budgets: vec![16 * MIB, 64 * MIB, 256 * MIB, 1024 * MIB],
+        }
+    }
+}
+
+/// Prediction quality accumulated over a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationReport {
+    /// Ticks whose predictions were scored against the following tick.
+    pub ticks: u64,
+    /// Exe predictions scored (one per known, not running exe per tick).
+    pub samples: u64,
+    /// Exes that started between two ticks.
+    pub starts: u64,
+    /// Starts of exes the model had never seen, which no predictor can score.
+    pub unseen_starts: u64,
+    /// ROC AUC of exe scores; `None` until both outcomes have been seen.
+    pub auc: Option<f64>,
+    pub thresholds: Vec<ThresholdMetrics>,
+    pub budgets: Vec<BudgetMetrics>,
+}
+
+/// Scores each tick's prediction against the exes that started in the next
+/// cycle.
+///
+/// Feed it the stores and prediction after every tick with
+/// [`Evaluator::observe`], or let [`Evaluator::run`] drive an engine whose
+/// scanner replays a trace.
+#[derive(Debug)]
+pub struct Evaluator {
+    histogram: ScoreHistogram,
+    report: EvaluationReport,
+    pending: Option<PendingTick>,
+}
+
+/// Everything about a tick needed to score it once the next tick is known.
+#[derive(Debug)]
+struct PendingTick {
+    running: HashSet<ExeId>,
+    exe_scores: Vec<(ExeId, f32)>,
+    /// Maps with a positive score, highest first, with their length.
+    maps: Vec<(MapId, u64)>,
+}
+
+impl Evaluator {
+    pub fn new(options: EvaluationOptions) -> Self {
+        Self {
+            histogram: ScoreHistogram::default(),
+            report: EvaluationReport {
+                ticks: 0,
+                samples: 0,
+                starts: 0,
+                unseen_starts: 0,
+                auc: None,
+                thresholds: options
+                    .thresholds
+                    .into_iter()
+                    .map(ThresholdMetrics::new)
+                    .collect(),
+                budgets: options
+                    .budgets
+                    .into_iter()
+                    .map(BudgetMetrics::new)
+                    .collect(),
+            },
+            pending: None,
+        }
+    }
+
+    /// Tick `engine` until its scanner reports [`Error::TraceExhausted`],
+    /// scoring every prediction along the way.
+    pub async fn run(mut self, engine: &mut PreloadEngine) -> Result<EvaluationReport, Error> {
+        loop {
+            match engine.tick().await {
+                Ok(_) => self.observe(engine.stores(), engine.last_prediction()),
+                Err(Error::TraceExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        let report = self.report();
+        debug!(?report, "evaluation finished");
+        Ok(report)
+    }
+
+    /// Score the previous tick against `stores`, then remember `prediction`
+    /// for the next call.
+    pub fn observe(&mut self, stores: &Stores, prediction: &Prediction) {
+        let running: HashSet<ExeId> = stores
+            .exes
+            .iter()
+            .filter(|(_, exe)| exe.running)
+            .map(|(id, _)| id)
+            .collect();
+
+        if let Some(previous) = self.pending.take() {
+            self.score(&previous, &running, stores);
+        }
+
+        let exe_scores = prediction
+            .exe_scores
+            .iter()
+            .filter(|(id, _)| !running.contains(id))
+            .map(|(id, score)| (*id, *score))
+            .collect();
+
+        let mut maps: Vec<(MapId, f32)> = prediction
+            .map_scores
+            .iter()
+            .filter(|(_, score)| **score > 0.0)
+            .map(|(id, score)| (*id, *score))
+            .collect();
+        maps.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        let maps = maps
+            .into_iter()
+            .filter_map(|(id, _)| stores.maps.get(id).map(|map| (id, map.length)))
+            .collect();
+
+        self.pending = Some(PendingTick {
+            running,
+            exe_scores,
+            maps,
+        });
+    }
+
+    /// Metrics accumulated so far.
+    pub fn report(&self) -> EvaluationReport {
+        EvaluationReport {
+            auc: self.histogram.auc(),
+            ..self.report.clone()
+        }
+    }
+
+    fn score(&mut self, previous: &PendingTick, running: &HashSet<ExeId>, stores: &Stores) {
+        let started: HashSet<ExeId> = running.difference(&previous.running).copied().collect();
+        let report = &mut self.report;
+        report.ticks += 1;
+        report.starts += started.len() as u64;
+
+        let scored: HashSet<ExeId> = previous.exe_scores.iter().map(|(id, _)| *id).collect();
+        report.unseen_starts += started.difference(&scored).count() as u64;
+
+        for (exe_id, score) in &previous.exe_scores {
+            let hit = started.contains(exe_id);
+            report.samples += 1;
+            self.histogram.record(*score, hit);
+            for metrics in &mut report.thresholds {
+                metrics.record(*score, hit);
+            }
+        }
+
+        let needed: HashMap<MapId, u64> = started
+            .iter()
+            .flat_map(|exe_id| stores.exe_maps.maps_for_exe(*exe_id))
+            .filter_map(|map_id| stores.maps.get(map_id).map(|map| (map_id, map.length)))
+            .collect();
+
+        for budget in &mut report.budgets {
+            let mut remaining = budget.budget_bytes;
+            let mut useful = 0u64;
+            for (map_id, length) in &previous.maps {
+                if *length > remaining {
+                    continue;
+                }
+                remaining -= length;
+                if needed.contains_key(map_id) {
+                    useful += length;
+                } else {
+                    budget.wasted_bytes += length;
+                }
+            }
+            let needed_bytes: u64 = needed.values().sum();
+            budget.useful_bytes += useful;
+            budget.missed_bytes += needed_bytes - useful;
+        }
+    }
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |value: Option<f64>| match value {
+            Some(value) => format!("{:.1}%", value * 100.0),
+            None => "n/a".to_string(),
+        };
+
+        writeln!(
+            f,
+            "ticks: {}  samples: {}  starts: {} ({} unseen)",
+            self.ticks, self.samples, self.starts, self.unseen_starts
+        )?;
+        match self.auc {
+            Some(auc) => writeln!(f, "auc: {auc:.4}")?,
+            None => writeln!(f, "auc: n/a")?,
+        }
+        for metrics in &self.thresholds {
+            writeln!(
+                f,
+                "threshold {:.2}: precision {}  recall {}",
+                metrics.threshold,
+                percent(metrics.precision()),
+                percent(metrics.recall())
+            )?;
+        }
+        for metrics in &self.budgets {
+            writeln!(
+                f,
+                "budget {:.1} MiB: useful {:.1} MiB  wasted {:.1} MiB  missed {:.1} MiB",
+                mib(metrics.budget_bytes),
+                mib(metrics.useful_bytes),
+                mib(metrics.wasted_bytes),
+                mib(metrics.missed_bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / MIB as f64
+}
@@ -0,0 +1,197 @@
+#![forbid(unsafe_code)]
+
+/// Resolution of the score histogram used for AUC.
+const AUC_BINS: usize = 1000;
+
+/// Outcomes of exe predictions at one score threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdMetrics {
+    /// Exes scoring at or above this value count as predicted to start.
+    pub threshold: f32,
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub false_negatives: u64,
+}
+
+impl ThresholdMetrics {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            true_positives: 0,
+            false_positives: 0,
+            false_negatives: 0,
+        }
+    }
+
+    pub fn record(&mut self, score: f32, started: bool) {
+        match (score >= self.threshold, started) {
+            (true, true) => self.true_positives += 1,
+            (true, false) => self.false_positives += 1,
+            (false, true) => self.false_negatives += 1,
+            (false, false) => {}
+        }
+    }
+
+    /// Fraction of predicted exes that started; `None` if nothing was predicted.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    /// Fraction of started exes that were predicted; `None` if nothing started.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+}
+
+/// Prefetch outcome had the planner been given a fixed byte budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetMetrics {
+    pub budget_bytes: u64,
+    /// Selected bytes belonging to an exe that started next cycle.
+    pub useful_bytes: u64,
+    /// Selected bytes no starting exe needed.
+    pub wasted_bytes: u64,
+    /// Bytes needed by starting exes that were not selected.
+    pub missed_bytes: u64,
+}
+
+impl BudgetMetrics {
+    pub fn new(budget_bytes: u64) -> Self {
+        Self {
+            budget_bytes,
+            useful_bytes: 0,
+            wasted_bytes: 0,
+            missed_bytes: 0,
+        }
+    }
+}
+
+/// Bucketed exe scores split by outcome, for computing ROC AUC in constant
+/// memory over arbitrarily long traces.
+#[derive(Debug, Clone)]
+pub(crate) struct ScoreHistogram {
+    positives: Vec<u64>,
+    negatives: Vec<u64>,
+}
+
+impl Default for ScoreHistogram {
+    fn default() -> Self {
+        Self {
+            positives: vec![0; AUC_BINS + 1],
+            negatives: vec![0; AUC_BINS + 1],
+        }
+    }
+}
+
+impl ScoreHistogram {
+    pub(crate) fn record(&mut self, score: f32, started: bool) {
+        let bin = (score.clamp(0.0, 1.0) * AUC_BINS as f32).round() as usize;
+        if started {
+            self.positives[bin] += 1;
+        } else {
+            self.negatives[bin] += 1;
+        }
+    }
+
+    /// Probability that a started exe outscored one that did not (ties count
+    /// half). `None` until both outcomes have been seen.
+    pub(crate) fn auc(&self) -> Option<f64> {
+        let total_pos: u64 = self.positives.iter().sum();
+        let total_neg: u64 = self.negatives.iter().sum();
+        if total_pos == 0 || total_neg == 0 {
+            return None;
+        }
+
+        let mut neg_below = 0u64;
+        let mut area = 0.0f64;
+        for (pos, neg) in self.positives.iter().zip(&self.negatives) {
+            // Positives in this bin beat every negative in a lower bin.
+            area += *pos as f64 * (neg_below as f64 + *neg as f64 / 2.0);
+            neg_below += neg;
+        }
+        Some(area / (total_pos as f64 * total_neg as f64))
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn auc_of_perfect_and_inverted_rankings() {
+        let mut perfect = ScoreHistogram::default();
+        perfect.record(0.9, true);
+        perfect.record(0.1, false);
+        assert_eq!(perfect.auc(), Some(1.0));
+
+        let mut inverted = ScoreHistogram::default();
+        inverted.record(0.1, true);
+        inverted.record(0.9, false);
+        assert_eq!(inverted.auc(), Some(0.0));
+
+        let mut tied = ScoreHistogram::default();
+        tied.record(0.5, true);
+        tied.record(0.5, false);
+        assert_eq!(tied.auc(), Some(0.5));
+
+        assert_eq!(ScoreHistogram::default().auc(), None);
+    }
+
+    #[test]
+    fn precision_and_recall_count_threshold_crossings() {
+        let mut metrics = ThresholdMetrics::new(0.5);
+        metrics.record(0.8, true);
+        metrics.record(0.6, false);
+        metrics.record(0.2, true);
+        metrics.record(0.1, false);
+        assert_eq!(metrics.precision(), Some(0.5));
+        assert_eq!(metrics.recall(), Some(0.5));
+        assert_eq!(ThresholdMetrics::new(0.5).precision(), None);
+    }
+
+    proptest! {
+        #[test]
+        fn auc_matches_pairwise_comparison(
+            samples in prop::collection::vec((0u16..=1000, any::<bool>()), 1..64),
+        ) {
+            let mut histogram = ScoreHistogram::default();
+            for (score, started) in &samples {
+                histogram.record(*score as f32 / 1000.0, *started);
+            }
+
+            let positives: Vec<u16> = samples.iter().filter(|s| s.1).map(|s| s.0).collect();
+            let negatives: Vec<u16> = samples.iter().filter(|s| !s.1).map(|s| s.0).collect();
+            let expected = if positives.is_empty() || negatives.is_empty() {
+                None
+            } else {
+                let mut wins = 0.0;
+                for p in &positives {
+                    for n in &negatives {
+                        wins += match p.cmp(n) {
+                            std::cmp::Ordering::Greater => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Less => 0.0,
+                        };
+                    }
+                }
+                Some(wins / (positives.len() * negatives.len()) as f64)
+            };
+
+            match (histogram.auc(), expected) {
+                (Some(actual), Some(expected)) => prop_assert!((actual - expected).abs() < 1e-9),
+                (actual, expected) => prop_assert_eq!(actual, expected),
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+#![forbid(unsafe_code)]
+
+//! Offline prediction-quality evaluation over recorded observation traces.
+
+mod evaluator;
+mod metrics;
+
+pub use evaluator::{EvaluationOptions, EvaluationReport, Evaluator};
+pub use metrics::{BudgetMetrics, ThresholdMetrics};
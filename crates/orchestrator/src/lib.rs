@@ -1,9 +1,11 @@
 #![forbid(unsafe_code)]
 
 pub mod clock;
+pub mod control;
 pub mod domain;
 pub mod engine;
 pub mod error;
+pub mod evaluation;
 pub mod observation;
 pub mod persistence;
 pub mod prediction;
@@ -11,18 +13,23 @@ pub mod prefetch;
 pub mod stores;
 
 pub use engine::{ControlEvent, PreloadEngine, ReloadBundle, Services, TickReport};
+pub use evaluation::{EvaluationOptions, EvaluationReport, Evaluator};
 pub use observation::{
     AdmissionDecision, AdmissionPolicy, AdmissionPolicyStats, CandidateExe, Completeness,
-    DefaultAdmissionPolicy, DefaultModelUpdater, ModelDelta, ModelUpdater, Observation,
-    ObservationEvent, ProcfsScanner, RejectReason, ScanWarning, Scanner,
+    DefaultAdmissionPolicy, DefaultModelUpdater, ModelDelta, ModelUpdater, NetlinkScanner,
+    Observation, ObservationEvent, ProcfsScanner, RecordingScanner, RejectReason, ReplayScanner,
+    ScanWarning, Scanner, TraceReader, TraceWriter,
 };
 pub use persistence::{NoopRepository, SqliteRepository, StateRepository, StoresSnapshot};
 pub use prediction::{MarkovPredictor, Prediction, PredictionSummary, Predictor};
 pub use prefetch::{
-    GreedyPrefetchPlanner, NoopPrefetcher, PosixFadvisePrefetcher, PrefetchPlan, PrefetchPlanner,
-    PrefetchReport, Prefetcher,
+    FileMapValidator, GreedyPrefetchPlanner, MapValidator, NoopMapValidator, NoopPrefetcher,
+    PosixFadvisePrefetcher, PrefetchPlan, PrefetchPlanner, PrefetchReport, Prefetcher,
+    ValidationReport,
 };
 
 pub use clock::{Clock, SystemClock};
-pub use domain::{Exe, ExeId, ExeKey, MapId, MapKey, MapSegment, MarkovEdge, MarkovState, MemStat};
+pub use domain::{
+    Exe, ExeId, ExeKey, FileIdentity, MapId, MapKey, MapSegment, MarkovEdge, MarkovState, MemStat,
+};
 pub use stores::Stores;
@@ -4,6 +4,8 @@ use crate::observation::CandidateExe;
 use config::Config;
 use moka::policy::EvictionPolicy;
 use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
@@ -14,7 +16,8 @@ pub enum Completeness {
     Partial,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RejectReason {
     TooSmall,
     ExePrefixDenied,
@@ -85,19 +88,18 @@ impl DefaultAdmissionPolicy {
         }
     }
 
+    /// Prefixes match raw path bytes, so paths that are not UTF-8 are
+    /// judged like any other.
     fn accept_path<T: AsRef<str>>(path: &Path, prefixes: &[T]) -> bool {
         let mut best: Option<(bool, usize)> = None;
-        let path_str = match path.to_str() {
-            Some(s) => s,
-            None => return false,
-        };
+        let path_bytes = path.as_os_str().as_bytes();
         for prefix in prefixes {
             let prefix = prefix.as_ref();
             let (neg, p) = prefix
                 .strip_prefix('!')
                 .map(|p| (true, p))
                 .unwrap_or((false, prefix));
-            if path_str.starts_with(p) {
+            if path_bytes.starts_with(p.as_bytes()) {
                 let len = p.len();
                 if best.map(|(_, l)| l).unwrap_or(0) < len {
                     best = Some((!neg, len));
@@ -211,7 +213,7 @@ impl DefaultAdmissionPolicy {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdmissionPolicyStats {
     pub cache_enabled: bool,
     pub cache_hits: u64,
@@ -387,6 +389,21 @@ mod tests {
         assert!(stats.cache_entries >= 1);
     }
 
+    #[test]
+    fn non_utf8_paths_match_by_bytes() {
+        use std::ffi::OsStr;
+
+        let prefixes = ["!/", "/opt/caf", "!/opt/caf\u{e9}"];
+        let latin1 = Path::new(OsStr::from_bytes(b"/opt/caf\xe9/bin/app"));
+        let utf8 = Path::new("/opt/caf\u{e9}/bin/app");
+        assert!(DefaultAdmissionPolicy::accept_path(latin1, &prefixes));
+        assert!(!DefaultAdmissionPolicy::accept_path(utf8, &prefixes));
+        assert!(!DefaultAdmissionPolicy::accept_path(
+            Path::new(OsStr::from_bytes(b"/usr/\xff")),
+            &prefixes
+        ));
+    }
+
     proptest! {
         #[test]
         fn accept_path_matches_reference(
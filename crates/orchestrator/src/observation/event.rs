@@ -1,19 +1,23 @@
 #![forbid(unsafe_code)]
 
 use crate::domain::{MapSegment, MemStat};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum ObservationEvent {
     ObsBegin {
         time: u64,
         scan_id: u64,
     },
     ExeSeen {
+        #[serde(with = "config::path_as_text")]
         path: PathBuf,
         pid: u32,
     },
     MapSeen {
+        #[serde(with = "config::path_as_text")]
         exe_path: PathBuf,
         map: MapSegment,
     },
@@ -29,7 +33,8 @@ pub enum ObservationEvent {
 
 pub type Observation = Vec<ObservationEvent>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum ScanWarning {
     MapScanFailed { pid: u32, reason: String },
 }
@@ -0,0 +1,359 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{ExeIdentity, FileIdentity};
+use config::{Identity, IdentityMode};
+use regex::bytes::Regex;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use tracing::{trace, warn};
+
+const PT_NOTE: u32 = 4;
+const NT_GNU_BUILD_ID: u32 = 3;
+/// Note segments are tiny; anything larger is not worth reading.
+const MAX_NOTE_SEGMENT: u64 = 64 * 1024;
+
+/// Resolves exe paths to an [`ExeIdentity`] according to `model.identity`.
+///
+/// Results are cached per path and reused while the file keeps its device,
+/// inode and mtime, so each version of a binary is read once.
+#[derive(Debug, Clone)]
+pub struct ExeIdentityResolver {
+    mode: IdentityMode,
+    rewrites: Vec<(Regex, String)>,
+    cache: HashMap<PathBuf, (Option<FileIdentity>, ExeIdentity)>,
+}
+
+impl ExeIdentityResolver {
+    /// Build a resolver; rewrites with an invalid pattern are skipped with a
+    /// warning.
+    pub fn new(config: &Identity) -> Self {
+        let rewrites = config
+            .normalize
+            .iter()
+            .filter_map(|rewrite| match Regex::new(&rewrite.pattern) {
+                Ok(regex) => Some((regex, rewrite.replace.clone())),
+                Err(err) => {
+                    warn!(pattern = %rewrite.pattern, %err, "ignoring invalid identity rewrite");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            mode: config.mode,
+            rewrites,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != IdentityMode::Path
+    }
+
+    pub fn resolve(&mut self, path: &Path) -> Option<ExeIdentity> {
+        if !self.is_enabled() {
+            return None;
+        }
+        // An upgrade replaces the binary, and with it the build-id; only
+        // build-ids depend on the file's contents.
+        let file = match self.mode {
+            IdentityMode::BuildId => FileIdentity::of(path).ok(),
+            _ => None,
+        };
+        if let Some((seen, identity)) = self.cache.get(path)
+            && *seen == file
+        {
+            return Some(identity.clone());
+        }
+
+        let build_id = match self.mode {
+            IdentityMode::BuildId => match File::open(path).and_then(read_build_id) {
+                Ok(id) => id,
+                Err(err) => {
+                    trace!(?path, %err, "failed to read build-id");
+                    None
+                }
+            },
+            _ => None,
+        };
+        let identity = match build_id {
+            Some(id) => ExeIdentity::BuildId(hex(&id)),
+            None => ExeIdentity::Path(self.normalize(path)),
+        };
+
+        self.cache
+            .insert(path.to_path_buf(), (file, identity.clone()));
+        Some(identity)
+    }
+
+    /// Apply every rewrite in order, on the path's bytes. Patterns match
+    /// UTF-8 text; `(?-u:...)` matches bytes that are not.
+    pub fn normalize(&self, path: &Path) -> PathBuf {
+        let mut normalized = path.as_os_str().as_bytes().to_vec();
+        for (regex, replace) in &self.rewrites {
+            normalized = regex
+                .replace_all(&normalized, replace.as_bytes())
+                .into_owned();
+        }
+        PathBuf::from(OsString::from_vec(normalized))
+    }
+}
+
+/// Read the GNU build-id note from an ELF file, if it has one.
+pub fn read_build_id(file: impl Read + Seek) -> io::Result<Option<Vec<u8>>> {
+    let mut file = BufReader::new(file);
+    let mut ident = [0u8; 16];
+    file.read_exact(&mut ident)?;
+    if &ident[..4] != b"\x7fELF" {
+        return Ok(None);
+    }
+    let wide = match ident[4] {
+        1 => false,
+        2 => true,
+        _ => return Ok(None),
+    };
+    let elf = Elf {
+        big_endian: ident[5] == 2,
+    };
+
+    let mut header = vec![0u8; if wide { 48 } else { 36 }];
+    file.read_exact(&mut header)?;
+    // Offsets below are relative to the end of e_ident.
+    let (phoff, phentsize, phnum) = if wide {
+        (
+            elf.u64(&header[16..]),
+            elf.u16(&header[38..]),
+            elf.u16(&header[40..]),
+        )
+    } else {
+        (
+            elf.u32(&header[12..]) as u64,
+            elf.u16(&header[26..]),
+            elf.u16(&header[28..]),
+        )
+    };
+
+    if (phentsize as usize) < if wide { 56 } else { 32 } {
+        return Ok(None);
+    }
+    let mut entry = vec![0u8; phentsize as usize];
+    for index in 0..phnum as u64 {
+        file.seek(SeekFrom::Start(phoff + index * phentsize as u64))?;
+        file.read_exact(&mut entry)?;
+        if elf.u32(&entry) != PT_NOTE {
+            continue;
+        }
+        let (offset, size, align) = if wide {
+            (
+                elf.u64(&entry[8..]),
+                elf.u64(&entry[32..]),
+                elf.u64(&entry[48..]),
+            )
+        } else {
+            (
+                elf.u32(&entry[4..]) as u64,
+                elf.u32(&entry[16..]) as u64,
+                elf.u32(&entry[28..]) as u64,
+            )
+        };
+        if size > MAX_NOTE_SEGMENT {
+            continue;
+        }
+        let mut notes = vec![0u8; size as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut notes)?;
+        if let Some(id) = elf.find_build_id(&notes, if align == 8 { 8 } else { 4 }) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Elf {
+    big_endian: bool,
+}
+
+impl Elf {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn u64(self, bytes: &[u8]) -> u64 {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&bytes[..8]);
+        if self.big_endian {
+            u64::from_be_bytes(buf)
+        } else {
+            u64::from_le_bytes(buf)
+        }
+    }
+
+    fn find_build_id(self, mut notes: &[u8], align: usize) -> Option<Vec<u8>> {
+        let pad = |len: usize| len.div_ceil(align) * align;
+        while notes.len() >= 12 {
+            let name_size = self.u32(notes) as usize;
+            let desc_size = self.u32(&notes[4..]) as usize;
+            let kind = self.u32(&notes[8..]);
+            let desc_start = 12usize.checked_add(pad(name_size))?;
+            let desc_end = desc_start.checked_add(desc_size)?;
+            if desc_end > notes.len() {
+                return None;
+            }
+            if kind == NT_GNU_BUILD_ID && notes[12..12 + name_size] == *b"GNU\0" {
+                return Some(notes[desc_start..desc_end].to_vec());
+            }
+            notes = notes.get(desc_start + pad(desc_size)..)?;
+        }
+        None
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::PathRewrite;
+    use std::io::Cursor;
+
+    /// A minimal little-endian ELF64 image with one PT_NOTE segment.
+    fn elf_with_notes(notes: &[u8]) -> Vec<u8> {
+        let phoff = 64u64;
+        let note_offset = phoff + 56;
+        let mut image = vec![0u8; note_offset as usize];
+        image[..4].copy_from_slice(b"\x7fELF");
+        image[4] = 2;
+        image[5] = 1;
+        image[32..40].copy_from_slice(&phoff.to_le_bytes());
+        image[54..56].copy_from_slice(&56u16.to_le_bytes());
+        image[56..58].copy_from_slice(&1u16.to_le_bytes());
+
+        let ph = phoff as usize;
+        image[ph..ph + 4].copy_from_slice(&PT_NOTE.to_le_bytes());
+        image[ph + 8..ph + 16].copy_from_slice(&note_offset.to_le_bytes());
+        image[ph + 32..ph + 40].copy_from_slice(&(notes.len() as u64).to_le_bytes());
+        image[ph + 48..ph + 56].copy_from_slice(&4u64.to_le_bytes());
+        image.extend_from_slice(notes);
+        image
+    }
+
+    fn note(name: &[u8], kind: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&kind.to_le_bytes());
+        note.extend_from_slice(name);
+        note.resize(note.len().div_ceil(4) * 4, 0);
+        note.extend_from_slice(desc);
+        note.resize(note.len().div_ceil(4) * 4, 0);
+        note
+    }
+
+    #[test]
+    fn finds_the_gnu_build_id_note() {
+        let mut notes = note(b"GNU\0", 5, &[0; 12]);
+        notes.extend(note(
+            b"GNU\0",
+            NT_GNU_BUILD_ID,
+            &[0xde, 0xad, 0xbe, 0xef, 0x01],
+        ));
+        let image = elf_with_notes(&notes);
+
+        let id = read_build_id(Cursor::new(image)).unwrap();
+        assert_eq!(id, Some(vec![0xde, 0xad, 0xbe, 0xef, 0x01]));
+
+        let image = elf_with_notes(&note(b"Go\0\0", 4, b"go-build-id"));
+        assert_eq!(read_build_id(Cursor::new(image)).unwrap(), None);
+        assert_eq!(
+            read_build_id(Cursor::new(b"#!/bin/sh\nexit 0\n".to_vec())).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn build_id_falls_back_to_normalized_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf = dir.path().join("app");
+        let notes = note(b"GNU\0", NT_GNU_BUILD_ID, &[0xab, 0xcd]);
+        std::fs::write(&elf, elf_with_notes(&notes)).unwrap();
+
+        let mut resolver = ExeIdentityResolver::new(&Identity {
+            mode: IdentityMode::BuildId,
+            normalize: vec![
+                PathRewrite {
+                    pattern: r"^/opt/([^/]+)-[0-9.]+/".into(),
+                    replace: "/opt/$1/".into(),
+                },
+                PathRewrite {
+                    pattern: "(".into(),
+                    replace: String::new(),
+                },
+            ],
+        });
+
+        assert_eq!(
+            resolver.resolve(&elf),
+            Some(ExeIdentity::BuildId("abcd".into()))
+        );
+        assert_eq!(
+            resolver.resolve(Path::new("/opt/app-1.2.3/bin/app")),
+            Some(ExeIdentity::Path("/opt/app/bin/app".into()))
+        );
+        let latin1 = |bytes: &[u8]| PathBuf::from(OsString::from_vec(bytes.to_vec()));
+        assert_eq!(
+            resolver.resolve(&latin1(b"/opt/app-2.0/caf\xe9")),
+            Some(ExeIdentity::Path(latin1(b"/opt/app/caf\xe9")))
+        );
+
+        let mut disabled = ExeIdentityResolver::new(&Identity::default());
+        assert_eq!(disabled.resolve(&elf), None);
+    }
+
+    #[test]
+    fn replaced_binaries_are_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf = dir.path().join("app");
+        let write = |id: &[u8]| {
+            // Package managers install through a rename, like this.
+            let staged = dir.path().join("app.new");
+            let notes = note(b"GNU\0", NT_GNU_BUILD_ID, id);
+            std::fs::write(&staged, elf_with_notes(&notes)).unwrap();
+            std::fs::rename(&staged, &elf).unwrap();
+        };
+        let mut resolver = ExeIdentityResolver::new(&Identity {
+            mode: IdentityMode::BuildId,
+            normalize: Vec::new(),
+        });
+
+        write(&[0x01]);
+        assert_eq!(
+            resolver.resolve(&elf),
+            Some(ExeIdentity::BuildId("01".into()))
+        );
+        write(&[0x02]);
+        assert_eq!(
+            resolver.resolve(&elf),
+            Some(ExeIdentity::BuildId("02".into()))
+        );
+    }
+}
@@ -0,0 +1,158 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{FileIdentity, MapSegment};
+use crate::observation::ProcfsScanner;
+use procfs::process::Process;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Parsed maps of running processes, reused across scans.
+///
+/// A process is identified by its pid and start time. `/proc/<pid>/maps` is
+/// only parsed again when the process is new, its exe changed, or its virtual
+/// memory size (read from the much cheaper `/proc/<pid>/stat`) moved, which is
+/// what an `mmap`/`munmap` of a library does.
+///
+/// File identities are not kept across scans: every mapped path is stat'd
+/// once per scan, so a library replaced by an upgrade is reported with its
+/// new identity even while old processes keep it mapped.
+#[derive(Debug, Default)]
+pub(crate) struct MapsCache {
+    entries: HashMap<u32, CachedMaps>,
+    /// Identities stat'd during the current scan, by path.
+    identities: HashMap<PathBuf, Option<FileIdentity>>,
+    generation: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug)]
+struct CachedMaps {
+    start_time: u64,
+    vsize: u64,
+    exe_path: PathBuf,
+    maps: Vec<MapSegment>,
+    generation: u64,
+}
+
+impl MapsCache {
+    /// Start a new scan; entries not touched until [`MapsCache::finish_scan`] are dropped.
+    pub(crate) fn begin_scan(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.identities.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Drop entries for processes that were not seen during this scan.
+    /// Returns `(hits, misses)` for the scan.
+    pub(crate) fn finish_scan(&mut self) -> (u64, u64) {
+        let generation = self.generation;
+        self.entries
+            .retain(|_, entry| entry.generation == generation);
+        (self.hits, self.misses)
+    }
+
+    /// Return the maps of `process`, stamped with `time`.
+    pub(crate) fn maps(
+        &mut self,
+        process: &Process,
+        exe_path: &Path,
+        time: u64,
+    ) -> procfs::ProcResult<Vec<MapSegment>> {
+        let pid = process.pid as u32;
+        // Read stat before maps so a concurrent change is caught next scan.
+        let stat = process.stat()?;
+
+        let valid = self.entries.get(&pid).is_some_and(|entry| {
+            entry.start_time == stat.starttime
+                && entry.vsize == stat.vsize
+                && entry.exe_path == exe_path
+        });
+
+        if valid {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let maps = ProcfsScanner::read_maps(process, time)?;
+            for map in &maps {
+                self.identities
+                    .entry(map.path.clone())
+                    .or_insert(map.identity);
+            }
+            self.entries.insert(
+                pid,
+                CachedMaps {
+                    start_time: stat.starttime,
+                    vsize: stat.vsize,
+                    exe_path: exe_path.to_path_buf(),
+                    maps,
+                    generation: self.generation,
+                },
+            );
+        }
+
+        let Some(entry) = self.entries.get_mut(&pid) else {
+            return Ok(Vec::new());
+        };
+        entry.generation = self.generation;
+        let identities = &mut self.identities;
+        Ok(entry
+            .maps
+            .iter()
+            .map(|map| MapSegment {
+                update_time: time,
+                identity: *identities
+                    .entry(map.path.clone())
+                    .or_insert_with(|| FileIdentity::of(&map.path).ok()),
+                ..map.clone()
+            })
+            .collect())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn unchanged_process_is_served_from_cache() {
+        let mut child = Command::new("sleep").arg("5").spawn().expect("spawn sleep");
+        // Give the dynamic loader time to finish mapping libraries.
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let process = Process::new(child.id() as i32).expect("child process");
+        let exe_path = process.exe().expect("child exe");
+
+        let mut cache = MapsCache::default();
+        cache.begin_scan();
+        let first = cache.maps(&process, &exe_path, 1).expect("maps");
+        assert_eq!(cache.finish_scan(), (0, 1));
+
+        cache.begin_scan();
+        let second = cache.maps(&process, &exe_path, 2).expect("maps");
+        assert_eq!(cache.finish_scan(), (1, 0));
+
+        assert!(!first.is_empty());
+        assert_eq!(first.len(), second.len());
+        assert!(second.iter().all(|map| map.update_time == 2));
+
+        cache.begin_scan();
+        cache
+            .maps(&process, Path::new("/other/exe"), 3)
+            .expect("maps");
+        assert_eq!(cache.finish_scan(), (0, 1));
+
+        cache.begin_scan();
+        cache.finish_scan();
+        assert_eq!(cache.len(), 0);
+
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
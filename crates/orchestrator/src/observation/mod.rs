@@ -2,16 +2,26 @@
 
 mod admission;
 mod event;
+mod exe_identity;
+mod maps_cache;
 mod model_updater;
+mod netlink_scanner;
+mod proc_connector;
 mod procfs_scanner;
+mod trace;
+mod trace_scanner;
 
 pub use admission::{
     AdmissionDecision, AdmissionPolicy, AdmissionPolicyStats, Completeness, DefaultAdmissionPolicy,
     RejectReason,
 };
 pub use event::{Observation, ObservationEvent, ScanWarning};
+pub use exe_identity::{ExeIdentityResolver, read_build_id};
 pub use model_updater::{DefaultModelUpdater, ModelDelta, ModelUpdater};
+pub use netlink_scanner::NetlinkScanner;
 pub use procfs_scanner::ProcfsScanner;
+pub use trace::{TRACE_FORMAT, TRACE_VERSION, TraceHeader, TraceReader, TraceWriter};
+pub use trace_scanner::{RecordingScanner, ReplayScanner};
 
 use crate::error::Error;
 
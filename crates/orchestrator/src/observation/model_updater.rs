@@ -3,14 +3,16 @@
 use crate::domain::{ExeKey, MapKey, MarkovState};
 use crate::error::Error;
 use crate::observation::{
-    AdmissionDecision, AdmissionPolicy, CandidateExe, Completeness, Observation, ObservationEvent,
+    AdmissionDecision, AdmissionPolicy, CandidateExe, Completeness, ExeIdentityResolver,
+    Observation, ObservationEvent,
 };
-use crate::stores::Stores;
+use crate::stores::{EdgeKey, EvictionLimits, Stores};
 use config::Config;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use tracing::{debug, trace};
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ModelDelta {
     pub new_exes: Vec<ExeKey>,
     pub new_maps: Vec<MapKey>,
@@ -19,6 +21,11 @@ pub struct ModelDelta {
     pub stopped_now: Vec<ExeKey>,
     pub rejected: Vec<(ExeKey, super::RejectReason)>,
     pub partial_exes: Vec<ExeKey>,
+    pub evicted_exes: Vec<ExeKey>,
+    pub evicted_maps: Vec<MapKey>,
+    /// `(merged, kept)`: idle exes whose history moved to an exe with the same
+    /// identity.
+    pub merged_exes: Vec<(ExeKey, ExeKey)>,
 }
 
 pub trait ModelUpdater: Send + Sync {
@@ -34,6 +41,8 @@ pub trait ModelUpdater: Send + Sync {
 pub struct DefaultModelUpdater {
     active_window: u64,
     decay: f32,
+    eviction: EvictionLimits,
+    identity: ExeIdentityResolver,
 }
 
 impl DefaultModelUpdater {
@@ -41,6 +50,13 @@ impl DefaultModelUpdater {
         Self {
             active_window: config.model.active_window.as_secs(),
             decay: config.model.decay_factor(),
+            eviction: EvictionLimits {
+                max_exes: config.model.eviction.max_exes,
+                max_maps: config.model.eviction.max_maps,
+                max_exe_age: config.model.eviction.max_exe_age.map(|age| age.as_secs()),
+                max_map_age: config.model.eviction.max_map_age.map(|age| age.as_secs()),
+            },
+            identity: ExeIdentityResolver::new(&config.model.identity),
         }
     }
 }
@@ -106,15 +122,52 @@ impl ModelUpdater for DefaultModelUpdater {
                         exe.last_seen_time = Some(now);
                     }
 
+                    // Resolved at every sighting, since the binary behind a
+                    // path can be replaced; the resolver caches by file.
+                    if let Some(identity) = self.identity.resolve(&candidate.path)
+                        && stores
+                            .exes
+                            .get(exe_id)
+                            .is_some_and(|exe| exe.identity.as_ref() != Some(&identity))
+                    {
+                        // Take over the history of idle exes that are the same
+                        // program under another path.
+                        let same: Vec<_> = stores
+                            .exes
+                            .iter()
+                            .filter(|(id, exe)| {
+                                *id != exe_id
+                                    && exe.identity.as_ref() == Some(&identity)
+                                    && !running_paths.contains(exe.key.path())
+                            })
+                            .map(|(id, _)| id)
+                            .collect();
+                        if let Some(exe) = stores.exes.get_mut(exe_id) {
+                            exe.identity = Some(identity);
+                        }
+                        for other in same {
+                            if let Some(merged) = stores.merge_exe(other, exe_id) {
+                                debug!(?merged, into = ?exe_key, "merged exe history");
+                                delta.merged_exes.push((merged, exe_key.clone()));
+                            }
+                        }
+                    }
+
                     if completeness == Completeness::Partial {
                         delta.partial_exes.push(exe_key.clone());
                     }
 
                     for map in candidate.maps {
                         let map_key = map.key();
+                        let identity = map.identity;
                         let (map_id, is_new) = stores.ensure_map_with_flag(map);
                         if is_new {
                             delta.new_maps.push(map_key);
+                        } else {
+                            stores.maps.touch(map_id, now);
+                            if let Some(identity) = identity {
+                                stores.maps.set_identity(map_id, identity);
+                            }
                         }
                         stores.attach_map(exe_id, map_id);
                     }
@@ -126,23 +179,34 @@ impl ModelUpdater for DefaultModelUpdater {
             }
         }
 
-        // Update running flags and transitions.
+        // Update running flags and transitions. Exes are only borrowed
+        // mutably when their state flips, which keeps the rest out of the
+        // next incremental save.
         let exe_ids: Vec<_> = stores.exes.iter().map(|(id, _)| id).collect();
         for exe_id in exe_ids {
+            let Some(exe) = stores.exes.get(exe_id) else {
+                continue;
+            };
+            let is_running = running_paths.contains(exe.key.path());
+            if is_running {
+                active_exe_ids.insert(exe_id);
+            }
+            if exe.running == is_running {
+                continue;
+            }
             if let Some(exe_mut) = stores.exes.get_mut(exe_id) {
-                let is_running = running_paths.contains(exe_mut.key.path());
-                if exe_mut.running != is_running {
-                    exe_mut.change_time = now;
-                    if is_running {
-                        delta.running_now.push(exe_mut.key.clone());
-                    } else {
-                        delta.stopped_now.push(exe_mut.key.clone());
-                    }
-                }
-                exe_mut.running = is_running;
                 if is_running {
-                    active_exe_ids.insert(exe_id);
+                    delta.running_now.push(exe_mut.key.clone());
+                } else {
+                    // Learn which maps this run actually used; longer runs
+                    // carry more weight.
+                    let run_time = now.saturating_sub(exe_mut.change_time);
+                    let mix = (-self.decay * run_time as f32).exp();
+                    stores.exe_maps.end_run(exe_id, mix);
+                    delta.stopped_now.push(exe_mut.key.clone());
                 }
+                exe_mut.change_time = now;
+                exe_mut.running = is_running;
             }
         }
 
@@ -174,18 +238,25 @@ impl ModelUpdater for DefaultModelUpdater {
         // Accounting time updates.
         let period = now.saturating_sub(stores.last_accounting_time);
         if period > 0 {
-            let exe_ids: Vec<_> = stores.exes.iter().map(|(id, _)| id).collect();
-            for exe_id in exe_ids {
-                if let Some(exe_mut) = stores.exes.get_mut(exe_id)
-                    && exe_mut.running
-                {
+            let running: Vec<_> = stores
+                .exes
+                .iter()
+                .filter(|(_, exe)| exe.running)
+                .map(|(id, _)| id)
+                .collect();
+            for exe_id in running {
+                if let Some(exe_mut) = stores.exes.get_mut(exe_id) {
                     exe_mut.total_running_time = exe_mut.total_running_time.saturating_add(period);
                 }
             }
-            for (key, edge) in stores.markov.iter_mut() {
-                let a_running = stores.exes.get(key.a()).map(|e| e.running).unwrap_or(false);
-                let b_running = stores.exes.get(key.b()).map(|e| e.running).unwrap_or(false);
-                if a_running && b_running {
+            let both_running: Vec<_> = stores
+                .markov
+                .iter()
+                .filter(|(key, _)| edge_state(stores, *key) == MarkovState::Both)
+                .map(|(key, _)| key)
+                .collect();
+            for key in both_running {
+                if let Some(edge) = stores.markov.get_mut(key) {
                     edge.both_running_time = edge.both_running_time.saturating_add(period);
                 }
             }
@@ -193,13 +264,29 @@ impl ModelUpdater for DefaultModelUpdater {
         stores.last_accounting_time = now;
 
         // Update Markov transitions.
-        for (key, edge) in stores.markov.iter_mut() {
-            let a_running = stores.exes.get(key.a()).map(|e| e.running).unwrap_or(false);
-            let b_running = stores.exes.get(key.b()).map(|e| e.running).unwrap_or(false);
-            let new_state = MarkovState::from_running(a_running, b_running);
-            edge.update_state(new_state, now, self.decay);
+        let transitions: Vec<_> = stores
+            .markov
+            .iter()
+            .map(|(key, edge)| (key, edge.state, edge_state(stores, key)))
+            .filter(|(_, old, new)| old != new)
+            .collect();
+        for (key, _, new_state) in transitions {
+            if let Some(edge) = stores.markov.get_mut(key) {
+                edge.update_state(new_state, now, self.decay);
+            }
         }
 
+        let evicted = stores.evict(&self.eviction, now);
+        if !evicted.exes.is_empty() || !evicted.maps.is_empty() {
+            debug!(
+                exes = evicted.exes.len(),
+                maps = evicted.maps.len(),
+                "evicted stale model state"
+            );
+        }
+        delta.evicted_exes = evicted.exes;
+        delta.evicted_maps = evicted.maps;
+
         stores.model_time = now;
 
         trace!(?delta, "model delta computed");
@@ -208,3 +295,9 @@ impl ModelUpdater for DefaultModelUpdater {
         Ok(delta)
     }
 }
+
+/// The state an edge is in given which of its exes run now.
+fn edge_state(stores: &Stores, key: EdgeKey) -> MarkovState {
+    let running = |id| stores.exes.get(id).is_some_and(|exe| exe.running);
+    MarkovState::from_running(running(key.a()), running(key.b()))
+}
@@ -0,0 +1,403 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::MapSegment;
+use crate::error::Error;
+use crate::observation::maps_cache::MapsCache;
+use crate::observation::proc_connector::{self, ProcEvent};
+use crate::observation::procfs_scanner::DEFAULT_PROC_ROOT;
+use crate::observation::{Observation, ObservationEvent, ProcfsScanner, ScanWarning, Scanner};
+use netlink_sys::protocols::NETLINK_CONNECTOR;
+use netlink_sys::{Socket, SocketAddr};
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use procfs::process::Process;
+use std::collections::HashMap;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use tracing::{debug, trace, warn};
+
+/// How often the listener thread wakes up to check for shutdown.
+const POLL_INTERVAL_MS: u16 = 250;
+const RECV_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Scanner driven by the kernel process events connector.
+///
+/// A background thread receives exec/exit notifications and keeps a live set
+/// of running exes between ticks. Processes that start and exit within a
+/// single cycle are still reported as `ExeSeen` on the next tick, which a
+/// periodic `/proc` walk would miss.
+///
+/// The live set is rebuilt from `/proc` on the first scan and whenever the
+/// kernel reports dropped events. If the listener stops, every scan falls back
+/// to a full `/proc` walk.
+#[derive(Debug)]
+pub struct NetlinkScanner {
+    root: PathBuf,
+    live: Arc<Mutex<LiveSet>>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+    maps_cache: MapsCache,
+}
+
+#[derive(Debug, Clone)]
+struct TrackedProcess {
+    exe_path: PathBuf,
+    /// Maps captured when the exec was observed; used once the process is gone.
+    maps: Vec<MapSegment>,
+    /// Exec'd since the last tick and not yet reported.
+    fresh: bool,
+}
+
+#[derive(Debug)]
+struct LiveSet {
+    running: HashMap<u32, TrackedProcess>,
+    /// Fresh processes that exited before the tick could report them.
+    exited: Vec<(u32, TrackedProcess)>,
+    needs_resync: bool,
+    listening: bool,
+}
+
+impl NetlinkScanner {
+    /// Subscribe to the proc connector and start listening for exec/exit events.
+    ///
+    /// Requires `CAP_NET_ADMIN`; callers usually fall back to [`ProcfsScanner`]
+    /// when this fails.
+    pub fn new() -> Result<Self, Error> {
+        Self::with_root(DEFAULT_PROC_ROOT)
+    }
+
+    /// Like [`NetlinkScanner::new`], but read processes and memory stats
+    /// below `root` instead of the live `/proc`. Events still come from the
+    /// kernel, so `root` must show the same pid namespace.
+    pub fn with_root(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root = root.into();
+        let mut socket = Socket::new(NETLINK_CONNECTOR)?;
+        socket.bind(&SocketAddr::new(0, proc_connector::CN_IDX_PROC))?;
+        socket.send_to(
+            &proc_connector::subscribe_message(true),
+            &SocketAddr::new(0, 0),
+            0,
+        )?;
+
+        let live = Arc::new(Mutex::new(LiveSet {
+            running: HashMap::new(),
+            exited: Vec::new(),
+            needs_resync: true,
+            listening: true,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let worker = {
+            let root = root.clone();
+            let live = live.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("proc-connector".into())
+                .spawn(move || Self::listen(socket, &root, &live, &stop))?
+        };
+
+        debug!("subscribed to proc connector");
+        Ok(Self {
+            root,
+            live,
+            stop,
+            worker: Some(worker),
+            maps_cache: MapsCache::default(),
+        })
+    }
+
+    fn lock(live: &Mutex<LiveSet>) -> MutexGuard<'_, LiveSet> {
+        live.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn listen(socket: Socket, root: &Path, live: &Mutex<LiveSet>, stop: &AtomicBool) {
+        let mut buf = Vec::with_capacity(RECV_BUFFER_SIZE);
+        while !stop.load(Ordering::Relaxed) {
+            let mut fds = [PollFd::new(socket.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, PollTimeout::from(POLL_INTERVAL_MS)) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(?err, "proc connector poll failed");
+                    break;
+                }
+            }
+
+            buf.clear();
+            if let Err(err) = socket.recv(&mut buf, 0) {
+                if err.raw_os_error() == Some(libc::ENOBUFS) {
+                    warn!("proc connector dropped events; resyncing from procfs");
+                    Self::lock(live).needs_resync = true;
+                    continue;
+                }
+                warn!(%err, "proc connector receive failed");
+                break;
+            }
+
+            for event in proc_connector::parse_events(&buf) {
+                Self::handle_event(root, live, event);
+            }
+        }
+
+        {
+            // The live set no longer follows the kernel; scans walk /proc.
+            let mut live = Self::lock(live);
+            live.listening = false;
+            live.needs_resync = true;
+        }
+        let _ = socket.send_to(
+            &proc_connector::subscribe_message(false),
+            &SocketAddr::new(0, 0),
+            0,
+        );
+    }
+
+    fn handle_event(root: &Path, live: &Mutex<LiveSet>, event: ProcEvent) {
+        match event {
+            ProcEvent::Exec { tgid, .. } => {
+                // Read /proc before the process has a chance to exit.
+                let Some(tracked) = Self::inspect(root, tgid) else {
+                    return;
+                };
+                trace!(pid = tgid, exe = ?tracked.exe_path, "exec observed");
+                let mut live = Self::lock(live);
+                if let Some(previous) = live.running.insert(tgid, tracked)
+                    && previous.fresh
+                {
+                    live.exited.push((tgid, previous));
+                }
+            }
+            // Thread exits share the tgid; only the group leader ends the process.
+            ProcEvent::Exit { pid, tgid } if pid == tgid => {
+                let mut live = Self::lock(live);
+                if let Some(tracked) = live.running.remove(&tgid)
+                    && tracked.fresh
+                {
+                    live.exited.push((tgid, tracked));
+                }
+            }
+            ProcEvent::Exit { .. } => {}
+        }
+    }
+
+    /// Capture the exe path and current maps of a newly exec'd process.
+    fn inspect(root: &Path, pid: u32) -> Option<TrackedProcess> {
+        let process = Process::new_with_root(root.join(pid.to_string())).ok()?;
+        let exe_path = ProcfsScanner::sanitize_path(&process.exe().ok()?)?;
+        let maps = ProcfsScanner::read_maps(&process, 0).unwrap_or_default();
+        Some(TrackedProcess {
+            exe_path,
+            maps,
+            fresh: true,
+        })
+    }
+
+    /// Rebuild the running set from a full `/proc` walk.
+    fn resync(&self) -> Result<(), Error> {
+        let mut walked = HashMap::new();
+        for process in procfs::process::all_processes_with_root(&self.root)? {
+            let Ok(process) = process else {
+                continue;
+            };
+            let Ok(exe_path) = process.exe() else {
+                continue;
+            };
+            let Some(exe_path) = ProcfsScanner::sanitize_path(&exe_path) else {
+                continue;
+            };
+            walked.insert(process.pid as u32, exe_path);
+        }
+
+        let mut live = Self::lock(&self.live);
+        live.running
+            .retain(|pid, tracked| tracked.fresh || walked.contains_key(pid));
+        for (pid, exe_path) in walked {
+            live.running.entry(pid).or_insert(TrackedProcess {
+                exe_path,
+                maps: Vec::new(),
+                fresh: false,
+            });
+        }
+        live.needs_resync = !live.listening;
+        debug!(
+            running = live.running.len(),
+            "live set resynced from procfs"
+        );
+        Ok(())
+    }
+
+    fn push_process(
+        &mut self,
+        events: &mut Vec<ObservationEvent>,
+        warnings: &mut Vec<ScanWarning>,
+        pid: u32,
+        tracked: TrackedProcess,
+        alive: bool,
+        time: u64,
+    ) {
+        events.push(ObservationEvent::ExeSeen {
+            path: tracked.exe_path.clone(),
+            pid,
+        });
+
+        let maps = if alive {
+            let maps = Process::new_with_root(self.root.join(pid.to_string()))
+                .and_then(|p| self.maps_cache.maps(&p, &tracked.exe_path, time));
+            match maps {
+                Ok(maps) => maps,
+                Err(err) if tracked.maps.is_empty() => {
+                    warnings.push(ScanWarning::MapScanFailed {
+                        pid,
+                        reason: err.to_string(),
+                    });
+                    Vec::new()
+                }
+                Err(_) => tracked.maps,
+            }
+        } else {
+            tracked.maps
+        };
+
+        for mut map in maps {
+            map.update_time = time;
+            events.push(ObservationEvent::MapSeen {
+                exe_path: tracked.exe_path.clone(),
+                map,
+            });
+        }
+    }
+}
+
+impl Scanner for NetlinkScanner {
+    fn scan(&mut self, time: u64, scan_id: u64) -> Result<Observation, Error> {
+        if Self::lock(&self.live).needs_resync {
+            self.resync()?;
+        }
+
+        let (running, exited) = {
+            let mut live = Self::lock(&self.live);
+            let running: Vec<_> = live
+                .running
+                .iter_mut()
+                .map(|(pid, tracked)| {
+                    let snapshot = tracked.clone();
+                    tracked.fresh = false;
+                    tracked.maps = Vec::new();
+                    (*pid, snapshot)
+                })
+                .collect();
+            (running, std::mem::take(&mut live.exited))
+        };
+
+        let mut events = Vec::new();
+        let mut warnings = Vec::new();
+        events.push(ObservationEvent::ObsBegin { time, scan_id });
+
+        let short_lived = exited.len();
+        self.maps_cache.begin_scan();
+        for (pid, tracked) in running {
+            self.push_process(&mut events, &mut warnings, pid, tracked, true, time);
+        }
+        for (pid, tracked) in exited {
+            self.push_process(&mut events, &mut warnings, pid, tracked, false, time);
+        }
+        let (cached, reparsed) = self.maps_cache.finish_scan();
+
+        if let Ok(mem) = ProcfsScanner::read_memstat(&self.root) {
+            events.push(ObservationEvent::MemStat { mem });
+        }
+
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id,
+            warnings,
+        });
+
+        trace!(
+            scan_id,
+            event_count = events.len(),
+            short_lived,
+            cached,
+            reparsed,
+            "observation collected"
+        );
+        Ok(events)
+    }
+}
+
+impl Drop for NetlinkScanner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn add_process(root: &Path, pid: u32, exe: &str) {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        symlink(exe, dir.join("exe")).unwrap();
+    }
+
+    fn seen_pids(observation: &Observation) -> Vec<u32> {
+        let mut pids: Vec<u32> = observation
+            .iter()
+            .filter_map(|event| match event {
+                ObservationEvent::ExeSeen { pid, .. } => Some(*pid),
+                _ => None,
+            })
+            .collect();
+        pids.sort_unstable();
+        pids
+    }
+
+    #[test]
+    fn scans_walk_proc_once_the_listener_stops() {
+        let root = tempfile::tempdir().unwrap();
+        add_process(root.path(), 42, "/usr/bin/running");
+
+        let gone = TrackedProcess {
+            exe_path: PathBuf::from("/usr/bin/gone"),
+            maps: Vec::new(),
+            fresh: false,
+        };
+        let live = Arc::new(Mutex::new(LiveSet {
+            running: HashMap::from([(7, gone)]),
+            exited: Vec::new(),
+            needs_resync: false,
+            listening: true,
+        }));
+        // A worker asked to stop before its first poll exits at once.
+        let stop = Arc::new(AtomicBool::new(true));
+        let socket = Socket::new(NETLINK_CONNECTOR).unwrap();
+        {
+            let root = root.path().to_path_buf();
+            let live = live.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || NetlinkScanner::listen(socket, &root, &live, &stop))
+                .join()
+                .unwrap();
+        }
+
+        let mut scanner = NetlinkScanner {
+            root: root.path().to_path_buf(),
+            live,
+            stop,
+            worker: None,
+            maps_cache: MapsCache::default(),
+        };
+        assert_eq!(seen_pids(&scanner.scan(0, 1).unwrap()), [42]);
+
+        add_process(root.path(), 43, "/usr/bin/started");
+        assert_eq!(seen_pids(&scanner.scan(1, 2).unwrap()), [42, 43]);
+    }
+}
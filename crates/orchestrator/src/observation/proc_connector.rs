@@ -0,0 +1,186 @@
+#![forbid(unsafe_code)]
+
+//! Wire format of the kernel process events connector (`cn_proc`).
+//!
+//! Messages are a `nlmsghdr`, followed by a `cn_msg`, followed by either a
+//! subscription opcode (outgoing) or a `proc_event` (incoming). All fields are
+//! in native byte order.
+
+/// Connector index (and multicast group) of the process events connector.
+pub(crate) const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_NOOP: u16 = 1;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const CN_MSG_HDRLEN: usize = 20;
+
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_CN_MCAST_IGNORE: u32 = 2;
+
+/// `what`, `cpu` and `timestamp_ns` precede the event payload.
+const PROC_EVENT_HDRLEN: usize = 16;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProcEvent {
+    Exec { pid: u32, tgid: u32 },
+    Exit { pid: u32, tgid: u32 },
+}
+
+/// Build the message that starts (`listen = true`) or stops event delivery.
+pub(crate) fn subscribe_message(listen: bool) -> Vec<u8> {
+    let op = if listen {
+        PROC_CN_MCAST_LISTEN
+    } else {
+        PROC_CN_MCAST_IGNORE
+    };
+    let payload = op.to_ne_bytes();
+    let total = NLMSG_HDRLEN + CN_MSG_HDRLEN + payload.len();
+
+    let mut buf = Vec::with_capacity(total);
+    // nlmsghdr
+    buf.extend_from_slice(&(total as u32).to_ne_bytes());
+    buf.extend_from_slice(&NLMSG_DONE.to_ne_bytes());
+    buf.extend_from_slice(&0u16.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    // cn_msg
+    buf.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    buf.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(&(payload.len() as u16).to_ne_bytes());
+    buf.extend_from_slice(&0u16.to_ne_bytes());
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+/// Decode every exec/exit event contained in a received datagram.
+///
+/// Truncated or foreign messages are skipped; other event kinds (fork, uid
+/// changes, ...) are ignored.
+pub(crate) fn parse_events(buf: &[u8]) -> Vec<ProcEvent> {
+    let mut events = Vec::new();
+    let mut offset = 0;
+
+    while offset + NLMSG_HDRLEN <= buf.len() {
+        let Some(len) = read_u32(buf, offset).map(|len| len as usize) else {
+            break;
+        };
+        if len < NLMSG_HDRLEN || offset + len > buf.len() {
+            break;
+        }
+        let kind = read_u16(buf, offset + 4).unwrap_or(NLMSG_NOOP);
+        if kind != NLMSG_NOOP && kind != NLMSG_ERROR {
+            let message = &buf[offset + NLMSG_HDRLEN..offset + len];
+            if let Some(event) = parse_cn_msg(message) {
+                events.push(event);
+            }
+        }
+        offset += len.next_multiple_of(4);
+    }
+
+    events
+}
+
+fn parse_cn_msg(message: &[u8]) -> Option<ProcEvent> {
+    if read_u32(message, 0)? != CN_IDX_PROC || read_u32(message, 4)? != CN_VAL_PROC {
+        return None;
+    }
+    let data_len = read_u16(message, 16)? as usize;
+    let data = message.get(CN_MSG_HDRLEN..CN_MSG_HDRLEN + data_len)?;
+
+    let what = read_u32(data, 0)?;
+    let pid = read_u32(data, PROC_EVENT_HDRLEN)?;
+    let tgid = read_u32(data, PROC_EVENT_HDRLEN + 4)?;
+    match what {
+        PROC_EVENT_EXEC => Some(ProcEvent::Exec { pid, tgid }),
+        PROC_EVENT_EXIT => Some(ProcEvent::Exit { pid, tgid }),
+        _ => None,
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buf.get(offset..offset + 2)?;
+    Some(u16::from_ne_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn encode_event(what: u32, pid: u32, tgid: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&what.to_ne_bytes());
+        data.extend_from_slice(&0u32.to_ne_bytes());
+        data.extend_from_slice(&0u64.to_ne_bytes());
+        data.extend_from_slice(&pid.to_ne_bytes());
+        data.extend_from_slice(&tgid.to_ne_bytes());
+        // exit_code/exit_signal/parent ids are present on exit events.
+        data.extend_from_slice(&[0u8; 16]);
+
+        let total = NLMSG_HDRLEN + CN_MSG_HDRLEN + data.len();
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(total as u32).to_ne_bytes());
+        buf.extend_from_slice(&NLMSG_DONE.to_ne_bytes());
+        buf.extend_from_slice(&[0u8; 10]);
+        buf.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        buf.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        buf.extend_from_slice(&[0u8; 8]);
+        buf.extend_from_slice(&(data.len() as u16).to_ne_bytes());
+        buf.extend_from_slice(&0u16.to_ne_bytes());
+        buf.extend_from_slice(&data);
+        buf
+    }
+
+    #[test]
+    fn subscribe_message_has_consistent_lengths() {
+        let msg = subscribe_message(true);
+        assert_eq!(read_u32(&msg, 0), Some(msg.len() as u32));
+        assert_eq!(read_u16(&msg, NLMSG_HDRLEN + 16), Some(4));
+        assert_eq!(
+            read_u32(&msg, NLMSG_HDRLEN + CN_MSG_HDRLEN),
+            Some(PROC_CN_MCAST_LISTEN)
+        );
+        assert_eq!(
+            read_u32(&subscribe_message(false), NLMSG_HDRLEN + CN_MSG_HDRLEN),
+            Some(PROC_CN_MCAST_IGNORE)
+        );
+    }
+
+    #[test]
+    fn ignores_foreign_and_truncated_messages() {
+        let mut fork = encode_event(0x1, 10, 10);
+        fork.extend_from_slice(&encode_event(PROC_EVENT_EXEC, 11, 11)[..20]);
+        assert!(parse_events(&fork).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn parse_decodes_encoded_events(
+            raw in prop::collection::vec((any::<bool>(), 1u32..1_000_000, 1u32..1_000_000), 0..16),
+        ) {
+            let mut buf = Vec::new();
+            let mut expected = Vec::new();
+            for (exec, pid, tgid) in raw {
+                let what = if exec { PROC_EVENT_EXEC } else { PROC_EVENT_EXIT };
+                buf.extend_from_slice(&encode_event(what, pid, tgid));
+                expected.push(if exec {
+                    ProcEvent::Exec { pid, tgid }
+                } else {
+                    ProcEvent::Exit { pid, tgid }
+                });
+            }
+            prop_assert_eq!(parse_events(&buf), expected);
+        }
+    }
+}
@@ -1,44 +1,120 @@
 #![forbid(unsafe_code)]
 
-use crate::domain::{MapSegment, MemStat};
+use crate::domain::{FileIdentity, MapSegment, MemStat};
 use crate::error::Error;
+use crate::observation::maps_cache::MapsCache;
 use crate::observation::{Observation, ObservationEvent, ScanWarning, Scanner};
-use procfs::process::MMapPath;
-use procfs::{Current, Meminfo, page_size, vmstat};
+use procfs::process::Process;
+use procfs::{FromRead, Meminfo, VmStat, page_size};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use tracing::{trace, warn};
 
-#[derive(Debug, Default)]
-pub struct ProcfsScanner;
+/// Mount point of the live proc filesystem.
+pub(crate) const DEFAULT_PROC_ROOT: &str = "/proc";
+
+/// Scanner that walks `/proc` once per cycle.
+///
+/// Parsed maps are cached per process, so the cost of a scan follows process
+/// churn rather than the number of running processes.
+#[derive(Debug)]
+pub struct ProcfsScanner {
+    root: PathBuf,
+    /// Only processes owned by this uid are reported.
+    owner: Option<u32>,
+    maps_cache: MapsCache,
+}
+
+impl Default for ProcfsScanner {
+    fn default() -> Self {
+        Self::with_root(DEFAULT_PROC_ROOT)
+    }
+}
 
 impl ProcfsScanner {
-    fn sanitize_path(path: &Path) -> Option<PathBuf> {
+    /// Create a scanner that reads processes and memory stats below `root`
+    /// instead of the live `/proc`.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            owner: None,
+            maps_cache: MapsCache::default(),
+        }
+    }
+
+    /// Report only processes owned by `uid`.
+    pub fn with_owner(mut self, uid: u32) -> Self {
+        self.owner = Some(uid);
+        self
+    }
+
+    /// The proc filesystem root this scanner reads from.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn sanitize_path(path: &Path) -> Option<PathBuf> {
         if !path.has_root() {
             return None;
         }
-        let path_str = path.to_str()?;
-        if path_str.contains("(deleted)") {
+        // Work on bytes; file names need not be UTF-8.
+        let bytes = path.as_os_str().as_bytes();
+        if find(bytes, b"(deleted)").is_some() {
             return None;
         }
-        let trimmed = path_str.split(".#prelink#.").next()?;
-        Some(PathBuf::from(trimmed))
+        let trimmed = match find(bytes, b".#prelink#.") {
+            Some(end) => &bytes[..end],
+            None => bytes,
+        };
+        Some(PathBuf::from(OsStr::from_bytes(trimmed)))
     }
 
-    fn read_memstat() -> Result<MemStat, Error> {
-        let mem = Meminfo::current()?;
-        let vm = vmstat()?;
+    pub(crate) fn read_memstat(root: &Path) -> Result<MemStat, Error> {
+        let mem = Meminfo::from_file(root.join("meminfo"))?;
+        let vm = VmStat::from_file(root.join("vmstat"))?.0;
         let page = page_size() as i64;
         let pagein = vm.get("pgpgin").map(|v| v * page / 1024).unwrap_or(0);
         let pageout = vm.get("pgpgout").map(|v| v * page / 1024).unwrap_or(0);
 
+        // procfs reports bytes; MemStat is in kB like the kernel's meminfo.
         Ok(MemStat {
-            total: mem.mem_total,
-            free: mem.mem_free,
-            cached: mem.cached,
+            total: mem.mem_total / 1024,
+            free: mem.mem_free / 1024,
+            cached: mem.cached / 1024,
             pagein,
             pageout,
         })
     }
+
+    /// Read the sanitized, file-backed map segments of a process.
+    ///
+    /// `/proc/<pid>/maps` is parsed as bytes: procfs' own parser rejects the
+    /// whole file when a single mapped path is not UTF-8.
+    pub(crate) fn read_maps(process: &Process, time: u64) -> procfs::ProcResult<Vec<MapSegment>> {
+        let mut contents = Vec::new();
+        process.open_relative("maps")?.read_to_end(&mut contents)?;
+
+        let mut segments = Vec::new();
+        // A library usually has several segments; stat it once.
+        let mut identities: HashMap<PathBuf, Option<FileIdentity>> = HashMap::new();
+        for line in contents.split(|&byte| byte == b'\n') {
+            let Some((start, end, offset, path)) = parse_maps_line(line) else {
+                continue;
+            };
+            let Some(path) = Self::sanitize_path(Path::new(OsStr::from_bytes(path))) else {
+                continue;
+            };
+            let length = end.saturating_sub(start);
+            let identity = *identities
+                .entry(path.clone())
+                .or_insert_with(|| FileIdentity::of(&path).ok());
+            segments.push(MapSegment::new(path, offset, length, time).with_identity(identity));
+        }
+        Ok(segments)
+    }
 }
 
 impl Scanner for ProcfsScanner {
@@ -46,8 +122,9 @@ impl Scanner for ProcfsScanner {
         let mut events = Vec::new();
         let mut warnings = Vec::new();
         events.push(ObservationEvent::ObsBegin { time, scan_id });
+        self.maps_cache.begin_scan();
 
-        for process in procfs::process::all_processes()? {
+        for process in procfs::process::all_processes_with_root(&self.root)? {
             let process = match process {
                 Ok(p) => p,
                 Err(err) => {
@@ -55,6 +132,11 @@ impl Scanner for ProcfsScanner {
                     continue;
                 }
             };
+            if let Some(owner) = self.owner
+                && process.uid().ok() != Some(owner)
+            {
+                continue;
+            }
             let pid = process.pid as u32;
             let exe_path = match process.exe() {
                 Ok(path) => path,
@@ -72,21 +154,12 @@ impl Scanner for ProcfsScanner {
                 pid,
             });
 
-            match process.maps() {
+            match self.maps_cache.maps(&process, &exe_path, time) {
                 Ok(maps) => {
                     for map in maps {
-                        let MMapPath::Path(path) = map.pathname else {
-                            continue;
-                        };
-                        let Some(path) = Self::sanitize_path(&path) else {
-                            continue;
-                        };
-                        let (start, end) = map.address;
-                        let length = end.saturating_sub(start);
-                        let segment = MapSegment::new(path, map.offset, length, time);
                         events.push(ObservationEvent::MapSeen {
                             exe_path: exe_path.clone(),
-                            map: segment,
+                            map,
                         });
                     }
                 }
@@ -99,7 +172,9 @@ impl Scanner for ProcfsScanner {
             }
         }
 
-        if let Ok(mem) = Self::read_memstat() {
+        let (cached, reparsed) = self.maps_cache.finish_scan();
+
+        if let Ok(mem) = Self::read_memstat(&self.root) {
             events.push(ObservationEvent::MemStat { mem });
         }
 
@@ -109,7 +184,76 @@ impl Scanner for ProcfsScanner {
             warnings,
         });
 
-        trace!(scan_id, event_count = events.len(), "observation collected");
+        trace!(
+            scan_id,
+            event_count = events.len(),
+            cached,
+            reparsed,
+            "observation collected"
+        );
         Ok(events)
     }
 }
+
+/// Split a `/proc/<pid>/maps` line into start and end address, file offset
+/// and path. The path is empty for anonymous mappings and may contain spaces.
+fn parse_maps_line(line: &[u8]) -> Option<(u64, u64, u64, &[u8])> {
+    // address perms offset dev inode path
+    let mut fields = [&[][..]; 5];
+    let mut rest = line;
+    for field in &mut fields {
+        rest = rest.trim_ascii_start();
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b' ')
+            .unwrap_or(rest.len());
+        (*field, rest) = rest.split_at(end);
+    }
+    let [address, _, offset, _, _] = fields;
+    let hex = |field: &[u8]| u64::from_str_radix(std::str::from_utf8(field).ok()?, 16).ok();
+    let dash = address.iter().position(|&byte| byte == b'-')?;
+    Some((
+        hex(&address[..dash])?,
+        hex(&address[dash + 1..])?,
+        hex(offset)?,
+        rest.trim_ascii_start(),
+    ))
+}
+
+/// Position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_lines_keep_the_path_bytes() {
+        let line = b"7f00-7f80 r-xp 00001000 fe:00 1234      /opt/my app/lib\xff.so";
+        assert_eq!(
+            parse_maps_line(line),
+            Some((0x7f00, 0x7f80, 0x1000, &b"/opt/my app/lib\xff.so"[..]))
+        );
+        let anon = b"7f00-7f80 rw-p 00000000 00:00 0 ";
+        assert_eq!(parse_maps_line(anon), Some((0x7f00, 0x7f80, 0, &b""[..])));
+        assert_eq!(parse_maps_line(b""), None);
+    }
+
+    #[test]
+    fn sanitize_keeps_non_utf8_bytes() {
+        let raw = |bytes: &[u8]| PathBuf::from(OsStr::from_bytes(bytes));
+        assert_eq!(
+            ProcfsScanner::sanitize_path(&raw(b"/opt/caf\xe9/lib.so.#prelink#.12345")),
+            Some(raw(b"/opt/caf\xe9/lib.so"))
+        );
+        assert_eq!(
+            ProcfsScanner::sanitize_path(&raw(b"/tmp/\xff (deleted)")),
+            None
+        );
+        assert_eq!(ProcfsScanner::sanitize_path(Path::new("lib.so")), None);
+    }
+}
@@ -0,0 +1,288 @@
+#![forbid(unsafe_code)]
+
+//! Observation trace files.
+//!
+//! A trace is a JSON-lines file: a [`TraceHeader`] on the first line, then
+//! one [`Observation`] per line in scan order. Every line is flushed as it is
+//! written so a trace cut short by a crash is still readable up to the last
+//! complete tick.
+//!
+//! Paths are written as text via [`config::encode_path`]: `%` becomes `%25`
+//! and bytes that are not UTF-8 become `%XX`, so any file name round-trips.
+
+use crate::error::Error;
+use crate::observation::Observation;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value of [`TraceHeader::format`] identifying a preload-rs trace.
+pub const TRACE_FORMAT: &str = "preload-rs-trace";
+
+/// Trace format version written by this build.
+pub const TRACE_VERSION: u32 = 1;
+
+/// First line of every trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceHeader {
+    pub format: String,
+    pub version: u32,
+    /// Wall-clock creation time, in seconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+impl TraceHeader {
+    fn current() -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            format: TRACE_FORMAT.to_string(),
+            version: TRACE_VERSION,
+            created_at,
+        }
+    }
+}
+
+/// Appends observations to a trace.
+#[derive(Debug)]
+pub struct TraceWriter<W: Write = BufWriter<File>> {
+    out: W,
+}
+
+impl TraceWriter {
+    /// Create (or truncate) the trace file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Start a trace on `out` by writing its header.
+    pub fn new(out: W) -> Result<Self, Error> {
+        let mut writer = Self { out };
+        writer.write_line(&TraceHeader::current())?;
+        Ok(writer)
+    }
+
+    /// Append one observation.
+    pub fn write(&mut self, observation: &Observation) -> Result<(), Error> {
+        self.write_line(observation)
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.out, value)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads observations back from a trace, one per iteration.
+#[derive(Debug)]
+pub struct TraceReader<R: BufRead = BufReader<File>> {
+    lines: Lines<R>,
+    header: TraceHeader,
+    line: usize,
+}
+
+impl TraceReader {
+    /// Open the trace file at `path` and validate its header.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /// Read and validate the header from `input`.
+    pub fn new(input: R) -> Result<Self, Error> {
+        let mut lines = input.lines();
+        let first = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| Error::InvalidTrace("empty trace".into()))?;
+        let header: TraceHeader = serde_json::from_str(&first)
+            .map_err(|err| Error::InvalidTrace(format!("bad header: {err}")))?;
+
+        if header.format != TRACE_FORMAT {
+            return Err(Error::InvalidTrace(format!(
+                "unknown format {:?}",
+                header.format
+            )));
+        }
+        if header.version == 0 || header.version > TRACE_VERSION {
+            return Err(Error::UnsupportedTraceVersion {
+                found: header.version,
+                supported: TRACE_VERSION,
+            });
+        }
+
+        Ok(Self {
+            lines,
+            header,
+            line: 1,
+        })
+    }
+
+    pub fn header(&self) -> &TraceHeader {
+        &self.header
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<Observation, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .map_err(|err| Error::InvalidTrace(format!("line {}: {err}", self.line))),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MapSegment, MemStat};
+    use crate::observation::{ObservationEvent, ScanWarning};
+    use proptest::prelude::*;
+    use std::path::PathBuf;
+
+    fn observation_strategy() -> impl Strategy<Value = Observation> {
+        let exe = (0u8..8, 1u32..100_000).prop_map(|(n, pid)| ObservationEvent::ExeSeen {
+            path: PathBuf::from(format!("/usr/bin/app{n}")),
+            pid,
+        });
+        let map = (0u8..8, 0u64..1 << 20, 1u64..1 << 24).prop_map(|(n, offset, length)| {
+            ObservationEvent::MapSeen {
+                exe_path: PathBuf::from(format!("/usr/bin/app{n}")),
+                map: MapSegment::new(format!("/usr/lib/lib{n}.so"), offset, length, 7),
+            }
+        });
+        let mem =
+            (any::<u32>(), any::<i32>()).prop_map(|(total, pagein)| ObservationEvent::MemStat {
+                mem: MemStat {
+                    total: total.into(),
+                    free: 0,
+                    cached: 0,
+                    pagein: pagein.into(),
+                    pageout: 0,
+                },
+            });
+        (
+            any::<u64>(),
+            prop::collection::vec(prop_oneof![exe, map, mem], 0..16),
+            prop::collection::vec((1u32..100_000, "[a-z ]{0,12}"), 0..3),
+        )
+            .prop_map(|(time, body, warnings)| {
+                let mut events = vec![ObservationEvent::ObsBegin { time, scan_id: 1 }];
+                events.extend(body);
+                events.push(ObservationEvent::ObsEnd {
+                    time,
+                    scan_id: 1,
+                    warnings: warnings
+                        .into_iter()
+                        .map(|(pid, reason)| ScanWarning::MapScanFailed { pid, reason })
+                        .collect(),
+                });
+                events
+            })
+    }
+
+    #[test]
+    fn rejects_foreign_and_future_traces() {
+        assert!(matches!(
+            TraceReader::new(&b""[..]),
+            Err(Error::InvalidTrace(_))
+        ));
+        assert!(matches!(
+            TraceReader::new(&br#"{"format":"other","version":1,"created_at":0}"#[..]),
+            Err(Error::InvalidTrace(_))
+        ));
+        let future = format!(
+            r#"{{"format":"{TRACE_FORMAT}","version":{},"created_at":0}}"#,
+            TRACE_VERSION + 1
+        );
+        assert!(matches!(
+            TraceReader::new(future.as_bytes()),
+            Err(Error::UnsupportedTraceVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_corrupt_observation() {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.write(&Vec::new()).unwrap();
+        let mut bytes = writer.into_inner().unwrap();
+        bytes.extend_from_slice(b"\n{not json\n");
+
+        let mut reader = TraceReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next().unwrap().unwrap().is_empty());
+        let err = reader.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("line 4"), "{err}");
+    }
+
+    #[test]
+    fn non_utf8_paths_read_back_unchanged() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let exe = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/100%/app"));
+        let observation = vec![
+            ObservationEvent::ExeSeen {
+                path: exe.clone(),
+                pid: 42,
+            },
+            ObservationEvent::MapSeen {
+                exe_path: exe,
+                map: MapSegment::new(OsStr::from_bytes(b"/opt/lib\xff.so"), 0, 4096, 7),
+            },
+        ];
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.write(&observation).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let read: Vec<Observation> = TraceReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, vec![observation]);
+    }
+
+    proptest! {
+        #[test]
+        fn written_observations_read_back_unchanged(
+            observations in prop::collection::vec(observation_strategy(), 0..8),
+        ) {
+            let mut writer = TraceWriter::new(Vec::new()).unwrap();
+            for observation in &observations {
+                writer.write(observation).unwrap();
+            }
+            let bytes = writer.into_inner().unwrap();
+
+            let reader = TraceReader::new(bytes.as_slice()).unwrap();
+            prop_assert_eq!(reader.header().version, TRACE_VERSION);
+            let read: Vec<Observation> = reader.collect::<Result<_, _>>().unwrap();
+            prop_assert_eq!(read, observations);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+#![forbid(unsafe_code)]
+
+use crate::error::Error;
+use crate::observation::trace::{TraceReader, TraceWriter};
+use crate::observation::{Observation, ObservationEvent, Scanner};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use tracing::trace;
+
+/// Scanner wrapper that writes every observation of the inner scanner to a
+/// trace before handing it to the engine.
+pub struct RecordingScanner<W: Write = BufWriter<File>> {
+    inner: Box<dyn Scanner + Send + Sync>,
+    writer: TraceWriter<W>,
+}
+
+impl RecordingScanner {
+    /// Record the observations of `inner` into a new trace file at `path`.
+    pub fn create(
+        inner: Box<dyn Scanner + Send + Sync>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        Ok(Self::new(inner, TraceWriter::create(path)?))
+    }
+}
+
+impl<W: Write> RecordingScanner<W> {
+    pub fn new(inner: Box<dyn Scanner + Send + Sync>, writer: TraceWriter<W>) -> Self {
+        Self { inner, writer }
+    }
+}
+
+impl<W: Write + Send + Sync> Scanner for RecordingScanner<W> {
+    fn scan(&mut self, time: u64, scan_id: u64) -> Result<Observation, Error> {
+        let observation = self.inner.scan(time, scan_id)?;
+        self.writer.write(&observation)?;
+        Ok(observation)
+    }
+}
+
+/// Scanner that feeds a recorded trace back, one observation per tick.
+///
+/// Recorded times and scan ids are rewritten to the values the engine asks
+/// for, so a trace can be replayed on top of any state. Once the trace is
+/// exhausted every scan fails with [`Error::TraceExhausted`].
+#[derive(Debug)]
+pub struct ReplayScanner<R: BufRead = BufReader<File>> {
+    reader: TraceReader<R>,
+    replayed: u64,
+}
+
+impl ReplayScanner {
+    /// Open the trace file at `path` for replay.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(TraceReader::open(path)?))
+    }
+}
+
+impl<R: BufRead> ReplayScanner<R> {
+    pub fn new(reader: TraceReader<R>) -> Self {
+        Self {
+            reader,
+            replayed: 0,
+        }
+    }
+
+    /// Number of observations replayed so far.
+    pub fn replayed(&self) -> u64 {
+        self.replayed
+    }
+}
+
+impl<R: BufRead + Send + Sync> Scanner for ReplayScanner<R> {
+    fn scan(&mut self, time: u64, scan_id: u64) -> Result<Observation, Error> {
+        let mut observation = self.reader.next().ok_or(Error::TraceExhausted)??;
+        for event in &mut observation {
+            match event {
+                ObservationEvent::ObsBegin {
+                    time: t,
+                    scan_id: id,
+                }
+                | ObservationEvent::ObsEnd {
+                    time: t,
+                    scan_id: id,
+                    ..
+                } => {
+                    *t = time;
+                    *id = scan_id;
+                }
+                ObservationEvent::MapSeen { map, .. } => map.update_time = time,
+                ObservationEvent::ExeSeen { .. } | ObservationEvent::MemStat { .. } => {}
+            }
+        }
+        self.replayed += 1;
+        trace!(
+            scan_id,
+            replayed = self.replayed,
+            event_count = observation.len(),
+            "observation replayed"
+        );
+        Ok(observation)
+    }
+}
@@ -0,0 +1,250 @@
+#![forbid(unsafe_code)]
+
+//! Rotated copies of a state file and recovery of SQLite databases from them.
+//!
+//! Generation 1 (`state.db.1`) is the newest copy. Copies are complete
+//! state files (for SQLite, databases written with `VACUUM INTO`), so any of
+//! them can replace the state file as is. Saves refresh them on a
+//! [`Schedule`], whether the save was full or incremental.
+
+use crate::error::Error;
+use crate::persistence::file::MAGIC;
+use sqlx::error::DatabaseError;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// When saves refresh the backups: the first save, then the first one at
+/// least `interval` after the previous backup. Clones of a repository share
+/// it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Schedule {
+    interval: Duration,
+    last: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Schedule {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Arc::default(),
+        }
+    }
+
+    /// Whether a save now should also refresh the backups.
+    pub(crate) fn is_due(&self) -> bool {
+        let last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        last.is_none_or(|at| at.elapsed() >= self.interval)
+    }
+
+    /// Record a backup written now.
+    pub(crate) fn done(&self) {
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    }
+}
+
+/// Path of backup `generation` of `path`.
+pub(crate) fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    sibling(path, &format!(".{generation}"))
+}
+
+/// Existing backups of `path`, newest first.
+pub(crate) fn existing_backups(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(file)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut prefix = file.to_os_string();
+    prefix.push(".");
+    let prefix = prefix.as_encoded_bytes();
+    let mut generations: Vec<usize> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            let suffix = name.as_encoded_bytes().strip_prefix(prefix)?;
+            std::str::from_utf8(suffix).ok()?.parse().ok()
+        })
+        .filter(|&generation| generation > 0)
+        .collect();
+    generations.sort_unstable();
+    generations
+        .into_iter()
+        .map(|generation| backup_path(path, generation))
+        .collect()
+}
+
+/// Shift backups up one generation, dropping those beyond `keep`, and return
+/// the now free path for generation 1.
+pub(crate) fn rotate(path: &Path, keep: usize) -> std::io::Result<PathBuf> {
+    for old in existing_backups(path)
+        .into_iter()
+        .skip(keep.saturating_sub(1))
+    {
+        std::fs::remove_file(old)?;
+    }
+    for generation in (1..keep).rev() {
+        let from = backup_path(path, generation);
+        if from.exists() {
+            std::fs::rename(&from, backup_path(path, generation + 1))?;
+        }
+    }
+    Ok(backup_path(path, 1))
+}
+
+/// Whether the database at `path` opens and passes SQLite's `quick_check`.
+pub(crate) async fn is_healthy(path: &Path) -> bool {
+    matches!(damage(path).await, Ok(None))
+}
+
+/// What is wrong with the database at `path`: `None` if it passes SQLite's
+/// `quick_check`, otherwise SQLite's report. Only damage to the file itself
+/// is reported this way; a file that cannot be read, is locked, or holds a
+/// `file` backend state is an error, since a backup would not help.
+pub(crate) async fn damage(path: &Path) -> Result<Option<String>, Error> {
+    let mut header = [0u8; MAGIC.len()];
+    let read = std::fs::File::open(path)?.read(&mut header)?;
+    if header[..read] == MAGIC[..] {
+        return Err(Error::InvalidSnapshot(format!(
+            "{} holds a `file` backend state, not a SQLite database",
+            path.display()
+        )));
+    }
+
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let checked = async {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        let report = sqlx::query_scalar::<_, String>("PRAGMA quick_check")
+            .fetch_one(&pool)
+            .await;
+        pool.close().await;
+        report
+    }
+    .await;
+    match checked {
+        Ok(report) if report == "ok" => Ok(None),
+        Ok(report) => Ok(Some(report)),
+        Err(sqlx::Error::Database(err)) if is_corruption(err.as_ref()) => {
+            Ok(Some(err.message().to_string()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Whether SQLite failed because the file is damaged or not a database.
+fn is_corruption(err: &dyn DatabaseError) -> bool {
+    const SQLITE_CORRUPT: i32 = 11;
+    const SQLITE_NOTADB: i32 = 26;
+    // Extended result codes keep the primary code in the low byte.
+    let code = err.code().and_then(|code| code.parse::<i32>().ok());
+    code.is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB))
+}
+
+/// Move a damaged state file aside as `<path>.corrupt-<unix time>` and put
+/// the newest healthy backup in its place. With no healthy backup the state
+/// starts empty.
+pub(crate) async fn recover(path: &Path) -> Result<(), Error> {
+    let aside = corrupt_path(path);
+    warn!(path = %path.display(), aside = %aside.display(), "state database is damaged; moving it aside");
+    std::fs::rename(path, &aside)?;
+    // The write-ahead log belongs to the damaged file.
+    for suffix in ["-wal", "-shm"] {
+        let journal = sibling(path, suffix);
+        if journal.exists() {
+            std::fs::rename(&journal, sibling(&aside, suffix))?;
+        }
+    }
+
+    for backup in existing_backups(path) {
+        if is_healthy(&backup).await {
+            std::fs::copy(&backup, path)?;
+            info!(backup = %backup.display(), "restored state from backup");
+            return Ok(());
+        }
+        warn!(backup = %backup.display(), "backup is damaged too; skipping");
+    }
+    warn!("no usable backup; starting with an empty state");
+    Ok(())
+}
+
+/// A name for setting damaged `path` aside that no earlier failure used.
+fn corrupt_path(path: &Path) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let mut aside = sibling(path, &format!(".corrupt-{now}"));
+    let mut attempt = 1;
+    while aside.exists() {
+        aside = sibling(path, &format!(".corrupt-{now}-{attempt}"));
+        attempt += 1;
+    }
+    aside
+}
+
+/// Delete `path`, its siblings with `suffixes` and all its backups. Files
+/// that are already gone are skipped.
+pub(crate) fn remove_all(path: &Path, suffixes: &[&str]) -> std::io::Result<()> {
+    let siblings = suffixes.iter().map(|suffix| sibling(path, suffix));
+    for file in std::iter::once(path.to_path_buf())
+        .chain(siblings)
+        .chain(existing_backups(path))
+    {
+        match std::fs::remove_file(&file) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name.
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_the_newest_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let unrelated = dir.path().join("state.db.corrupt");
+        std::fs::write(&unrelated, "x").unwrap();
+
+        for round in 0..4 {
+            let next = rotate(&path, 3).unwrap();
+            assert_eq!(next, backup_path(&path, 1));
+            std::fs::write(next, round.to_string()).unwrap();
+        }
+
+        let backups = existing_backups(&path);
+        assert_eq!(
+            backups,
+            [1, 2, 3].map(|generation| backup_path(&path, generation))
+        );
+        let contents: Vec<_> = backups
+            .iter()
+            .map(|backup| std::fs::read_to_string(backup).unwrap())
+            .collect();
+        assert_eq!(contents, ["3", "2", "1"]);
+        assert!(unrelated.exists());
+    }
+}
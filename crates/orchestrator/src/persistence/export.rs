@@ -0,0 +1,69 @@
+#![forbid(unsafe_code)]
+
+//! Portable JSON form of a [`StoresSnapshot`].
+//!
+//! An export is a single JSON document: `format` (always [`EXPORT_FORMAT`]),
+//! `meta` and `state`, with the fields of [`SnapshotMeta`] and
+//! [`StateSnapshot`]. Records are sorted by their keys so the same model
+//! always exports to the same bytes, which keeps exports diffable under
+//! version control. Paths are written via [`config::encode_path`] (`%` as
+//! `%25`, bytes that are not UTF-8 as `%XX`), so any file name round-trips.
+
+use crate::error::Error;
+use crate::persistence::{SnapshotMeta, StateSnapshot, StoresSnapshot, migrate_snapshot};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Value of the `format` field identifying a preload-rs model export.
+pub const EXPORT_FORMAT: &str = "preload-rs-model";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ModelExport {
+    format: String,
+    meta: SnapshotMeta,
+    state: StateSnapshot,
+}
+
+/// Write `snapshot` to `out` as pretty-printed JSON.
+pub fn export_json(snapshot: &StoresSnapshot, mut out: impl Write) -> Result<(), Error> {
+    let mut state = snapshot.state.clone();
+    state.exes.sort_by(|a, b| a.path.cmp(&b.path));
+    state
+        .maps
+        .sort_by(|a, b| (&a.path, a.offset, a.length).cmp(&(&b.path, b.offset, b.length)));
+    state
+        .exe_maps
+        .sort_by(|a, b| (&a.exe_path, &a.map_key).cmp(&(&b.exe_path, &b.map_key)));
+    state
+        .markov_edges
+        .sort_by(|a, b| (&a.exe_a, &a.exe_b).cmp(&(&b.exe_a, &b.exe_b)));
+
+    let export = ModelExport {
+        format: EXPORT_FORMAT.to_string(),
+        meta: snapshot.meta.clone(),
+        state,
+    };
+    serde_json::to_writer_pretty(&mut out, &export)?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Read an export written by [`export_json`], rejecting unknown formats,
+/// newer schemas and dangling references. Older schemas are upgraded.
+pub fn import_json(input: impl Read) -> Result<StoresSnapshot, Error> {
+    let export: ModelExport = serde_json::from_reader(input)?;
+    if export.format != EXPORT_FORMAT {
+        return Err(Error::InvalidSnapshot(format!(
+            "unknown format {:?}",
+            export.format
+        )));
+    }
+
+    let snapshot = migrate_snapshot(StoresSnapshot {
+        meta: export.meta,
+        state: export.state,
+    })?;
+    snapshot.validate()?;
+    Ok(snapshot)
+}
@@ -0,0 +1,513 @@
+#![forbid(unsafe_code)]
+
+//! A state backend that keeps the whole snapshot in one rkyv archive, for
+//! systems where SQLite is unwanted.
+//!
+//! The file is a 16-byte header followed by the archive:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 0..8  | [`MAGIC`]                                 |
+//! | 8..12 | snapshot schema version, little endian    |
+//! | 12..16| CRC-32 of the archive, little endian      |
+//!
+//! Saves write a temporary file next to the state and rename it into place,
+//! so a crash leaves either the old or the new snapshot. Loads check the
+//! checksum and validate the archive in place before reading records out of
+//! it.
+
+use crate::domain::{FileIdentity, MapKey, MarkovState};
+use crate::error::Error;
+use crate::persistence::backup::{self, sibling};
+use crate::persistence::lock::StateLock;
+use crate::persistence::migrate::check_schema_version;
+use crate::persistence::{
+    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
+    StateRepository, StateSnapshot, StoresSnapshot, migrate_snapshot,
+};
+use async_trait::async_trait;
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// First bytes of every state file.
+pub const MAGIC: &[u8; 8] = b"PRLDSNAP";
+
+const HEADER_LEN: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct FileRepository {
+    path: PathBuf,
+    /// Backups kept next to the state file.
+    backups: usize,
+    backup_schedule: backup::Schedule,
+    /// `None` when opened read-only.
+    lock: Option<Arc<StateLock>>,
+}
+
+impl FileRepository {
+    /// Create a repository that stores its snapshot at `path`. The file is
+    /// written by the first save; until then loads return an empty
+    /// snapshot.
+    ///
+    /// The repository holds the state's writer lock until it is dropped;
+    /// while another writer has it this fails with [`Error::StateLocked`].
+    pub fn new(path: PathBuf) -> Result<Self, Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let lock = StateLock::acquire(&path)?;
+        Ok(Self {
+            path,
+            backups: 0,
+            backup_schedule: backup::Schedule::default(),
+            lock: Some(Arc::new(lock)),
+        })
+    }
+
+    /// Keep `count` rotated copies of the state file, refreshed by the first
+    /// save and then at most once per backup interval.
+    pub fn with_backups(mut self, count: usize) -> Self {
+        self.backups = count;
+        self
+    }
+
+    /// Refresh the backups at most once per `interval`, by whichever save
+    /// comes first after it. Zero, the default, refreshes them at every
+    /// save.
+    pub fn with_backup_interval(mut self, interval: Duration) -> Self {
+        self.backup_schedule = backup::Schedule::new(interval);
+        self
+    }
+
+    /// Open an existing state file for loading only, without taking the
+    /// writer lock. Saving through this repository fails.
+    pub fn open_read_only(path: PathBuf) -> Result<Self, Error> {
+        if !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("state file not found: {}", path.display()),
+            )
+            .into());
+        }
+        Ok(Self {
+            path,
+            backups: 0,
+            backup_schedule: backup::Schedule::default(),
+            lock: None,
+        })
+    }
+
+    fn save_snapshot(&self, snapshot: &StoresSnapshot) -> Result<(), Error> {
+        if self.lock.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("state file opened read-only: {}", self.path.display()),
+            )
+            .into());
+        }
+        let contents = encode(snapshot)?;
+
+        let temp = sibling(&self.path, ".tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp, &self.path)?;
+        if let Some(parent) = self.path.parent() {
+            // Make the rename itself durable.
+            File::open(parent)?.sync_all()?;
+        }
+        debug!(path = %self.path.display(), bytes = contents.len(), "snapshot persisted");
+
+        // The snapshot itself is safe; a missing copy is not worth failing
+        // the save over.
+        if self.backups > 0 && self.backup_schedule.is_due() {
+            match self.write_backup(&contents) {
+                Ok(()) => self.backup_schedule.done(),
+                Err(err) => warn!(%err, path = %self.path.display(), "failed to back up state"),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_backup(&self, contents: &[u8]) -> Result<(), Error> {
+        let target = backup::rotate(&self.path, self.backups)?;
+        std::fs::write(&target, contents)?;
+        debug!(backup = %target.display(), "state backed up");
+        Ok(())
+    }
+
+    fn load_snapshot(&self) -> Result<StoresSnapshot, Error> {
+        match File::open(&self.path) {
+            Ok(mut file) => {
+                let mut contents = AlignedVec::<16>::new();
+                contents.extend_from_reader(&mut file)?;
+                decode(&contents)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && self.lock.is_some() => {
+                Ok(StoresSnapshot::empty())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Load the newest backup that reads back, for when the state file
+    /// itself does not.
+    fn load_backup(&self) -> Option<StoresSnapshot> {
+        for path in backup::existing_backups(&self.path) {
+            let loaded = Self::open_read_only(path.clone()).and_then(|repo| repo.load_snapshot());
+            match loaded {
+                Ok(snapshot) => {
+                    warn!(backup = %path.display(), "loaded state from backup");
+                    return Some(snapshot);
+                }
+                Err(err) => warn!(%err, backup = %path.display(), "backup is unreadable"),
+            }
+        }
+        None
+    }
+}
+
+#[async_trait]
+impl StateRepository for FileRepository {
+    async fn load(&self) -> Result<StoresSnapshot, Error> {
+        match self.load_snapshot() {
+            Ok(snapshot) => Ok(snapshot),
+            // Backups come from the same or an older build.
+            Err(err @ Error::UnsupportedSnapshotVersion { .. }) => Err(err),
+            Err(err) => {
+                warn!(%err, path = %self.path.display(), "state is unreadable; trying backups");
+                self.load_backup().ok_or(err)
+            }
+        }
+    }
+
+    async fn save(&self, snapshot: &StoresSnapshot) -> Result<(), Error> {
+        self.save_snapshot(snapshot)
+    }
+
+    async fn remove(&self) -> Result<(), Error> {
+        if self.lock.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("state file opened read-only: {}", self.path.display()),
+            )
+            .into());
+        }
+        backup::remove_all(&self.path, &[".tmp", ".lock"])?;
+        debug!(path = %self.path.display(), "state removed");
+        Ok(())
+    }
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredSnapshot {
+    app_version: Option<String>,
+    created_at: Option<u64>,
+    model_time: u64,
+    last_accounting_time: u64,
+    exes: Vec<StoredExe>,
+    maps: Vec<StoredMap>,
+    exe_maps: Vec<StoredExeMap>,
+    markov_edges: Vec<StoredEdge>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredExe {
+    path: Vec<u8>,
+    total_running_time: u64,
+    last_seen_time: Option<u64>,
+    running: bool,
+    change_time: u64,
+    identity: Option<String>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredMap {
+    path: Vec<u8>,
+    offset: u64,
+    length: u64,
+    update_time: u64,
+    identity: Option<StoredFileIdentity>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredFileIdentity {
+    device: u64,
+    inode: u64,
+    mtime_ns: i64,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredExeMap {
+    exe_path: Vec<u8>,
+    map_path: Vec<u8>,
+    map_offset: u64,
+    map_length: u64,
+    prob: f32,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct StoredEdge {
+    exe_a: Vec<u8>,
+    exe_b: Vec<u8>,
+    state: u8,
+    last_change_time: u64,
+    state_last_left: [u64; 4],
+    time_to_leave: [f32; 4],
+    transition_prob: [[f32; 4]; 4],
+    both_running_time: u64,
+}
+
+/// Header and archive for `snapshot`.
+fn encode(snapshot: &StoresSnapshot) -> Result<Vec<u8>, Error> {
+    let meta = &snapshot.meta;
+    let state = &snapshot.state;
+    let bytes = |path: &Path| path.as_os_str().as_bytes().to_vec();
+    let stored = StoredSnapshot {
+        app_version: meta.app_version.clone(),
+        created_at: meta
+            .created_at
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        model_time: state.model_time,
+        last_accounting_time: state.last_accounting_time,
+        exes: state
+            .exes
+            .iter()
+            .map(|exe| StoredExe {
+                path: bytes(&exe.path),
+                total_running_time: exe.total_running_time,
+                last_seen_time: exe.last_seen_time,
+                running: exe.running,
+                change_time: exe.change_time,
+                identity: exe.identity.as_ref().map(ToString::to_string),
+            })
+            .collect(),
+        maps: state
+            .maps
+            .iter()
+            .map(|map| StoredMap {
+                path: bytes(&map.path),
+                offset: map.offset,
+                length: map.length,
+                update_time: map.update_time,
+                identity: map.identity.map(|id| StoredFileIdentity {
+                    device: id.device,
+                    inode: id.inode,
+                    mtime_ns: id.mtime_ns,
+                }),
+            })
+            .collect(),
+        exe_maps: state
+            .exe_maps
+            .iter()
+            .map(|link| StoredExeMap {
+                exe_path: bytes(&link.exe_path),
+                map_path: bytes(&link.map_key.path),
+                map_offset: link.map_key.offset,
+                map_length: link.map_key.length,
+                prob: link.prob,
+            })
+            .collect(),
+        markov_edges: state
+            .markov_edges
+            .iter()
+            .map(|edge| StoredEdge {
+                exe_a: bytes(&edge.exe_a),
+                exe_b: bytes(&edge.exe_b),
+                state: edge.state.index() as u8,
+                last_change_time: edge.last_change_time,
+                state_last_left: edge.state_last_left,
+                time_to_leave: edge.time_to_leave,
+                transition_prob: edge.transition_prob,
+                both_running_time: edge.both_running_time,
+            })
+            .collect(),
+    };
+
+    let archive = rkyv::to_bytes::<rancor::Error>(&stored)
+        .map_err(|err| Error::RkyvSerialize(err.to_string()))?;
+    let mut contents = Vec::with_capacity(HEADER_LEN + archive.len());
+    contents.extend_from_slice(MAGIC);
+    // The archive is always in the current layout, whatever the snapshot
+    // was loaded from.
+    contents.extend_from_slice(&SNAPSHOT_SCHEMA_VERSION.to_le_bytes());
+    contents.extend_from_slice(&crc32fast::hash(&archive).to_le_bytes());
+    contents.extend_from_slice(&archive);
+    Ok(contents)
+}
+
+/// Check and read back what [`encode`] wrote. `contents` must be 16-byte
+/// aligned so the archive after the header can be accessed in place.
+fn decode(contents: &[u8]) -> Result<StoresSnapshot, Error> {
+    let invalid = |what: &str| Error::InvalidSnapshot(what.to_string());
+    if contents.len() < HEADER_LEN || !contents.starts_with(MAGIC) {
+        return Err(invalid("not a preload-rs state file"));
+    }
+    let (header, archive) = contents.split_at(HEADER_LEN);
+    let word = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+
+    let schema_version = word(8);
+    // Newer builds may lay the archive out differently.
+    check_schema_version(schema_version)?;
+    if crc32fast::hash(archive) != word(12) {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let stored = rkyv::access::<ArchivedStoredSnapshot, rancor::Error>(archive)
+        .map_err(|err| Error::RkyvDeserialize(err.to_string()))?;
+    let path = |bytes: &[u8]| PathBuf::from(OsStr::from_bytes(bytes));
+
+    let meta = SnapshotMeta {
+        schema_version,
+        app_version: stored.app_version.as_ref().map(|v| v.as_str().to_string()),
+        created_at: stored
+            .created_at
+            .as_ref()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs.to_native())),
+    };
+    let mut state = StateSnapshot {
+        model_time: stored.model_time.to_native(),
+        last_accounting_time: stored.last_accounting_time.to_native(),
+        exes: Vec::with_capacity(stored.exes.len()),
+        maps: Vec::with_capacity(stored.maps.len()),
+        exe_maps: Vec::with_capacity(stored.exe_maps.len()),
+        markov_edges: Vec::with_capacity(stored.markov_edges.len()),
+    };
+
+    for exe in stored.exes.iter() {
+        state.exes.push(ExeRecord {
+            path: path(&exe.path),
+            total_running_time: exe.total_running_time.to_native(),
+            last_seen_time: exe.last_seen_time.as_ref().map(|v| v.to_native()),
+            running: exe.running,
+            change_time: exe.change_time.to_native(),
+            identity: exe
+                .identity
+                .as_ref()
+                .map(|text| {
+                    text.as_str()
+                        .parse()
+                        .map_err(|_| invalid(&format!("unreadable exe identity {text:?}")))
+                })
+                .transpose()?,
+        });
+    }
+
+    for map in stored.maps.iter() {
+        state.maps.push(MapRecord {
+            path: path(&map.path),
+            offset: map.offset.to_native(),
+            length: map.length.to_native(),
+            update_time: map.update_time.to_native(),
+            identity: map.identity.as_ref().map(|id| FileIdentity {
+                device: id.device.to_native(),
+                inode: id.inode.to_native(),
+                mtime_ns: id.mtime_ns.to_native(),
+            }),
+        });
+    }
+
+    for link in stored.exe_maps.iter() {
+        state.exe_maps.push(ExeMapRecord {
+            exe_path: path(&link.exe_path),
+            map_key: MapKey::new(
+                path(&link.map_path),
+                link.map_offset.to_native(),
+                link.map_length.to_native(),
+            ),
+            prob: link.prob.to_native(),
+        });
+    }
+
+    for edge in stored.markov_edges.iter() {
+        let markov_state = MarkovState::from_index(edge.state as usize)
+            .ok_or_else(|| invalid("unknown markov state"))?;
+        state.markov_edges.push(MarkovRecord {
+            exe_a: path(&edge.exe_a),
+            exe_b: path(&edge.exe_b),
+            state: markov_state,
+            last_change_time: edge.last_change_time.to_native(),
+            state_last_left: edge.state_last_left.map(|v| v.to_native()),
+            time_to_leave: edge.time_to_leave.map(|v| v.to_native()),
+            transition_prob: edge.transition_prob.map(|row| row.map(|v| v.to_native())),
+            both_running_time: edge.both_running_time.to_native(),
+        });
+    }
+
+    migrate_snapshot(StoresSnapshot { meta, state })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aligned(bytes: &[u8]) -> AlignedVec<16> {
+        let mut contents = AlignedVec::<16>::new();
+        contents.extend_from_slice(bytes);
+        contents
+    }
+
+    #[test]
+    fn flipped_bits_fail_the_checksum() {
+        let mut contents = encode(&StoresSnapshot::empty()).unwrap();
+        decode(&aligned(&contents)).unwrap();
+
+        let last = contents.len() - 1;
+        contents[last] ^= 0x01;
+        let err = decode(&aligned(&contents)).unwrap_err();
+        assert!(matches!(err, Error::InvalidSnapshot(_)), "{err}");
+    }
+
+    #[test]
+    fn header_carries_the_current_schema_version() {
+        let mut snapshot = StoresSnapshot::empty();
+        snapshot.meta.schema_version = 1;
+        let contents = encode(&snapshot).unwrap();
+        assert_eq!(contents[8..12], SNAPSHOT_SCHEMA_VERSION.to_le_bytes());
+    }
+
+    #[test]
+    fn unreadable_identities_are_rejected() {
+        let stored = StoredSnapshot {
+            app_version: None,
+            created_at: None,
+            model_time: 0,
+            last_accounting_time: 0,
+            exes: vec![StoredExe {
+                path: b"/usr/bin/app".to_vec(),
+                total_running_time: 0,
+                last_seen_time: None,
+                running: false,
+                change_time: 0,
+                identity: Some("bogus".to_string()),
+            }],
+            maps: Vec::new(),
+            exe_maps: Vec::new(),
+            markov_edges: Vec::new(),
+        };
+        let archive = rkyv::to_bytes::<rancor::Error>(&stored).unwrap();
+        let mut contents = MAGIC.to_vec();
+        contents.extend_from_slice(&SNAPSHOT_SCHEMA_VERSION.to_le_bytes());
+        contents.extend_from_slice(&crc32fast::hash(&archive).to_le_bytes());
+        contents.extend_from_slice(&archive);
+
+        let err = decode(&aligned(&contents)).unwrap_err();
+        assert!(matches!(err, Error::InvalidSnapshot(_)), "{err}");
+    }
+
+    #[test]
+    fn foreign_files_are_rejected() {
+        let err = decode(&aligned(b"SQLite format 3\0")).unwrap_err();
+        assert!(matches!(err, Error::InvalidSnapshot(_)), "{err}");
+    }
+}
@@ -0,0 +1,466 @@
+#![forbid(unsafe_code)]
+
+use crate::persistence::StoresSnapshot;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+const MIB: f64 = 1024.0 * 1024.0;
+const STATES: [&str; 4] = ["neither", "a only", "b only", "both"];
+
+/// What to include in a [`StateReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InspectOptions {
+    /// Rows listed per section.
+    pub limit: usize,
+    /// Leading path components that make up a directory prefix, e.g. 2 groups
+    /// `/usr/lib/libc.so.6` under `/usr/lib`.
+    pub prefix_depth: usize,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self {
+            limit: 20,
+            prefix_depth: 2,
+        }
+    }
+}
+
+/// A human-oriented summary of a saved model.
+#[derive(Debug, Clone, Serialize)]
+pub struct StateReport {
+    pub schema_version: u32,
+    pub app_version: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub created_at: Option<u64>,
+    pub model_time: u64,
+    pub totals: Totals,
+    /// Longest running first.
+    pub exes: Vec<ExeSummary>,
+    /// Largest first.
+    pub maps: Vec<MapSummary>,
+    /// Longest time run together first.
+    pub edges: Vec<EdgeSummary>,
+    /// Largest mapped size first.
+    pub prefixes: Vec<PrefixSummary>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub exes: usize,
+    pub maps: usize,
+    pub map_bytes: u64,
+    pub exe_maps: usize,
+    pub edges: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExeSummary {
+    #[serde(with = "config::path_as_text")]
+    pub path: PathBuf,
+    pub total_running_time: u64,
+    pub last_seen_time: Option<u64>,
+    pub maps: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapSummary {
+    #[serde(with = "config::path_as_text")]
+    pub path: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+    /// Exes that map this region.
+    pub exes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EdgeSummary {
+    #[serde(with = "config::path_as_text")]
+    pub exe_a: PathBuf,
+    #[serde(with = "config::path_as_text")]
+    pub exe_b: PathBuf,
+    pub both_running_time: u64,
+    /// Mean seconds spent in each state, indexed neither, A only, B only,
+    /// both.
+    pub time_to_leave: [f32; 4],
+    /// `transition_prob[from][to]`, indexed like `time_to_leave`.
+    pub transition_prob: [[f32; 4]; 4],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PrefixSummary {
+    #[serde(with = "config::path_as_text")]
+    pub prefix: PathBuf,
+    pub exes: usize,
+    pub total_running_time: u64,
+    pub maps: usize,
+    pub map_bytes: u64,
+}
+
+impl StateReport {
+    pub fn new(snapshot: &StoresSnapshot, options: InspectOptions) -> Self {
+        let state = &snapshot.state;
+
+        let mut maps_per_exe: HashMap<&Path, usize> = HashMap::new();
+        let mut exes_per_map: HashMap<(&Path, u64, u64), usize> = HashMap::new();
+        for link in &state.exe_maps {
+            *maps_per_exe.entry(&link.exe_path).or_default() += 1;
+            let key = (
+                link.map_key.path.as_path(),
+                link.map_key.offset,
+                link.map_key.length,
+            );
+            *exes_per_map.entry(key).or_default() += 1;
+        }
+
+        let mut exes: Vec<ExeSummary> = state
+            .exes
+            .iter()
+            .map(|exe| ExeSummary {
+                path: exe.path.clone(),
+                total_running_time: exe.total_running_time,
+                last_seen_time: exe.last_seen_time,
+                maps: maps_per_exe.get(exe.path.as_path()).copied().unwrap_or(0),
+            })
+            .collect();
+        exes.sort_by_key(|exe| Reverse(exe.total_running_time));
+        exes.truncate(options.limit);
+
+        let mut maps: Vec<MapSummary> = state
+            .maps
+            .iter()
+            .map(|map| MapSummary {
+                path: map.path.clone(),
+                offset: map.offset,
+                length: map.length,
+                exes: exes_per_map
+                    .get(&(map.path.as_path(), map.offset, map.length))
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect();
+        maps.sort_by_key(|map| Reverse(map.length));
+        maps.truncate(options.limit);
+
+        let mut edges: Vec<EdgeSummary> = state
+            .markov_edges
+            .iter()
+            .map(|edge| EdgeSummary {
+                exe_a: edge.exe_a.clone(),
+                exe_b: edge.exe_b.clone(),
+                both_running_time: edge.both_running_time,
+                time_to_leave: edge.time_to_leave,
+                transition_prob: edge.transition_prob,
+            })
+            .collect();
+        edges.sort_by_key(|edge| Reverse(edge.both_running_time));
+        edges.truncate(options.limit);
+
+        let mut prefixes: BTreeMap<PathBuf, PrefixSummary> = BTreeMap::new();
+        let depth = options.prefix_depth;
+        for exe in &state.exes {
+            let totals = prefix_entry(&mut prefixes, &exe.path, depth);
+            totals.exes += 1;
+            totals.total_running_time += exe.total_running_time;
+        }
+        for map in &state.maps {
+            let totals = prefix_entry(&mut prefixes, &map.path, depth);
+            totals.maps += 1;
+            totals.map_bytes += map.length;
+        }
+        let mut prefixes: Vec<PrefixSummary> = prefixes.into_values().collect();
+        prefixes.sort_by_key(|prefix| Reverse(prefix.map_bytes));
+        prefixes.truncate(options.limit);
+
+        Self {
+            schema_version: snapshot.meta.schema_version,
+            app_version: snapshot.meta.app_version.clone(),
+            created_at: snapshot
+                .meta
+                .created_at
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            model_time: state.model_time,
+            totals: Totals {
+                exes: state.exes.len(),
+                maps: state.maps.len(),
+                map_bytes: state.maps.iter().map(|map| map.length).sum(),
+                exe_maps: state.exe_maps.len(),
+                edges: state.markov_edges.len(),
+            },
+            exes,
+            maps,
+            edges,
+            prefixes,
+        }
+    }
+}
+
+fn prefix_entry<'a>(
+    prefixes: &'a mut BTreeMap<PathBuf, PrefixSummary>,
+    path: &Path,
+    depth: usize,
+) -> &'a mut PrefixSummary {
+    let prefix = directory_prefix(path, depth);
+    prefixes
+        .entry(prefix.clone())
+        .or_insert_with(|| PrefixSummary {
+            prefix,
+            ..Default::default()
+        })
+}
+
+/// The first `depth` components below the root of the directory holding
+/// `path`.
+fn directory_prefix(path: &Path, depth: usize) -> PathBuf {
+    let dir = path.parent().unwrap_or(path);
+    let mut prefix = PathBuf::new();
+    let mut taken = 0;
+    for component in dir.components() {
+        if let Component::Normal(_) = component {
+            if taken == depth {
+                break;
+            }
+            taken += 1;
+        }
+        prefix.push(component);
+    }
+    prefix
+}
+
+impl fmt::Display for StateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |value: Option<u64>| match value {
+            Some(value) => value.to_string(),
+            None => "-".to_string(),
+        };
+
+        writeln!(
+            f,
+            "schema {}  app {}  saved at {}  model time {}s",
+            self.schema_version,
+            self.app_version.as_deref().unwrap_or("-"),
+            optional(self.created_at),
+            self.model_time
+        )?;
+        let totals = &self.totals;
+        writeln!(
+            f,
+            "{} exes, {} maps ({:.1} MiB), {} exe-map links, {} markov edges",
+            totals.exes,
+            totals.maps,
+            totals.map_bytes as f64 / MIB,
+            totals.exe_maps,
+            totals.edges
+        )?;
+
+        writeln!(f, "\nexes by running time:")?;
+        writeln!(
+            f,
+            "  {:>10}  {:>10}  {:>5}  path",
+            "running", "last seen", "maps"
+        )?;
+        for exe in &self.exes {
+            writeln!(
+                f,
+                "  {:>9}s  {:>10}  {:>5}  {}",
+                exe.total_running_time,
+                optional(exe.last_seen_time),
+                exe.maps,
+                exe.path.display()
+            )?;
+        }
+
+        writeln!(f, "\nmaps by size:")?;
+        writeln!(f, "  {:>10}  {:>5}  path [offset]", "MiB", "exes")?;
+        for map in &self.maps {
+            writeln!(
+                f,
+                "  {:>10.2}  {:>5}  {} [{}]",
+                map.length as f64 / MIB,
+                map.exes,
+                map.path.display(),
+                map.offset
+            )?;
+        }
+
+        writeln!(f, "\nmarkov edges by time run together:")?;
+        for edge in &self.edges {
+            writeln!(
+                f,
+                "  A {}\n  B {}\n    together {}s",
+                edge.exe_a.display(),
+                edge.exe_b.display(),
+                edge.both_running_time
+            )?;
+            write!(f, "    {:<8}", "state")?;
+            write!(f, "  {:>8}", "stay s")?;
+            for to in STATES {
+                write!(f, "  {:>8}", format!("->{to}"))?;
+            }
+            writeln!(f)?;
+            for (from, name) in STATES.iter().enumerate() {
+                write!(f, "    {name:<8}  {:>8.1}", edge.time_to_leave[from])?;
+                for prob in edge.transition_prob[from] {
+                    write!(f, "  {prob:>8.3}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f, "\ndirectory prefixes by mapped size:")?;
+        writeln!(
+            f,
+            "  {:>5}  {:>10}  {:>5}  {:>10}  prefix",
+            "exes", "running", "maps", "MiB"
+        )?;
+        for prefix in &self.prefixes {
+            writeln!(
+                f,
+                "  {:>5}  {:>9}s  {:>5}  {:>10.2}  {}",
+                prefix.exes,
+                prefix.total_running_time,
+                prefix.maps,
+                prefix.map_bytes as f64 / MIB,
+                prefix.prefix.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::MapKey;
+    use crate::domain::MarkovState;
+    use crate::persistence::{
+        ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
+        StateSnapshot,
+    };
+
+    fn exe(path: &str, running: u64) -> ExeRecord {
+        ExeRecord {
+            path: path.into(),
+            total_running_time: running,
+            last_seen_time: Some(running),
+            running: false,
+            change_time: 0,
+            identity: None,
+        }
+    }
+
+    fn map(path: &str, length: u64) -> MapRecord {
+        MapRecord {
+            path: path.into(),
+            offset: 0,
+            length,
+            update_time: 0,
+            identity: None,
+        }
+    }
+
+    fn edge(a: &str, b: &str, together: u64) -> MarkovRecord {
+        MarkovRecord {
+            exe_a: a.into(),
+            exe_b: b.into(),
+            state: MarkovState::Neither,
+            last_change_time: 0,
+            state_last_left: [0; 4],
+            time_to_leave: [1.0; 4],
+            transition_prob: [[0.25; 4]; 4],
+            both_running_time: together,
+        }
+    }
+
+    #[test]
+    fn report_orders_sections_and_groups_prefixes() {
+        let snapshot = StoresSnapshot {
+            meta: SnapshotMeta {
+                schema_version: SNAPSHOT_SCHEMA_VERSION,
+                app_version: None,
+                created_at: None,
+            },
+            state: StateSnapshot {
+                model_time: 100,
+                last_accounting_time: 100,
+                exes: vec![
+                    exe("/usr/bin/a", 10),
+                    exe("/usr/bin/b", 50),
+                    exe("/opt/c/bin/c", 30),
+                ],
+                maps: vec![
+                    map("/usr/lib/liba.so", 4096),
+                    map("/usr/lib/x86_64/libb.so", 8192),
+                    map("/opt/c/lib/libc.so", 1024),
+                ],
+                exe_maps: vec![
+                    ExeMapRecord {
+                        exe_path: "/usr/bin/a".into(),
+                        map_key: MapKey::new("/usr/lib/liba.so", 0, 4096),
+                        prob: 1.0,
+                    },
+                    ExeMapRecord {
+                        exe_path: "/usr/bin/b".into(),
+                        map_key: MapKey::new("/usr/lib/liba.so", 0, 4096),
+                        prob: 1.0,
+                    },
+                ],
+                markov_edges: vec![
+                    edge("/usr/bin/a", "/usr/bin/b", 5),
+                    edge("/usr/bin/b", "/opt/c/bin/c", 20),
+                ],
+            },
+        };
+
+        let report = StateReport::new(
+            &snapshot,
+            InspectOptions {
+                limit: 2,
+                prefix_depth: 2,
+            },
+        );
+
+        assert_eq!(report.totals.exes, 3);
+        assert_eq!(report.totals.map_bytes, 4096 + 8192 + 1024);
+        let exes: Vec<_> = report.exes.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(exes, [PathBuf::from("/usr/bin/b"), "/opt/c/bin/c".into()]);
+        assert_eq!(report.exes[0].maps, 1);
+        assert_eq!(report.maps[0].length, 8192);
+        assert_eq!(report.maps[1].exes, 2);
+        assert_eq!(report.edges[0].both_running_time, 20);
+
+        let usr_lib = &report.prefixes[0];
+        assert_eq!(usr_lib.prefix, PathBuf::from("/usr/lib"));
+        assert_eq!((usr_lib.maps, usr_lib.map_bytes), (2, 4096 + 8192));
+        // An exe and a map under the same prefix share one row.
+        let opt = &report.prefixes[1];
+        assert_eq!(opt.prefix, PathBuf::from("/opt/c"));
+        assert_eq!((opt.exes, opt.maps, opt.map_bytes), (1, 1, 1024));
+
+        // Every section renders, including the decoded matrices.
+        let text = report.to_string();
+        assert!(text.contains("->both"));
+        assert!(text.contains("/usr/lib"));
+    }
+
+    #[test]
+    fn prefixes_stop_at_the_requested_depth() {
+        assert_eq!(
+            directory_prefix(Path::new("/usr/lib/x86_64/libc.so"), 2),
+            PathBuf::from("/usr/lib")
+        );
+        assert_eq!(
+            directory_prefix(Path::new("/usr/libc.so"), 2),
+            PathBuf::from("/usr")
+        );
+        assert_eq!(
+            directory_prefix(Path::new("/usr/lib/libc.so"), 0),
+            PathBuf::from("/")
+        );
+    }
+}
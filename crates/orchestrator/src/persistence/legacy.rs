@@ -0,0 +1,336 @@
+#![forbid(unsafe_code)]
+
+//! Reader for the state file of the original C preload daemon
+//! (`/var/lib/preload/preload.state`).
+//!
+//! The file is line oriented with tab-separated fields:
+//!
+//! ```text
+//! PRELOAD <version> <time>
+//! MAP     <seq> <update_time> <offset> <length> <expansion> <uri>
+//! BADEXE  <update_time> <expansion> <uri>
+//! EXE     <seq> <update_time> <time> <expansion> <uri>
+//! EXEMAP  <exe seq> <map seq> <prob>
+//! MARKOV  <a seq> <b seq> <time> <time_to_leave x4> <weight x16>
+//! ```
+//!
+//! Paths are `file://` URIs. Markov weights count transitions: `weight[i][i]`
+//! is how often state `i` was left and `weight[i][j]` how often it moved to
+//! `j`, so `weight[i][j] / weight[i][i]` is the transition probability this
+//! crate stores. State indices agree: bit 0 is A running, bit 1 is B running.
+
+use crate::domain::{MapKey, MarkovState};
+use crate::error::Error;
+use crate::persistence::{
+    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
+    StateSnapshot, StoresSnapshot,
+};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::BufRead;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Where the C preload daemon keeps its state.
+pub const LEGACY_STATE_PATH: &str = "/var/lib/preload/preload.state";
+
+/// Convert a C preload state file into a snapshot.
+///
+/// Exes the daemon had blacklisted (`BADEXE`) have no counterpart and are
+/// skipped. Malformed lines and references to unknown sequence numbers are
+/// errors naming the offending line.
+pub fn import_legacy_state(input: impl BufRead) -> Result<StoresSnapshot, Error> {
+    let mut parser = Parser::default();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        parser
+            .line(&line)
+            .map_err(|message| Error::InvalidSnapshot(format!("line {}: {message}", index + 1)))?;
+    }
+
+    let Some(model_time) = parser.time else {
+        return Err(Error::InvalidSnapshot(
+            "missing PRELOAD header; not a preload state file".into(),
+        ));
+    };
+    debug!(
+        exes = parser.exes.len(),
+        maps = parser.maps.len(),
+        skipped_bad_exes = parser.bad_exes,
+        "parsed legacy preload state"
+    );
+
+    let snapshot = StoresSnapshot {
+        meta: SnapshotMeta {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            app_version: parser.version.map(|version| format!("preload {version}")),
+            created_at: None,
+        },
+        state: StateSnapshot {
+            model_time,
+            last_accounting_time: model_time,
+            exes: parser.exes.into_values().collect(),
+            maps: parser
+                .maps
+                .into_values()
+                .map(|key| MapRecord {
+                    update_time: parser.map_times.get(&key).copied().unwrap_or(0),
+                    path: key.path,
+                    offset: key.offset,
+                    length: key.length,
+                    identity: None,
+                })
+                .collect(),
+            exe_maps: parser.exe_maps,
+            markov_edges: parser.markov_edges,
+        },
+    };
+    snapshot.validate()?;
+    Ok(snapshot)
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    version: Option<String>,
+    time: Option<u64>,
+    maps: HashMap<i64, MapKey>,
+    map_times: HashMap<MapKey, u64>,
+    exes: HashMap<i64, ExeRecord>,
+    exe_maps: Vec<ExeMapRecord>,
+    markov_edges: Vec<MarkovRecord>,
+    bad_exes: usize,
+}
+
+impl Parser {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut fields = line.split_whitespace();
+        let Some(tag) = fields.next() else {
+            return Ok(());
+        };
+        let mut fields = Fields(fields);
+
+        match tag {
+            "PRELOAD" => {
+                self.version = Some(fields.text("version")?.to_string());
+                self.time = Some(fields.parse("time")?);
+            }
+            "MAP" => {
+                let seq: i64 = fields.parse("seq")?;
+                let update_time: u64 = fields.parse("update_time")?;
+                let offset: u64 = fields.parse("offset")?;
+                let length: u64 = fields.parse("length")?;
+                let _expansion: i64 = fields.parse("expansion")?;
+                let path = uri_to_path(fields.text("uri")?)?;
+                let key = MapKey::new(path, offset, length);
+                self.map_times.insert(key.clone(), update_time);
+                if self.maps.insert(seq, key).is_some() {
+                    return Err(format!("duplicate map seq {seq}"));
+                }
+            }
+            "BADEXE" => self.bad_exes += 1,
+            "EXE" => {
+                let seq: i64 = fields.parse("seq")?;
+                let update_time: u64 = fields.parse("update_time")?;
+                let time: u64 = fields.parse("time")?;
+                let _expansion: i64 = fields.parse("expansion")?;
+                let path = uri_to_path(fields.text("uri")?)?;
+                let exe = ExeRecord {
+                    path,
+                    total_running_time: time,
+                    last_seen_time: Some(update_time),
+                    running: false,
+                    change_time: 0,
+                    identity: None,
+                };
+                if self.exes.insert(seq, exe).is_some() {
+                    return Err(format!("duplicate exe seq {seq}"));
+                }
+            }
+            "EXEMAP" => {
+                let exe = self.exe_path(fields.parse("exe seq")?)?;
+                let map = self.map_key(fields.parse("map seq")?)?;
+                let prob: f32 = fields.parse("prob")?;
+                self.exe_maps.push(ExeMapRecord {
+                    exe_path: exe,
+                    map_key: map,
+                    prob: prob.clamp(0.0, 1.0),
+                });
+            }
+            "MARKOV" => {
+                let exe_a = self.exe_path(fields.parse("a seq")?)?;
+                let exe_b = self.exe_path(fields.parse("b seq")?)?;
+                let both_running_time: u64 = fields.parse("time")?;
+                let mut time_to_leave = [0.0f32; 4];
+                for ttl in &mut time_to_leave {
+                    *ttl = fields.parse("time_to_leave")?;
+                }
+                let mut weight = [[0.0f64; 4]; 4];
+                for row in &mut weight {
+                    for cell in row.iter_mut() {
+                        *cell = fields.parse("weight")?;
+                    }
+                }
+
+                let mut transition_prob = [[0.0f32; 4]; 4];
+                for (i, row) in weight.iter().enumerate() {
+                    let left = row[i];
+                    if left <= 0.0 {
+                        continue;
+                    }
+                    for (j, count) in row.iter().enumerate() {
+                        if i != j {
+                            transition_prob[i][j] = (count / left).clamp(0.0, 1.0) as f32;
+                        }
+                    }
+                }
+
+                // preload recomputes the state from the running exes on load.
+                let now = self.time.unwrap_or(0);
+                self.markov_edges.push(MarkovRecord {
+                    exe_a,
+                    exe_b,
+                    state: MarkovState::Neither,
+                    last_change_time: now,
+                    state_last_left: [now; 4],
+                    time_to_leave,
+                    transition_prob,
+                    both_running_time,
+                });
+            }
+            other => return Err(format!("unknown record {other:?}")),
+        }
+        Ok(())
+    }
+
+    fn exe_path(&self, seq: i64) -> Result<PathBuf, String> {
+        self.exes
+            .get(&seq)
+            .map(|exe| exe.path.clone())
+            .ok_or_else(|| format!("unknown exe seq {seq}"))
+    }
+
+    fn map_key(&self, seq: i64) -> Result<MapKey, String> {
+        self.maps
+            .get(&seq)
+            .cloned()
+            .ok_or_else(|| format!("unknown map seq {seq}"))
+    }
+}
+
+struct Fields<'a>(std::str::SplitWhitespace<'a>);
+
+impl<'a> Fields<'a> {
+    fn text(&mut self, name: &str) -> Result<&'a str, String> {
+        self.0.next().ok_or_else(|| format!("missing {name}"))
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str) -> Result<T, String> {
+        let text = self.text(name)?;
+        text.parse().map_err(|_| format!("invalid {name} {text:?}"))
+    }
+}
+
+/// Decode a `file://` URI as written by GLib; bare paths are accepted too.
+fn uri_to_path(uri: &str) -> Result<PathBuf, String> {
+    let encoded = match uri.strip_prefix("file://") {
+        // An optional host precedes the path.
+        Some(rest) => {
+            &rest[rest
+                .find('/')
+                .ok_or_else(|| format!("invalid uri {uri:?}"))?..]
+        }
+        None => uri,
+    };
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut input = encoded.bytes();
+    while let Some(byte) = input.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [input.next(), input.next()];
+        let decoded = match hex {
+            [Some(hi), Some(lo)] => std::str::from_utf8(&[hi, lo])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        bytes.push(decoded.ok_or_else(|| format!("invalid escape in {uri:?}"))?);
+    }
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    const STATE: &str = "\
+PRELOAD\t0.6.4\t5000
+MAP\t1\t4900\t0\t8192\t-1\tfile:///usr/lib/libfoo.so.1
+MAP\t2\t4800\t4096\t4096\t-1\tfile:///opt/My%20App/lib%FF.so
+BADEXE\t4000\t-1\tfile:///usr/bin/tiny
+EXE\t3\t4950\t1200\t-1\tfile:///usr/bin/foo
+EXE\t4\t4000\t300\t-1\tfile:///opt/My%20App/app
+EXEMAP\t3\t1\t1
+EXEMAP\t4\t1\t0.5
+EXEMAP\t4\t2\t1
+MARKOV\t3\t4\t250\t10\t20\t30\t40\t4\t1\t3\t0\t2\t2\t0\t0\t0\t0\t0\t0\t0\t1\t0\t1
+";
+
+    #[test]
+    fn converts_the_c_state_format() {
+        let snapshot = import_legacy_state(STATE.as_bytes()).unwrap();
+        let state = &snapshot.state;
+        assert_eq!(state.model_time, 5000);
+        assert_eq!(snapshot.meta.app_version.as_deref(), Some("preload 0.6.4"));
+        assert_eq!(state.exes.len(), 2);
+        assert_eq!(state.maps.len(), 2);
+        assert_eq!(state.exe_maps.len(), 3);
+
+        let app = state
+            .exes
+            .iter()
+            .find(|exe| exe.total_running_time == 300)
+            .unwrap();
+        assert_eq!(app.path, PathBuf::from("/opt/My App/app"));
+        assert_eq!(app.last_seen_time, Some(4000));
+
+        // Percent escapes decode to raw bytes, not just UTF-8.
+        let odd = state.maps.iter().find(|map| map.offset == 4096).unwrap();
+        assert_eq!(odd.path.as_os_str().as_bytes(), b"/opt/My App/lib\xff.so");
+        assert_eq!(odd.update_time, 4800);
+
+        let edge = &state.markov_edges[0];
+        assert_eq!(edge.exe_a, PathBuf::from("/usr/bin/foo"));
+        assert_eq!(edge.both_running_time, 250);
+        assert_eq!(edge.time_to_leave, [10.0, 20.0, 30.0, 40.0]);
+        // Neither was left 4 times: once to A only, 3 times to B only.
+        assert_eq!(edge.transition_prob[0], [0.0, 0.25, 0.75, 0.0]);
+        assert_eq!(edge.transition_prob[1], [1.0, 0.0, 0.0, 0.0]);
+        // Never left: no estimate.
+        assert_eq!(edge.transition_prob[2], [0.0; 4]);
+        assert_eq!(edge.transition_prob[3], [0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let broken = STATE.replace("EXEMAP\t4\t2\t1", "EXEMAP\t4\t9\t1");
+        let err = import_legacy_state(broken.as_bytes()).unwrap_err();
+        assert!(
+            err.to_string().contains("line 9: unknown map seq 9"),
+            "{err}"
+        );
+
+        let err = import_legacy_state("MAP\t1\tx".as_bytes()).unwrap_err();
+        assert!(
+            err.to_string().contains("line 1: invalid update_time"),
+            "{err}"
+        );
+
+        assert!(import_legacy_state("".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+#![forbid(unsafe_code)]
+
+//! Advisory single-writer lock on a state location.
+//!
+//! Writers hold an exclusive `flock` on `<path>.lock` for as long as their
+//! repository is open, and record their pid in it for the error a second
+//! writer gets. The lock file is left in place when the writer exits; the
+//! kernel drops the lock with the last descriptor. Read-only opens do not
+//! take the lock.
+
+use crate::error::Error;
+use crate::persistence::backup::sibling;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use tracing::debug;
+
+/// Held while a repository may write its state.
+#[derive(Debug)]
+pub(crate) struct StateLock {
+    _file: File,
+}
+
+impl StateLock {
+    /// Lock the state at `path`, failing with [`Error::StateLocked`] if
+    /// another writer holds it.
+    pub(crate) fn acquire(path: &Path) -> Result<Self, Error> {
+        let lock_path = sibling(path, ".lock");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                file.read_to_string(&mut holder)?;
+                return Err(Error::StateLocked {
+                    path: path.to_path_buf(),
+                    pid: holder.trim().parse().ok(),
+                });
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        debug!(lock = %lock_path.display(), "state locked");
+        Ok(Self { _file: file })
+    }
+}
@@ -0,0 +1,132 @@
+#![forbid(unsafe_code)]
+
+//! Upgrades of stored snapshots to the current [`SNAPSHOT_SCHEMA_VERSION`].
+//!
+//! SQL migrations only change the shape of the tables; columns they add read
+//! back as defaults. Anything that has to be derived from the old data (a
+//! field that used to be recomputed at load time, a changed unit) is done here,
+//! once the rows have been read into records.
+
+use crate::domain::MarkovState;
+use crate::error::Error;
+use crate::persistence::{SNAPSHOT_SCHEMA_VERSION, StateSnapshot, StoresSnapshot};
+use tracing::info;
+
+/// Rewrites a snapshot from one schema version to the next.
+type Migration = fn(&mut StateSnapshot);
+
+/// `MIGRATIONS[i]` upgrades schema version `i + 1` to `i + 2`.
+const MIGRATIONS: &[Migration] = &[markov_state, raw_paths];
+
+const _: () = assert!(MIGRATIONS.len() + 1 == SNAPSHOT_SCHEMA_VERSION as usize);
+
+/// Reject schema versions this build does not know.
+pub(crate) fn check_schema_version(found: u32) -> Result<(), Error> {
+    if found == 0 || found > SNAPSHOT_SCHEMA_VERSION {
+        return Err(Error::UnsupportedSnapshotVersion {
+            found,
+            supported: SNAPSHOT_SCHEMA_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Bring a loaded snapshot up to [`SNAPSHOT_SCHEMA_VERSION`].
+///
+/// Snapshots written by a newer build are refused with
+/// [`Error::UnsupportedSnapshotVersion`] rather than loaded with fields
+/// silently dropped.
+pub fn migrate_snapshot(mut snapshot: StoresSnapshot) -> Result<StoresSnapshot, Error> {
+    let found = snapshot.meta.schema_version;
+    check_schema_version(found)?;
+
+    for (from, migration) in (found..).zip(&MIGRATIONS[found as usize - 1..]) {
+        info!(from, to = from + 1, "migrating snapshot");
+        migration(&mut snapshot.state);
+    }
+    snapshot.meta.schema_version = SNAPSHOT_SCHEMA_VERSION;
+    Ok(snapshot)
+}
+
+/// Version 1 kept only Markov statistics, and edges were loaded as if neither
+/// exe had run since the saved model time. Keep loading them that way.
+fn markov_state(state: &mut StateSnapshot) {
+    for edge in &mut state.markov_edges {
+        edge.state = MarkovState::Neither;
+        edge.last_change_time = state.model_time;
+        edge.state_last_left = [state.model_time; 4];
+    }
+}
+
+/// Version 3 stores paths as raw bytes. The SQL migration converts the
+/// columns, and records always held `PathBuf`s, so nothing changes here.
+fn raw_paths(_state: &mut StateSnapshot) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{MarkovRecord, SnapshotMeta};
+
+    fn snapshot(schema_version: u32) -> StoresSnapshot {
+        StoresSnapshot {
+            meta: SnapshotMeta {
+                schema_version,
+                app_version: None,
+                created_at: None,
+            },
+            state: StateSnapshot {
+                model_time: 7,
+                last_accounting_time: 7,
+                exes: Vec::new(),
+                maps: Vec::new(),
+                exe_maps: Vec::new(),
+                markov_edges: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn current_version_loads_unchanged() {
+        let migrated = migrate_snapshot(snapshot(SNAPSHOT_SCHEMA_VERSION)).unwrap();
+        assert_eq!(migrated.meta.schema_version, SNAPSHOT_SCHEMA_VERSION);
+        assert_eq!(migrated.state.model_time, 7);
+    }
+
+    #[test]
+    fn version_1_edges_restart_in_neither() {
+        let mut old = snapshot(1);
+        old.state.markov_edges.push(MarkovRecord {
+            exe_a: "/bin/a".into(),
+            exe_b: "/bin/b".into(),
+            state: MarkovState::Both,
+            last_change_time: 0,
+            state_last_left: [0; 4],
+            time_to_leave: [1.0; 4],
+            transition_prob: [[0.25; 4]; 4],
+            both_running_time: 3,
+        });
+
+        let migrated = migrate_snapshot(old).unwrap();
+        assert_eq!(migrated.meta.schema_version, SNAPSHOT_SCHEMA_VERSION);
+        let edge = &migrated.state.markov_edges[0];
+        assert_eq!(edge.state, MarkovState::Neither);
+        assert_eq!(edge.last_change_time, 7);
+        assert_eq!(edge.state_last_left, [7; 4]);
+        assert_eq!(edge.time_to_leave, [1.0; 4]);
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        for found in [0, SNAPSHOT_SCHEMA_VERSION + 1] {
+            let err = migrate_snapshot(snapshot(found)).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    Error::UnsupportedSnapshotVersion { found: f, supported }
+                        if f == found && supported == SNAPSHOT_SCHEMA_VERSION
+                ),
+                "{err}"
+            );
+        }
+    }
+}
@@ -1,10 +1,29 @@
 #![forbid(unsafe_code)]
 
+mod backup;
+mod export;
+mod file;
+mod inspect;
+mod legacy;
+mod lock;
+mod migrate;
+mod registry;
 mod repo;
 mod snapshot;
 
+pub use export::{EXPORT_FORMAT, export_json, import_json};
+pub use file::FileRepository;
+pub use inspect::{
+    EdgeSummary, ExeSummary, InspectOptions, MapSummary, PrefixSummary, StateReport, Totals,
+};
+pub use legacy::{LEGACY_STATE_PATH, import_legacy_state};
+pub use migrate::migrate_snapshot;
+pub use registry::{
+    FileBackend, MemoryBackend, RepositoryBackend, RepositoryOptions, RepositoryRegistry,
+    SqliteBackend,
+};
 pub use repo::{NoopRepository, SqliteRepository, StateRepository};
 pub use snapshot::{
-    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
-    StateSnapshot, StoresSnapshot,
+    APP_VERSION, ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION,
+    SnapshotDelta, SnapshotMeta, StateSnapshot, StoresSnapshot,
 };
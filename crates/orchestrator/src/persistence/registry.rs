@@ -0,0 +1,312 @@
+#![forbid(unsafe_code)]
+
+//! Opening a [`StateRepository`] from a backend URL.
+//!
+//! A URL is `<scheme>://<location>`. The registry maps each scheme to a
+//! [`RepositoryBackend`] and hands it the location unchanged. The built-in
+//! path backends read it with [`config::decode_path`], so
+//! `sqlite:///var/lib/preload-rs/state.db` opens the absolute path
+//! `/var/lib/preload-rs/state.db`, `sqlite://state.db` a relative one, and
+//! `%XX` escapes stand for single bytes.
+//! Embedders add their own schemes with [`RepositoryRegistry::register`].
+
+use crate::error::Error;
+use crate::persistence::{FileRepository, NoopRepository, SqliteRepository, StateRepository};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a repository is opened.
+#[derive(Debug, Clone, Default)]
+pub struct RepositoryOptions {
+    /// Rotated copies of the state to keep, for backends that support them.
+    pub backups: usize,
+    /// How often saves refresh those copies; zero means at every save.
+    pub backup_interval: Duration,
+    /// Replace a damaged state by its newest healthy copy when opening it,
+    /// instead of failing, for backends that check their state on open.
+    pub repair: bool,
+    /// Open an existing state for loading only; saves fail. Backends must
+    /// not create, migrate or otherwise modify the state.
+    pub read_only: bool,
+}
+
+/// Builds repositories for one URL scheme.
+#[async_trait]
+pub trait RepositoryBackend: Send + Sync {
+    /// Open the state at `location`, the part of the URL after `://`.
+    async fn open(
+        &self,
+        location: &str,
+        options: &RepositoryOptions,
+    ) -> Result<Box<dyn StateRepository>, Error>;
+}
+
+/// Backends by URL scheme.
+#[derive(Clone)]
+pub struct RepositoryRegistry {
+    backends: HashMap<String, Arc<dyn RepositoryBackend>>,
+}
+
+impl RepositoryRegistry {
+    /// A registry without any backends.
+    pub fn empty() -> Self {
+        Self {
+            backends: HashMap::new(),
+        }
+    }
+
+    /// Make `scheme` open repositories with `backend`, replacing any backend
+    /// registered for it before.
+    pub fn register(
+        &mut self,
+        scheme: impl Into<String>,
+        backend: impl RepositoryBackend + 'static,
+    ) -> &mut Self {
+        self.backends
+            .insert(scheme.into().to_ascii_lowercase(), Arc::new(backend));
+        self
+    }
+
+    /// Registered schemes, sorted.
+    pub fn schemes(&self) -> Vec<&str> {
+        let mut schemes: Vec<&str> = self.backends.keys().map(String::as_str).collect();
+        schemes.sort_unstable();
+        schemes
+    }
+
+    /// Open the repository `url` points at.
+    pub async fn open(
+        &self,
+        url: &str,
+        options: &RepositoryOptions,
+    ) -> Result<Box<dyn StateRepository>, Error> {
+        let (scheme, location) = url
+            .split_once("://")
+            .ok_or_else(|| Error::InvalidStateUrl(url.to_string()))?;
+        let backend = self
+            .backends
+            .get(&scheme.to_ascii_lowercase())
+            .ok_or_else(|| Error::UnknownStateBackend(scheme.to_string()))?;
+        backend.open(location, options).await
+    }
+}
+
+/// `sqlite`, `file` and `memory`.
+impl Default for RepositoryRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register("sqlite", SqliteBackend)
+            .register("file", FileBackend)
+            .register("memory", MemoryBackend);
+        registry
+    }
+}
+
+impl std::fmt::Debug for RepositoryRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RepositoryRegistry")
+            .field("schemes", &self.schemes())
+            .finish()
+    }
+}
+
+/// `sqlite://<path>`: [`SqliteRepository`].
+#[derive(Debug, Default)]
+pub struct SqliteBackend;
+
+#[async_trait]
+impl RepositoryBackend for SqliteBackend {
+    async fn open(
+        &self,
+        location: &str,
+        options: &RepositoryOptions,
+    ) -> Result<Box<dyn StateRepository>, Error> {
+        let path = state_file(location)?;
+        if options.read_only {
+            // SQLite would only say it cannot open the file.
+            require_existing(&path)?;
+            return Ok(Box::new(SqliteRepository::open_read_only(path).await?));
+        }
+        let repo = if options.repair {
+            SqliteRepository::new(path).await?
+        } else {
+            SqliteRepository::new_strict(path).await?
+        };
+        let repo = repo
+            .with_backups(options.backups)
+            .with_backup_interval(options.backup_interval);
+        Ok(Box::new(repo))
+    }
+}
+
+/// `file://<path>`: [`FileRepository`].
+#[derive(Debug, Default)]
+pub struct FileBackend;
+
+#[async_trait]
+impl RepositoryBackend for FileBackend {
+    async fn open(
+        &self,
+        location: &str,
+        options: &RepositoryOptions,
+    ) -> Result<Box<dyn StateRepository>, Error> {
+        let path = state_file(location)?;
+        if options.read_only {
+            return Ok(Box::new(FileRepository::open_read_only(path)?));
+        }
+        Ok(Box::new(
+            FileRepository::new(path)?
+                .with_backups(options.backups)
+                .with_backup_interval(options.backup_interval),
+        ))
+    }
+}
+
+/// `memory://`: [`NoopRepository`]; the model lives only as long as the
+/// process.
+#[derive(Debug, Default)]
+pub struct MemoryBackend;
+
+#[async_trait]
+impl RepositoryBackend for MemoryBackend {
+    async fn open(
+        &self,
+        _location: &str,
+        _options: &RepositoryOptions,
+    ) -> Result<Box<dyn StateRepository>, Error> {
+        Ok(Box::new(NoopRepository))
+    }
+}
+
+fn state_file(location: &str) -> Result<PathBuf, Error> {
+    if location.is_empty() {
+        return Err(Error::InvalidStateUrl("no path after `://`".into()));
+    }
+    Ok(config::decode_path(location))
+}
+
+fn require_existing(path: &Path) -> Result<(), Error> {
+    if !path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("state not found: {}", path.display()),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::StoresSnapshot;
+    use std::sync::Mutex;
+
+    /// Records the locations it was asked to open.
+    #[derive(Default, Clone)]
+    struct Recording(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl RepositoryBackend for Recording {
+        async fn open(
+            &self,
+            location: &str,
+            _options: &RepositoryOptions,
+        ) -> Result<Box<dyn StateRepository>, Error> {
+            self.0.lock().unwrap().push(location.to_string());
+            Ok(Box::new(NoopRepository))
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_backends_get_the_location() {
+        let recording = Recording::default();
+        let mut registry = RepositoryRegistry::default();
+        registry.register("Redis", recording.clone());
+        assert_eq!(registry.schemes(), ["file", "memory", "redis", "sqlite"]);
+
+        let options = RepositoryOptions::default();
+        registry
+            .open("redis://localhost:6379/0", &options)
+            .await
+            .unwrap();
+        registry.open("REDIS://other", &options).await.unwrap();
+        assert_eq!(*recording.0.lock().unwrap(), ["localhost:6379/0", "other"]);
+    }
+
+    #[tokio::test]
+    async fn bad_urls_are_reported() {
+        let registry = RepositoryRegistry::default();
+        let options = RepositoryOptions::default();
+        assert!(matches!(
+            registry.open("/var/lib/state.db", &options).await,
+            Err(Error::InvalidStateUrl(_))
+        ));
+        assert!(matches!(
+            registry.open("postgres://db/state", &options).await,
+            Err(Error::UnknownStateBackend(scheme)) if scheme == "postgres"
+        ));
+        assert!(matches!(
+            registry.open("sqlite://", &options).await,
+            Err(Error::InvalidStateUrl(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn built_in_backends_open_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = RepositoryRegistry::default();
+        let options = RepositoryOptions::default();
+        let read_only = RepositoryOptions {
+            read_only: true,
+            ..RepositoryOptions::default()
+        };
+
+        for (scheme, name) in [("sqlite", "state.db"), ("file", "state.bin")] {
+            let url = format!("{scheme}://{}", dir.path().join(name).display());
+            assert!(registry.open(&url, &read_only).await.is_err(), "{url}");
+
+            let mut snapshot = StoresSnapshot::empty();
+            snapshot.state.model_time = 5;
+            registry
+                .open(&url, &options)
+                .await
+                .unwrap()
+                .save(&snapshot)
+                .await
+                .unwrap();
+            let reader = registry.open(&url, &read_only).await.unwrap();
+            assert_eq!(reader.load().await.unwrap().state.model_time, 5, "{url}");
+            assert!(reader.save(&snapshot).await.is_err(), "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn state_urls_keep_every_path_byte() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(OsStr::from_bytes(b"100%41-\xff.bin"));
+        let persistence = config::Persistence {
+            state_path: Some(path.clone()),
+            backend: config::StateBackend::File,
+            ..config::Persistence::default()
+        };
+        let url = persistence.state_url().unwrap();
+
+        let registry = RepositoryRegistry::default();
+        registry
+            .open(&url, &RepositoryOptions::default())
+            .await
+            .unwrap()
+            .save(&StoresSnapshot::empty())
+            .await
+            .unwrap();
+        assert!(path.exists(), "{url}");
+    }
+}
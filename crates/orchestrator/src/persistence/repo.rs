@@ -1,23 +1,44 @@
 #![forbid(unsafe_code)]
 
+use crate::domain::{FileIdentity, MarkovState};
 use crate::error::Error;
+use crate::persistence::backup;
+use crate::persistence::lock::StateLock;
+use crate::persistence::migrate::check_schema_version;
 use crate::persistence::{
-    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
-    StateSnapshot, StoresSnapshot,
+    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotDelta,
+    SnapshotMeta, StateSnapshot, StoresSnapshot, migrate_snapshot,
 };
 use async_trait::async_trait;
-use sqlx::SqlitePool;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
-use std::path::PathBuf;
-use std::time::SystemTime;
-use tracing::debug;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+use tracing::{debug, warn};
 
 #[async_trait]
 pub trait StateRepository: Send + Sync {
     /// Load a snapshot from persistence.
     async fn load(&self) -> Result<StoresSnapshot, Error>;
-    /// Persist a snapshot.
+    /// Persist a snapshot, replacing whatever was stored before.
     async fn save(&self, snapshot: &StoresSnapshot) -> Result<(), Error>;
+    /// Apply the rows changed since the previous save on top of it. Returns
+    /// `false` if the backend cannot, in which case the caller saves a full
+    /// snapshot instead.
+    async fn save_delta(&self, _delta: &SnapshotDelta) -> Result<bool, Error> {
+        Ok(false)
+    }
+    /// Delete everything this repository stored, once the state has moved
+    /// elsewhere. Nothing may be saved through it afterwards. Backends with
+    /// nothing to delete do nothing.
+    async fn remove(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -26,21 +47,7 @@ pub struct NoopRepository;
 #[async_trait]
 impl StateRepository for NoopRepository {
     async fn load(&self) -> Result<StoresSnapshot, Error> {
-        Ok(StoresSnapshot {
-            meta: SnapshotMeta {
-                schema_version: SNAPSHOT_SCHEMA_VERSION,
-                app_version: None,
-                created_at: None,
-            },
-            state: StateSnapshot {
-                model_time: 0,
-                last_accounting_time: 0,
-                exes: Vec::new(),
-                maps: Vec::new(),
-                exe_maps: Vec::new(),
-                markov_edges: Vec::new(),
-            },
-        })
+        Ok(StoresSnapshot::empty())
     }
 
     async fn save(&self, _snapshot: &StoresSnapshot) -> Result<(), Error> {
@@ -52,14 +59,53 @@ impl StateRepository for NoopRepository {
 pub struct SqliteRepository {
     path: PathBuf,
     pool: SqlitePool,
+    /// Backups kept next to the database.
+    backups: usize,
+    backup_schedule: backup::Schedule,
+    /// `None` when opened read-only.
+    lock: Option<Arc<StateLock>>,
+    /// Holds the migrated copy a read-only open of an older database reads.
+    _upgraded: Option<Arc<TempDir>>,
 }
 
 impl SqliteRepository {
-    /// Create a repository backed by a SQLite database file.
+    /// Create a repository backed by a SQLite database file. A file that
+    /// fails SQLite's integrity check is moved aside and replaced by its
+    /// newest healthy backup, or by an empty database if there is none.
+    /// Other failures to open it, such as missing permissions, are errors.
+    ///
+    /// The repository holds the state's writer lock until it is dropped;
+    /// while another writer has it this fails with [`Error::StateLocked`].
+    /// SQLite needs a UTF-8 `path`; [`FileRepository`] takes any.
+    ///
+    /// [`FileRepository`]: crate::persistence::FileRepository
     pub async fn new(path: PathBuf) -> Result<Self, Error> {
+        Self::open(path, true).await
+    }
+
+    /// Like [`SqliteRepository::new`], but a damaged database is an error
+    /// instead of being replaced, for `persistence.repair = false`.
+    pub async fn new_strict(path: PathBuf) -> Result<Self, Error> {
+        Self::open(path, false).await
+    }
+
+    async fn open(path: PathBuf, recover: bool) -> Result<Self, Error> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
+        let lock = StateLock::acquire(&path)?;
+        if path.exists()
+            && let Some(damage) = backup::damage(&path).await?
+        {
+            if !recover {
+                return Err(Error::InvalidSnapshot(format!(
+                    "state database {} is damaged: {damage}",
+                    path.display()
+                )));
+            }
+            warn!(path = %path.display(), %damage, "state database failed its integrity check");
+            backup::recover(&path).await?;
+        }
 
         let options = SqliteConnectOptions::new()
             .filename(&path)
@@ -71,12 +117,98 @@ impl SqliteRepository {
             .connect_with(options)
             .await?;
 
+        // A newer build may have added tables this one does not know;
+        // migrating would fail with a less helpful error.
+        if let Some(found) = stored_schema_version(&pool).await? {
+            check_schema_version(found)?;
+        }
+
         sqlx::migrate!("./migrations")
             .run(&pool)
             .await
             .map_err(sqlx::Error::from)?;
 
-        Ok(Self { path, pool })
+        Ok(Self {
+            path,
+            pool,
+            backups: 0,
+            backup_schedule: backup::Schedule::default(),
+            lock: Some(Arc::new(lock)),
+            _upgraded: None,
+        })
+    }
+
+    /// Close the database, checkpointing its write-ahead log.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Keep `count` rotated copies of the database, refreshed by the first
+    /// save and then at most once per backup interval.
+    pub fn with_backups(mut self, count: usize) -> Self {
+        self.backups = count;
+        self
+    }
+
+    /// Refresh the backups at most once per `interval`, by whichever save
+    /// comes first after it; full and incremental saves alike. Zero, the
+    /// default, refreshes them at every save.
+    pub fn with_backup_interval(mut self, interval: Duration) -> Self {
+        self.backup_schedule = backup::Schedule::new(interval);
+        self
+    }
+
+    /// Open an existing database without creating, migrating or writing to
+    /// it, so a live daemon's state can be inspected safely. The writer lock
+    /// is not taken. Saving through this repository fails.
+    ///
+    /// A database from an older build is copied to a temporary file and the
+    /// copy migrated, since the queries need the current tables; the file
+    /// itself is left as it is.
+    pub async fn open_read_only(path: PathBuf) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::new().filename(&path).read_only(true);
+
+        let mut pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        let mut upgraded = None;
+        if has_pending_migrations(&pool).await? {
+            if let Some(found) = stored_schema_version(&pool).await? {
+                check_schema_version(found)?;
+            }
+            let dir = tempfile::tempdir()?;
+            let copy = dir.path().join("state.db");
+            vacuum_into(&pool, &copy).await?;
+            pool.close().await;
+
+            let migrating = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect_with(SqliteConnectOptions::new().filename(&copy))
+                .await?;
+            sqlx::migrate!("./migrations")
+                .run(&migrating)
+                .await
+                .map_err(sqlx::Error::from)?;
+            migrating.close().await;
+
+            pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect_with(SqliteConnectOptions::new().filename(&copy).read_only(true))
+                .await?;
+            debug!(path = %path.display(), "reading a migrated copy of the state");
+            upgraded = Some(Arc::new(dir));
+        }
+
+        Ok(Self {
+            path,
+            pool,
+            backups: 0,
+            backup_schedule: backup::Schedule::default(),
+            lock: None,
+            _upgraded: upgraded,
+        })
     }
 
     async fn save_snapshot(&self, snapshot: &StoresSnapshot) -> Result<(), Error> {
@@ -92,102 +224,112 @@ impl SqliteRepository {
             .execute(&mut *tx)
             .await?;
 
-        let meta = &snapshot.meta;
-        let created_at = meta
-            .created_at
-            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs().to_string());
+        write_state(&mut tx, &snapshot.meta, &snapshot.state).await?;
+        write_rows(&mut tx, &snapshot.state).await?;
 
-        let app_version = meta.app_version.clone();
-        let schema_version = meta.schema_version as i64;
-        let model_time = snapshot.state.model_time as i64;
-        let last_accounting_time = snapshot.state.last_accounting_time as i64;
-        sqlx::query!(
-            "INSERT INTO state (id, schema_version, app_version, created_at, model_time, last_accounting_time) \
-             VALUES (1, ?, ?, ?, ?, ?)",
-            schema_version,
-            app_version,
-            created_at,
-            model_time,
-            last_accounting_time
-        )
-        .execute(&mut *tx)
-        .await?;
+        tx.commit().await?;
+        debug!(path = %self.path.display(), "snapshot persisted");
+        self.back_up_if_due().await;
+        Ok(())
+    }
+
+    async fn back_up_if_due(&self) {
+        if self.backups == 0 || !self.backup_schedule.is_due() {
+            return;
+        }
+        // The snapshot itself is safe; a missing copy is not worth failing
+        // the save over.
+        match self.write_backup().await {
+            Ok(()) => self.backup_schedule.done(),
+            Err(err) => warn!(%err, path = %self.path.display(), "failed to back up state"),
+        }
+    }
+
+    async fn write_backup(&self) -> Result<(), Error> {
+        let target = backup::rotate(&self.path, self.backups)?;
+        vacuum_into(&self.pool, &target).await?;
+        debug!(backup = %target.display(), "state backed up");
+        Ok(())
+    }
+
+    /// Load the newest backup that reads back, for when the database itself
+    /// does not.
+    async fn load_backup(&self) -> Option<StoresSnapshot> {
+        for path in backup::existing_backups(&self.path) {
+            let loaded = match Self::open_read_only(path.clone()).await {
+                Ok(repo) => repo.load_snapshot().await,
+                Err(err) => Err(err),
+            };
+            match loaded {
+                Ok(snapshot) => {
+                    warn!(backup = %path.display(), "loaded state from backup");
+                    return Some(snapshot);
+                }
+                Err(err) => warn!(%err, backup = %path.display(), "backup is unreadable"),
+            }
+        }
+        None
+    }
+
+    async fn save_snapshot_delta(&self, delta: &SnapshotDelta) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        write_state(&mut tx, &delta.meta, &delta.changed).await?;
 
-        for exe in &snapshot.state.exes {
-            let path = exe.path.to_string_lossy().to_string();
-            let total_running_time = exe.total_running_time as i64;
-            let last_seen_time = exe.last_seen_time.map(|v| v as i64);
+        for path in &delta.removed_exes {
+            let path = path.as_os_str().as_bytes();
+            sqlx::query!("DELETE FROM exes WHERE path = ?", path)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!("DELETE FROM exe_maps WHERE exe_path = ?", path)
+                .execute(&mut *tx)
+                .await?;
             sqlx::query!(
-                "INSERT INTO exes (path, total_running_time, last_seen_time) VALUES (?, ?, ?)",
+                "DELETE FROM markovs WHERE exe_a = ? OR exe_b = ?",
                 path,
-                total_running_time,
-                last_seen_time
+                path
             )
             .execute(&mut *tx)
             .await?;
         }
 
-        for map in &snapshot.state.maps {
-            let path = map.path.to_string_lossy().to_string();
-            let offset = map.offset as i64;
-            let length = map.length as i64;
-            let update_time = map.update_time as i64;
+        for key in &delta.removed_maps {
+            let path = key.path.as_os_str().as_bytes();
+            let offset = key.offset as i64;
+            let length = key.length as i64;
             sqlx::query!(
-                "INSERT INTO maps (path, offset, length, update_time) VALUES (?, ?, ?, ?)",
+                "DELETE FROM maps WHERE path = ? AND offset = ? AND length = ?",
                 path,
                 offset,
-                length,
-                update_time
+                length
             )
             .execute(&mut *tx)
             .await?;
-        }
-
-        for map in &snapshot.state.exe_maps {
-            let exe_path = map.exe_path.to_string_lossy().to_string();
-            let map_path = map.map_key.path.to_string_lossy().to_string();
-            let map_offset = map.map_key.offset as i64;
-            let map_length = map.map_key.length as i64;
-            let prob = map.prob as f64;
             sqlx::query!(
-                "INSERT INTO exe_maps (exe_path, map_path, map_offset, map_length, prob) \
-                 VALUES (?, ?, ?, ?, ?)",
-                exe_path,
-                map_path,
-                map_offset,
-                map_length,
-                prob
+                "DELETE FROM exe_maps WHERE map_path = ? AND map_offset = ? AND map_length = ?",
+                path,
+                offset,
+                length
             )
             .execute(&mut *tx)
             .await?;
         }
 
-        for markov in &snapshot.state.markov_edges {
-            let ttl: Vec<u8> = rkyv::to_bytes::<rkyv::rancor::Error>(&markov.time_to_leave)
-                .map_err(|err| Error::RkyvSerialize(err.to_string()))?
-                .into();
-            let tp: Vec<u8> = rkyv::to_bytes::<rkyv::rancor::Error>(&markov.transition_prob)
-                .map_err(|err| Error::RkyvSerialize(err.to_string()))?
-                .into();
-            let exe_a = markov.exe_a.to_string_lossy().to_string();
-            let exe_b = markov.exe_b.to_string_lossy().to_string();
-            let both_running_time = markov.both_running_time as i64;
-            sqlx::query!(
-                "INSERT INTO markovs (exe_a, exe_b, time_to_leave, transition_prob, both_running_time) \
-                 VALUES (?, ?, ?, ?, ?)",
-                exe_a,
-                exe_b,
-                ttl,
-                tp,
-                both_running_time
-            )
-            .execute(&mut *tx)
-            .await?;
+        for (exe_a, exe_b) in &delta.removed_edges {
+            delete_edge(&mut tx, exe_a, exe_b).await?;
+            delete_edge(&mut tx, exe_b, exe_a).await?;
+        }
+        // Edge endpoints are ordered by in-memory id, which can differ from
+        // the order a previous run stored them in.
+        for markov in &delta.changed.markov_edges {
+            delete_edge(&mut tx, &markov.exe_b, &markov.exe_a).await?;
         }
 
+        write_rows(&mut tx, &delta.changed).await?;
+
         tx.commit().await?;
-        debug!(path = %self.path.display(), "snapshot persisted");
+        debug!(path = %self.path.display(), rows = delta.len(), "snapshot delta persisted");
+        self.back_up_if_due().await;
         Ok(())
     }
 
@@ -225,33 +367,46 @@ impl SqliteRepository {
             state.model_time = row.model_time as u64;
             state.last_accounting_time = row.last_accounting_time as u64;
         }
+        // Rows of a newer schema may not read back with this build's queries.
+        check_schema_version(meta.schema_version)?;
 
         let rows = sqlx::query!(
-            "SELECT path as \"path!\", total_running_time as \"total_running_time!\", last_seen_time \
-             FROM exes"
+            "SELECT path as \"path!\", total_running_time as \"total_running_time!\", last_seen_time, \
+             running as \"running!\", change_time as \"change_time!\", identity FROM exes"
         )
             .fetch_all(&self.pool)
             .await?;
         for row in rows {
             state.exes.push(ExeRecord {
-                path: PathBuf::from(row.path),
+                path: path_from_bytes(row.path),
                 total_running_time: row.total_running_time as u64,
                 last_seen_time: row.last_seen_time.map(|v| v as u64),
+                running: row.running != 0,
+                change_time: row.change_time as u64,
+                identity: row.identity.and_then(|text| text.parse().ok()),
             });
         }
 
         let rows = sqlx::query!(
-            "SELECT path as \"path!\", offset as \"offset!\", length as \"length!\", update_time as \"update_time!\" \
-             FROM maps"
+            "SELECT path as \"path!\", offset as \"offset!\", length as \"length!\", update_time as \"update_time!\", \
+             device, inode, mtime_ns FROM maps"
         )
             .fetch_all(&self.pool)
             .await?;
         for row in rows {
             state.maps.push(MapRecord {
-                path: PathBuf::from(row.path),
+                path: path_from_bytes(row.path),
                 offset: row.offset as u64,
                 length: row.length as u64,
                 update_time: row.update_time as u64,
+                identity: match (row.device, row.inode, row.mtime_ns) {
+                    (Some(device), Some(inode), Some(mtime_ns)) => Some(FileIdentity {
+                        device: device as u64,
+                        inode: inode as u64,
+                        mtime_ns,
+                    }),
+                    _ => None,
+                },
             });
         }
 
@@ -263,9 +418,9 @@ impl SqliteRepository {
         .await?;
         for row in rows {
             state.exe_maps.push(ExeMapRecord {
-                exe_path: PathBuf::from(row.exe_path),
+                exe_path: path_from_bytes(row.exe_path),
                 map_key: crate::domain::MapKey::new(
-                    row.map_path,
+                    path_from_bytes(row.map_path),
                     row.map_offset as u64,
                     row.map_length as u64,
                 ),
@@ -274,7 +429,8 @@ impl SqliteRepository {
         }
 
         let rows = sqlx::query!(
-            "SELECT exe_a as \"exe_a!\", exe_b as \"exe_b!\", time_to_leave as \"time_to_leave!\", \
+            "SELECT exe_a as \"exe_a!\", exe_b as \"exe_b!\", state as \"state!\", \
+             last_change_time as \"last_change_time!\", state_last_left, time_to_leave as \"time_to_leave!\", \
              transition_prob as \"transition_prob!\", both_running_time as \"both_running_time!\" \
              FROM markovs"
         )
@@ -288,26 +444,274 @@ impl SqliteRepository {
             let transition_prob: [[f32; 4]; 4] =
                 rkyv::from_bytes::<[[f32; 4]; 4], rkyv::rancor::Error>(&tp)
                     .map_err(|err| Error::RkyvDeserialize(err.to_string()))?;
+            // Written by schema version 2 onwards; older rows are migrated.
+            let state_last_left: [u64; 4] = match row.state_last_left {
+                Some(bytes) => rkyv::from_bytes::<[u64; 4], rkyv::rancor::Error>(&bytes)
+                    .map_err(|err| Error::RkyvDeserialize(err.to_string()))?,
+                None => [0; 4],
+            };
+            let edge_state = MarkovState::from_index(row.state as usize).ok_or_else(|| {
+                Error::InvalidSnapshot(format!("unknown markov state {}", row.state))
+            })?;
             state.markov_edges.push(MarkovRecord {
-                exe_a: PathBuf::from(row.exe_a),
-                exe_b: PathBuf::from(row.exe_b),
+                exe_a: path_from_bytes(row.exe_a),
+                exe_b: path_from_bytes(row.exe_b),
+                state: edge_state,
+                last_change_time: row.last_change_time as u64,
+                state_last_left,
                 time_to_leave,
                 transition_prob,
                 both_running_time: row.both_running_time as u64,
             });
         }
 
-        Ok(StoresSnapshot { meta, state })
+        migrate_snapshot(StoresSnapshot { meta, state })
+    }
+}
+
+/// The schema version recorded by the last save, if the database has one.
+async fn stored_schema_version(pool: &SqlitePool) -> Result<Option<u32>, Error> {
+    let has_state: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'state')",
+    )
+    .fetch_one(pool)
+    .await?;
+    if !has_state {
+        return Ok(None);
+    }
+    let version: Option<i64> = sqlx::query_scalar("SELECT schema_version FROM state WHERE id = 1")
+        .fetch_optional(pool)
+        .await?;
+    Ok(version.map(|version| version as u32))
+}
+
+/// Whether this build has migrations the database at `pool` lacks.
+async fn has_pending_migrations(pool: &SqlitePool) -> Result<bool, Error> {
+    let tracked: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master \
+         WHERE type = 'table' AND name = '_sqlx_migrations')",
+    )
+    .fetch_one(pool)
+    .await?;
+    if !tracked {
+        return Ok(true);
+    }
+    let applied: HashSet<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+    Ok(sqlx::migrate!("./migrations")
+        .iter()
+        .any(|migration| !applied.contains(&migration.version)))
+}
+
+/// Write a consistent copy of the database at `pool` to `target`.
+async fn vacuum_into(pool: &SqlitePool, target: &Path) -> Result<(), Error> {
+    // Bound as bytes so any path works; SQLite wants the name as text.
+    sqlx::query("VACUUM INTO CAST(? AS TEXT)")
+        .bind(target.as_os_str().as_bytes())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn write_state(
+    conn: &mut SqliteConnection,
+    meta: &SnapshotMeta,
+    state: &StateSnapshot,
+) -> Result<(), Error> {
+    let created_at = meta
+        .created_at
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs().to_string());
+
+    let app_version = meta.app_version.clone();
+    let schema_version = meta.schema_version as i64;
+    let model_time = state.model_time as i64;
+    let last_accounting_time = state.last_accounting_time as i64;
+    sqlx::query!(
+        "INSERT OR REPLACE INTO state (id, schema_version, app_version, created_at, model_time, last_accounting_time) \
+         VALUES (1, ?, ?, ?, ?, ?)",
+        schema_version,
+        app_version,
+        created_at,
+        model_time,
+        last_accounting_time
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Insert the rows of `state`, replacing rows with the same key.
+async fn write_rows(conn: &mut SqliteConnection, state: &StateSnapshot) -> Result<(), Error> {
+    for exe in &state.exes {
+        let path = exe.path.as_os_str().as_bytes();
+        let total_running_time = exe.total_running_time as i64;
+        let last_seen_time = exe.last_seen_time.map(|v| v as i64);
+        let change_time = exe.change_time as i64;
+        let identity = exe.identity.as_ref().map(ToString::to_string);
+        sqlx::query!(
+            "INSERT OR REPLACE INTO exes (path, total_running_time, last_seen_time, running, change_time, identity) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            path,
+            total_running_time,
+            last_seen_time,
+            exe.running,
+            change_time,
+            identity
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for map in &state.maps {
+        let path = map.path.as_os_str().as_bytes();
+        let offset = map.offset as i64;
+        let length = map.length as i64;
+        let update_time = map.update_time as i64;
+        let device = map.identity.map(|id| id.device as i64);
+        let inode = map.identity.map(|id| id.inode as i64);
+        let mtime_ns = map.identity.map(|id| id.mtime_ns);
+        sqlx::query!(
+            "INSERT OR REPLACE INTO maps (path, offset, length, update_time, device, inode, mtime_ns) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            path,
+            offset,
+            length,
+            update_time,
+            device,
+            inode,
+            mtime_ns
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for map in &state.exe_maps {
+        let exe_path = map.exe_path.as_os_str().as_bytes();
+        let map_path = map.map_key.path.as_os_str().as_bytes();
+        let map_offset = map.map_key.offset as i64;
+        let map_length = map.map_key.length as i64;
+        let prob = map.prob as f64;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO exe_maps (exe_path, map_path, map_offset, map_length, prob) \
+             VALUES (?, ?, ?, ?, ?)",
+            exe_path,
+            map_path,
+            map_offset,
+            map_length,
+            prob
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for markov in &state.markov_edges {
+        let ttl: Vec<u8> = rkyv::to_bytes::<rkyv::rancor::Error>(&markov.time_to_leave)
+            .map_err(|err| Error::RkyvSerialize(err.to_string()))?
+            .into();
+        let tp: Vec<u8> = rkyv::to_bytes::<rkyv::rancor::Error>(&markov.transition_prob)
+            .map_err(|err| Error::RkyvSerialize(err.to_string()))?
+            .into();
+        let left: Vec<u8> = rkyv::to_bytes::<rkyv::rancor::Error>(&markov.state_last_left)
+            .map_err(|err| Error::RkyvSerialize(err.to_string()))?
+            .into();
+        let exe_a = markov.exe_a.as_os_str().as_bytes();
+        let exe_b = markov.exe_b.as_os_str().as_bytes();
+        let both_running_time = markov.both_running_time as i64;
+        let edge_state = markov.state.index() as i64;
+        let last_change_time = markov.last_change_time as i64;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO markovs (exe_a, exe_b, state, last_change_time, state_last_left, \
+             time_to_leave, transition_prob, both_running_time) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            exe_a,
+            exe_b,
+            edge_state,
+            last_change_time,
+            left,
+            ttl,
+            tp,
+            both_running_time
+        )
+        .execute(&mut *conn)
+        .await?;
     }
+    Ok(())
+}
+
+/// Paths are stored as their raw bytes, which need not be UTF-8.
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+async fn delete_edge(conn: &mut SqliteConnection, exe_a: &Path, exe_b: &Path) -> Result<(), Error> {
+    let exe_a = exe_a.as_os_str().as_bytes();
+    let exe_b = exe_b.as_os_str().as_bytes();
+    sqlx::query!(
+        "DELETE FROM markovs WHERE exe_a = ? AND exe_b = ?",
+        exe_a,
+        exe_b
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
 }
 
 #[async_trait]
 impl StateRepository for SqliteRepository {
     async fn load(&self) -> Result<StoresSnapshot, Error> {
-        self.load_snapshot().await
+        match self.load_snapshot().await {
+            Ok(snapshot) => Ok(snapshot),
+            // Backups come from the same or an older build.
+            Err(err @ Error::UnsupportedSnapshotVersion { .. }) => Err(err),
+            Err(err) => {
+                warn!(%err, path = %self.path.display(), "state is unreadable; trying backups");
+                self.load_backup().await.ok_or(err)
+            }
+        }
     }
 
     async fn save(&self, snapshot: &StoresSnapshot) -> Result<(), Error> {
         self.save_snapshot(snapshot).await
     }
+
+    async fn save_delta(&self, delta: &SnapshotDelta) -> Result<bool, Error> {
+        self.save_snapshot_delta(delta).await?;
+        Ok(true)
+    }
+
+    async fn remove(&self) -> Result<(), Error> {
+        if self.lock.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("database opened read-only: {}", self.path.display()),
+            )
+            .into());
+        }
+        self.pool.close().await;
+        backup::remove_all(&self.path, &["-wal", "-shm", ".lock"])?;
+        debug!(path = %self.path.display(), "state removed");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[tokio::test]
+    async fn copies_go_to_any_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = SqliteRepository::new(dir.path().join("state.db"))
+            .await
+            .unwrap();
+        let target = dir.path().join(OsStr::from_bytes(b"caf\xe9.db.1"));
+
+        vacuum_into(&repo.pool, &target).await.unwrap();
+        assert!(target.is_file());
+    }
 }
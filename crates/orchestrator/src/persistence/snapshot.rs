@@ -1,25 +1,34 @@
 #![forbid(unsafe_code)]
 
-use crate::domain::MapKey;
-use std::path::PathBuf;
+use crate::domain::{ExeIdentity, FileIdentity, MapKey, MarkovState};
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-pub const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 3;
 
-#[derive(Debug, Clone)]
+/// Recorded as [`SnapshotMeta::app_version`] by every save.
+pub const APP_VERSION: &str = concat!("preload-rs ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoresSnapshot {
     pub meta: SnapshotMeta,
     pub state: StateSnapshot,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotMeta {
     pub schema_version: u32,
+    #[serde(default)]
     pub app_version: Option<String>,
+    /// Stored as seconds since the Unix epoch.
+    #[serde(default, with = "unix_seconds")]
     pub created_at: Option<SystemTime>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateSnapshot {
     pub model_time: u64,
     pub last_accounting_time: u64,
@@ -29,33 +38,275 @@ pub struct StateSnapshot {
     pub markov_edges: Vec<MarkovRecord>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExeRecord {
+    #[serde(with = "config::path_as_text")]
     pub path: PathBuf,
     pub total_running_time: u64,
     pub last_seen_time: Option<u64>,
+    #[serde(default)]
+    pub running: bool,
+    /// Model time `running` last flipped.
+    #[serde(default)]
+    pub change_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<ExeIdentity>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapRecord {
+    #[serde(with = "config::path_as_text")]
     pub path: PathBuf,
     pub offset: u64,
     pub length: u64,
     pub update_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<FileIdentity>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExeMapRecord {
+    #[serde(with = "config::path_as_text")]
     pub exe_path: PathBuf,
     pub map_key: MapKey,
     pub prob: f32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarkovRecord {
+    #[serde(with = "config::path_as_text")]
     pub exe_a: PathBuf,
+    #[serde(with = "config::path_as_text")]
     pub exe_b: PathBuf,
+    #[serde(default)]
+    pub state: MarkovState,
+    #[serde(default)]
+    pub last_change_time: u64,
+    #[serde(default)]
+    pub state_last_left: [u64; 4],
     pub time_to_leave: [f32; 4],
     pub transition_prob: [[f32; 4]; 4],
     pub both_running_time: u64,
 }
+
+/// The rows that changed since the previous save, for backends that can
+/// update a stored snapshot in place.
+///
+/// `changed` holds rows to insert or replace. Removals are applied first:
+/// removing an exe or map also removes its exe-map rows (and, for exes, its
+/// Markov edges), so rows re-added afterwards are always in `changed`.
+#[derive(Debug, Clone)]
+pub struct SnapshotDelta {
+    pub meta: SnapshotMeta,
+    pub changed: StateSnapshot,
+    pub removed_exes: Vec<PathBuf>,
+    pub removed_maps: Vec<MapKey>,
+    /// Markov edges between exes that are still known.
+    pub removed_edges: Vec<(PathBuf, PathBuf)>,
+}
+
+impl SnapshotDelta {
+    /// Number of rows written or removed.
+    pub fn len(&self) -> usize {
+        let changed = &self.changed;
+        changed.exes.len()
+            + changed.maps.len()
+            + changed.exe_maps.len()
+            + changed.markov_edges.len()
+            + self.removed_exes.len()
+            + self.removed_maps.len()
+            + self.removed_edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl StoresSnapshot {
+    /// A snapshot of a model that has learned nothing yet.
+    pub fn empty() -> Self {
+        Self {
+            meta: SnapshotMeta {
+                schema_version: SNAPSHOT_SCHEMA_VERSION,
+                app_version: None,
+                created_at: None,
+            },
+            state: StateSnapshot {
+                model_time: 0,
+                last_accounting_time: 0,
+                exes: Vec::new(),
+                maps: Vec::new(),
+                exe_maps: Vec::new(),
+                markov_edges: Vec::new(),
+            },
+        }
+    }
+
+    /// Check that the snapshot describes a model that can be loaded: keys are
+    /// unique, every exe-map link and Markov edge refers to a known exe and
+    /// map, and all statistics are finite.
+    pub fn validate(&self) -> Result<(), Error> {
+        let state = &self.state;
+        let invalid = |what: String| Err(Error::InvalidSnapshot(what));
+
+        let mut exes: HashSet<&Path> = HashSet::new();
+        for exe in &state.exes {
+            if !exes.insert(&exe.path) {
+                return invalid(format!("duplicate exe {}", exe.path.display()));
+            }
+        }
+
+        let mut maps: HashSet<MapKey> = HashSet::new();
+        for map in &state.maps {
+            if !maps.insert(MapKey::new(map.path.clone(), map.offset, map.length)) {
+                return invalid(format!(
+                    "duplicate map {} [{}+{}]",
+                    map.path.display(),
+                    map.offset,
+                    map.length
+                ));
+            }
+        }
+
+        for (index, link) in state.exe_maps.iter().enumerate() {
+            if !exes.contains(link.exe_path.as_path()) {
+                return invalid(format!(
+                    "exe_maps[{index}] refers to unknown exe {}",
+                    link.exe_path.display()
+                ));
+            }
+            if !maps.contains(&link.map_key) {
+                return invalid(format!(
+                    "exe_maps[{index}] refers to unknown map {} [{}+{}]",
+                    link.map_key.path.display(),
+                    link.map_key.offset,
+                    link.map_key.length
+                ));
+            }
+            if !(0.0..=1.0).contains(&link.prob) {
+                return invalid(format!(
+                    "exe_maps[{index}] has probability {} outside [0, 1]",
+                    link.prob
+                ));
+            }
+        }
+
+        for (index, edge) in state.markov_edges.iter().enumerate() {
+            for exe in [&edge.exe_a, &edge.exe_b] {
+                if !exes.contains(exe.as_path()) {
+                    return invalid(format!(
+                        "markov_edges[{index}] refers to unknown exe {}",
+                        exe.display()
+                    ));
+                }
+            }
+            if edge.exe_a == edge.exe_b {
+                return invalid(format!(
+                    "markov_edges[{index}] connects {} to itself",
+                    edge.exe_a.display()
+                ));
+            }
+            let finite = edge.time_to_leave.iter().all(|v| v.is_finite())
+                && edge.transition_prob.iter().flatten().all(|v| v.is_finite());
+            if !finite {
+                return invalid(format!("markov_edges[{index}] has non-finite statistics"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop whatever [`StoresSnapshot::validate`] would reject: repeated
+    /// keys (the first record wins), exe-map links and Markov edges to exes
+    /// or maps that are not listed, out-of-range probabilities and non-finite
+    /// statistics. Returns a description of each dropped record.
+    pub fn repair(&mut self) -> Vec<String> {
+        let state = &mut self.state;
+        let mut dropped = Vec::new();
+
+        let mut exes: HashSet<PathBuf> = HashSet::new();
+        state.exes.retain(|exe| {
+            let keep = exes.insert(exe.path.clone());
+            if !keep {
+                dropped.push(format!("duplicate exe {}", exe.path.display()));
+            }
+            keep
+        });
+
+        let mut maps: HashSet<MapKey> = HashSet::new();
+        state.maps.retain(|map| {
+            let keep = maps.insert(MapKey::new(map.path.clone(), map.offset, map.length));
+            if !keep {
+                dropped.push(format!(
+                    "duplicate map {} [{}+{}]",
+                    map.path.display(),
+                    map.offset,
+                    map.length
+                ));
+            }
+            keep
+        });
+
+        state.exe_maps.retain(|link| {
+            let keep = exes.contains(&link.exe_path)
+                && maps.contains(&link.map_key)
+                && (0.0..=1.0).contains(&link.prob);
+            if !keep {
+                dropped.push(format!(
+                    "exe map {} -> {} [{}+{}] (prob {})",
+                    link.exe_path.display(),
+                    link.map_key.path.display(),
+                    link.map_key.offset,
+                    link.map_key.length,
+                    link.prob
+                ));
+            }
+            keep
+        });
+
+        state.markov_edges.retain(|edge| {
+            let keep = exes.contains(&edge.exe_a)
+                && exes.contains(&edge.exe_b)
+                && edge.exe_a != edge.exe_b
+                && edge.time_to_leave.iter().all(|v| v.is_finite())
+                && edge.transition_prob.iter().flatten().all(|v| v.is_finite());
+            if !keep {
+                dropped.push(format!(
+                    "markov edge {} <-> {}",
+                    edge.exe_a.display(),
+                    edge.exe_b.display()
+                ));
+            }
+            keep
+        });
+
+        dropped
+    }
+}
+
+/// `Option<SystemTime>` as whole seconds since the Unix epoch.
+mod unix_seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime};
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let secs = time
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+        match secs {
+            Some(secs) => serializer.serialize_some(&secs),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        let secs = Option::<u64>::deserialize(deserializer)?;
+        Ok(secs.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+}
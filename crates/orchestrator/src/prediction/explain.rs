@@ -0,0 +1,38 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::MarkovState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// How a predictor arrived at an exe's score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Explanation {
+    #[serde(with = "config::path_as_text")]
+    pub path: PathBuf,
+    pub running: bool,
+    pub score: f32,
+    /// Strongest contribution first.
+    pub edges: Vec<EdgeContribution>,
+}
+
+/// One Markov edge's share of an [`Explanation`].
+///
+/// Edges combine as independent events:
+/// `score = 1 - Π(1 - probability)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeContribution {
+    /// The exe at the other end of the edge.
+    #[serde(with = "config::path_as_text")]
+    pub other: PathBuf,
+    pub other_running: bool,
+    /// Current edge state, oriented so that A is the explained exe.
+    pub state: MarkovState,
+    /// Chance the edge moves to a state where the explained exe runs within
+    /// one cycle.
+    pub transition: f32,
+    /// Absolute running-time correlation of the two exes; 1 when
+    /// `model.use_correlation` is off.
+    pub correlation: f32,
+    /// `transition * correlation`, clamped to `[0, 1]`.
+    pub probability: f32,
+}
@@ -1,7 +1,9 @@
 #![forbid(unsafe_code)]
 
+mod explain;
 mod predictor;
 mod types;
 
+pub use explain::{EdgeContribution, Explanation};
 pub use predictor::{MarkovPredictor, Predictor};
 pub use types::{Prediction, PredictionSummary};
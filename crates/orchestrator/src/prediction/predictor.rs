@@ -1,14 +1,21 @@
 #![forbid(unsafe_code)]
 
-use crate::domain::{ExeId, MarkovState};
-use crate::prediction::Prediction;
-use crate::stores::Stores;
+use crate::domain::{ExeId, MarkovEdge, MarkovState};
+use crate::prediction::{EdgeContribution, Explanation, Prediction};
+use crate::stores::{EdgeKey, Stores};
 use config::Config;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 pub trait Predictor: Send + Sync {
     /// Produce exe and map scores for the next cycle.
     fn predict(&self, stores: &Stores) -> Prediction;
+
+    /// Break down the score of `exe`. Predictors that cannot explain
+    /// themselves return `None`.
+    fn explain(&self, _stores: &Stores, _exe: ExeId) -> Option<Explanation> {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +73,27 @@ impl MarkovPredictor {
             edge.transition_prob[state_ix][target_ix] + edge.transition_prob[state_ix][both_ix];
         (p_state_change * p_runs_next).clamp(0.0, 1.0)
     }
+
+    /// What `edge` contributes to the chance that its `target` end
+    /// (`AOnly` or `BOnly`) starts next cycle: `(transition, correlation, p)`.
+    fn contribution(
+        &self,
+        stores: &Stores,
+        key: EdgeKey,
+        edge: &MarkovEdge,
+        state: MarkovState,
+        target: MarkovState,
+    ) -> (f32, f32, f32) {
+        let transition = Self::p_needed(edge, state, target, self.cycle_secs);
+        let correlation = if self.use_correlation {
+            self.correlation(stores, key.a(), key.b(), edge.both_running_time)
+                .abs()
+        } else {
+            1.0
+        };
+        let p = (transition * correlation).clamp(0.0, 1.0);
+        (transition, correlation, p)
+    }
 }
 
 impl Predictor for MarkovPredictor {
@@ -81,24 +109,12 @@ impl Predictor for MarkovPredictor {
             let state = MarkovState::from_running(a_running, b_running);
 
             if !a_running {
-                let base = Self::p_needed(edge, state, MarkovState::AOnly, self.cycle_secs);
-                let corr = if self.use_correlation {
-                    self.correlation(stores, a, b, edge.both_running_time).abs()
-                } else {
-                    1.0
-                };
-                let p = (base * corr).clamp(0.0, 1.0);
+                let (_, _, p) = self.contribution(stores, key, edge, state, MarkovState::AOnly);
                 let entry = not_needed.entry(a).or_insert(1.0);
                 *entry *= 1.0 - p;
             }
             if !b_running {
-                let base = Self::p_needed(edge, state, MarkovState::BOnly, self.cycle_secs);
-                let corr = if self.use_correlation {
-                    self.correlation(stores, a, b, edge.both_running_time).abs()
-                } else {
-                    1.0
-                };
-                let p = (base * corr).clamp(0.0, 1.0);
+                let (_, _, p) = self.contribution(stores, key, edge, state, MarkovState::BOnly);
                 let entry = not_needed.entry(b).or_insert(1.0);
                 *entry *= 1.0 - p;
             }
@@ -116,12 +132,14 @@ impl Predictor for MarkovPredictor {
             }
         }
 
-        // Map scores derived from exe scores (Pr map needed).
+        // Map scores derived from exe scores, weighted by how often each exe
+        // actually maps the file (Pr map needed).
         for (map_id, _map) in stores.maps.iter() {
             let mut not_needed_prob = 1.0;
             for exe_id in stores.exe_maps.exes_for_map(map_id) {
                 let exe_score = prediction.exe_scores.get(&exe_id).copied().unwrap_or(0.0);
-                not_needed_prob *= 1.0 - exe_score;
+                let usage = stores.exe_maps.prob(exe_id, map_id).unwrap_or(1.0);
+                not_needed_prob *= 1.0 - exe_score * usage;
             }
             let needed = (1.0 - not_needed_prob).clamp(0.0, 1.0);
             prediction.map_scores.insert(map_id, needed);
@@ -129,6 +147,55 @@ impl Predictor for MarkovPredictor {
 
         prediction
     }
+
+    fn explain(&self, stores: &Stores, exe: ExeId) -> Option<Explanation> {
+        let running_of = |id: ExeId| stores.exes.get(id).is_some_and(|e| e.running);
+        let target = stores.exes.get(exe)?;
+
+        let mut edges = Vec::new();
+        let mut not_needed = 1.0;
+        for (key, edge) in stores.markov.iter() {
+            let (other, target_state) = if key.a() == exe {
+                (key.b(), MarkovState::AOnly)
+            } else if key.b() == exe {
+                (key.a(), MarkovState::BOnly)
+            } else {
+                continue;
+            };
+            let Some(other_exe) = stores.exes.get(other) else {
+                continue;
+            };
+            let state = MarkovState::from_running(running_of(key.a()), running_of(key.b()));
+            let (transition, correlation, probability) =
+                self.contribution(stores, key, edge, state, target_state);
+            not_needed *= 1.0 - probability;
+            edges.push(EdgeContribution {
+                other: other_exe.key.path().clone(),
+                other_running: other_exe.running,
+                state: MarkovState::from_running(target.running, other_exe.running),
+                transition,
+                correlation,
+                probability,
+            });
+        }
+        edges.sort_by(|x, y| {
+            y.probability
+                .partial_cmp(&x.probability)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let score = if target.running {
+            0.0
+        } else {
+            (1.0 - not_needed).clamp(0.0, 1.0)
+        };
+        Some(Explanation {
+            path: target.key.path().clone(),
+            running: target.running,
+            score,
+            edges,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +286,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn map_scores_are_weighted_by_usage_probability() {
+        let mut stores = Stores::default();
+        let a = stores.ensure_exe(ExeKey::new("/exe/a"));
+        let b = stores.ensure_exe(ExeKey::new("/exe/b"));
+        stores.exes.get_mut(a).unwrap().running = true;
+        stores.ensure_markov_edge(a, b, 0, MarkovState::AOnly);
+        let edge = stores.markov.get_mut(EdgeKey::new(a, b)).unwrap();
+        edge.time_to_leave[MarkovState::AOnly.index()] = 10.0;
+        edge.transition_prob[MarkovState::AOnly.index()][MarkovState::Both.index()] = 1.0;
+
+        let common = stores.ensure_map(MapSegment::new("/lib/common.so", 0, 4096, 0));
+        let plugin = stores.ensure_map(MapSegment::new("/lib/plugin.so", 0, 4096, 0));
+        stores.exe_maps.attach_with_prob(b, common, 1.0);
+        stores.exe_maps.attach_with_prob(b, plugin, 0.25);
+
+        let mut config = Config::default();
+        config.model.use_correlation = false;
+        let prediction = MarkovPredictor::new(&config).predict(&stores);
+
+        let exe_score = prediction.exe_scores[&b];
+        assert!(exe_score > 0.0);
+        assert!((prediction.map_scores[&common] - exe_score).abs() < 1e-6);
+        assert!((prediction.map_scores[&plugin] - exe_score * 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn explanation_reproduces_the_predicted_score() {
+        let mut stores = Stores::default();
+        let a = stores.ensure_exe(ExeKey::new("/exe/a"));
+        let b = stores.ensure_exe(ExeKey::new("/exe/b"));
+        let c = stores.ensure_exe(ExeKey::new("/exe/c"));
+        stores.exes.get_mut(a).unwrap().running = true;
+        // Uniform statistics, so the result does not depend on edge orientation.
+        for (other, ttl) in [(a, 10.0), (c, 40.0)] {
+            stores.ensure_markov_edge(other, b, 0, MarkovState::Neither);
+            let edge = stores.markov.get_mut(EdgeKey::new(other, b)).unwrap();
+            edge.time_to_leave = [ttl; 4];
+            edge.transition_prob = [[0.25; 4]; 4];
+        }
+
+        let mut config = Config::default();
+        config.model.use_correlation = false;
+        let predictor = MarkovPredictor::new(&config);
+        let prediction = predictor.predict(&stores);
+        let explanation = predictor.explain(&stores, b).unwrap();
+
+        assert_eq!(explanation.edges.len(), 2);
+        assert!((explanation.score - prediction.exe_scores[&b]).abs() < 1e-6);
+        assert!(explanation.score > 0.0);
+        let strongest = &explanation.edges[0];
+        assert!(strongest.probability >= explanation.edges[1].probability);
+        assert_eq!(strongest.correlation, 1.0);
+
+        let running = predictor.explain(&stores, a).unwrap();
+        assert!(running.running);
+        assert_eq!(running.score, 0.0);
+    }
+
     fn edge_strategy() -> impl Strategy<Value = (u8, u8, [f32; 4], [[f32; 4]; 4], u64)> {
         (
             0u8..16,
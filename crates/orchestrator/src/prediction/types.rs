@@ -1,6 +1,7 @@
 #![forbid(unsafe_code)]
 
 use crate::domain::{ExeId, MapId};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug, Default, Clone)]
@@ -9,7 +10,7 @@ pub struct Prediction {
     pub map_scores: HashMap<MapId, f32>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PredictionSummary {
     pub num_exes_scored: usize,
     pub num_maps_scored: usize,
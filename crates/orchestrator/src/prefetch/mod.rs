@@ -3,7 +3,9 @@
 mod plan;
 mod planner;
 mod prefetcher;
+mod validator;
 
 pub use plan::{PrefetchPlan, PrefetchReport};
 pub use planner::{GreedyPrefetchPlanner, PrefetchPlanner};
 pub use prefetcher::{NoopPrefetcher, PosixFadvisePrefetcher, Prefetcher};
+pub use validator::{FileMapValidator, MapValidator, NoopMapValidator, ValidationReport};
@@ -2,6 +2,7 @@
 
 use crate::domain::MapId;
 use crate::domain::MapKey;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct PrefetchPlan {
@@ -10,7 +11,7 @@ pub struct PrefetchPlan {
     pub budget_bytes: u64,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PrefetchReport {
     pub num_maps: usize,
     pub total_bytes: u64,
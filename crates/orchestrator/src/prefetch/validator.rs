@@ -0,0 +1,131 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{FileIdentity, MapId, MapKey};
+use crate::prediction::Prediction;
+use crate::stores::Stores;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+pub trait MapValidator: Send + Sync {
+    /// Check the maps a prediction would prefetch against their backing files
+    /// before planning, removing stale ones from both `prediction` and
+    /// `stores`.
+    fn validate(&self, prediction: &mut Prediction, stores: &mut Stores) -> ValidationReport;
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Maps whose file identity was unknown and has now been recorded.
+    pub refreshed: usize,
+    /// Maps whose file disappeared or was replaced since it was seen.
+    pub dropped: Vec<MapKey>,
+}
+
+#[derive(Debug, Default)]
+pub struct NoopMapValidator;
+
+impl MapValidator for NoopMapValidator {
+    fn validate(&self, _prediction: &mut Prediction, _stores: &mut Stores) -> ValidationReport {
+        ValidationReport::default()
+    }
+}
+
+/// Stats the file behind every positively scored map.
+///
+/// A map is dropped when its file is gone or its device, inode or mtime no
+/// longer match what the scanner recorded; the next scan re-learns it if the
+/// new file is still mapped. Maps stored without an identity (older state,
+/// unreadable paths at scan time) adopt the current one.
+#[derive(Debug, Default)]
+pub struct FileMapValidator;
+
+impl MapValidator for FileMapValidator {
+    fn validate(&self, prediction: &mut Prediction, stores: &mut Stores) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let mut stale: Vec<MapId> = Vec::new();
+
+        for (map_id, score) in &prediction.map_scores {
+            if *score <= 0.0 {
+                continue;
+            }
+            let Some(map) = stores.maps.get(*map_id) else {
+                continue;
+            };
+            match (FileIdentity::of(&map.path), map.identity) {
+                (Ok(current), Some(stored)) if current == stored => {}
+                (Ok(current), None) => {
+                    stores.maps.set_identity(*map_id, current);
+                    report.refreshed += 1;
+                }
+                (current, stored) => {
+                    debug!(path = ?map.path, ?stored, ?current, "map backing file changed");
+                    stale.push(*map_id);
+                }
+            }
+        }
+
+        for map_id in stale {
+            prediction.map_scores.remove(&map_id);
+            if let Some(key) = stores.remove_map(map_id) {
+                report.dropped.push(key);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ExeKey, MapSegment};
+
+    #[test]
+    fn drops_replaced_and_missing_files_and_adopts_unknown_identities() {
+        let dir = tempfile::tempdir().unwrap();
+        let replaced = dir.path().join("replaced.so");
+        let unknown = dir.path().join("unknown.so");
+        let unscored = dir.path().join("gone-but-unscored.so");
+        std::fs::write(&replaced, b"old").unwrap();
+        std::fs::write(&unknown, b"lib").unwrap();
+
+        let mut stores = Stores::default();
+        let exe = stores.ensure_exe(ExeKey::new("/usr/bin/app"));
+        let old_identity = FileIdentity::of(&replaced).ok();
+        let replaced_id =
+            stores.ensure_map(MapSegment::new(&replaced, 0, 4096, 0).with_identity(old_identity));
+        let missing_id =
+            stores.ensure_map(MapSegment::new(dir.path().join("missing.so"), 0, 4096, 0));
+        let unknown_id = stores.ensure_map(MapSegment::new(&unknown, 0, 4096, 0));
+        let unscored_id = stores.ensure_map(MapSegment::new(&unscored, 0, 4096, 0));
+        for map in [replaced_id, missing_id, unknown_id, unscored_id] {
+            stores.attach_map(exe, map);
+        }
+
+        // Upgrades write a new file and rename it over the old one.
+        let staged = dir.path().join("replaced.so.new");
+        std::fs::write(&staged, b"new").unwrap();
+        std::fs::rename(&staged, &replaced).unwrap();
+
+        let mut prediction = Prediction::default();
+        for map in [replaced_id, missing_id, unknown_id] {
+            prediction.map_scores.insert(map, 0.5);
+        }
+        prediction.map_scores.insert(unscored_id, 0.0);
+
+        let report = FileMapValidator.validate(&mut prediction, &mut stores);
+
+        assert_eq!(report.refreshed, 1);
+        assert_eq!(report.dropped.len(), 2);
+        assert!(stores.maps.get(replaced_id).is_none());
+        assert!(stores.maps.get(missing_id).is_none());
+        assert!(!prediction.map_scores.contains_key(&replaced_id));
+        assert_eq!(
+            stores.maps.get(unknown_id).unwrap().identity,
+            FileIdentity::of(&unknown).ok()
+        );
+        assert!(stores.maps.get(unscored_id).is_some());
+        let attached: Vec<_> = stores.exe_maps.maps_for_exe(exe).collect();
+        assert_eq!(attached.len(), 2);
+    }
+}
@@ -28,6 +28,14 @@ impl ActiveSet {
         removed
     }
 
+    pub fn last_seen(&self, exe_id: ExeId) -> Option<u64> {
+        self.last_seen.get(&exe_id).copied()
+    }
+
+    pub fn remove(&mut self, exe_id: ExeId) {
+        self.last_seen.remove(&exe_id);
+    }
+
     pub fn exes(&self) -> HashSet<ExeId> {
         self.last_seen.keys().copied().collect()
     }
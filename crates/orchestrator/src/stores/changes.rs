@@ -0,0 +1,116 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{ExeId, ExeKey, MapId, MapKey};
+use crate::stores::{EdgeKey, Stores};
+use std::collections::HashSet;
+
+/// What changed in the model since the last [`Stores::take_changes`], so a
+/// save can write only those rows.
+///
+/// Changed entries are listed by id and may have been removed since. Removed
+/// exes and maps are listed by key, and their exe-map pairs and Markov edges
+/// are implied rather than listed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StoreChanges {
+    pub exes: HashSet<ExeId>,
+    pub removed_exes: HashSet<ExeKey>,
+    pub maps: HashSet<MapId>,
+    pub removed_maps: HashSet<MapKey>,
+    pub exe_maps: HashSet<(ExeId, MapId)>,
+    pub edges: HashSet<EdgeKey>,
+    pub removed_edges: HashSet<EdgeKey>,
+}
+
+impl StoreChanges {
+    pub fn is_empty(&self) -> bool {
+        self.exes.is_empty()
+            && self.removed_exes.is_empty()
+            && self.maps.is_empty()
+            && self.removed_maps.is_empty()
+            && self.exe_maps.is_empty()
+            && self.edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+impl Stores {
+    /// Collect and reset the change tracking of every store.
+    pub fn take_changes(&mut self) -> StoreChanges {
+        let (exes, removed_exes) = self.exes.take_changes();
+        let (maps, removed_maps) = self.maps.take_changes();
+        let (edges, removed_edges) = self.markov.take_changes();
+        StoreChanges {
+            exes,
+            removed_exes,
+            maps,
+            removed_maps,
+            exe_maps: self.exe_maps.take_changes(),
+            edges,
+            removed_edges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MapSegment, MarkovState};
+
+    #[test]
+    fn reads_are_not_changes() {
+        let mut stores = Stores::default();
+        let a = stores.ensure_exe(ExeKey::new("/usr/bin/a"));
+        let b = stores.ensure_exe(ExeKey::new("/usr/bin/b"));
+        let map = stores.ensure_map(MapSegment::new("/usr/lib/a.so", 0, 4096, 10));
+        stores.attach_map(a, map);
+        stores.ensure_markov_edge(a, b, 0, MarkovState::Neither);
+
+        let changes = stores.take_changes();
+        assert_eq!(changes.exes, HashSet::from([a, b]));
+        assert_eq!(changes.maps, HashSet::from([map]));
+        assert_eq!(changes.exe_maps, HashSet::from([(a, map)]));
+        assert_eq!(changes.edges.len(), 1);
+
+        // Seeing the same map again at an older time, reattaching it or
+        // ending a run that used it changes nothing that is persisted.
+        stores.maps.touch(map, 5);
+        stores.attach_map(a, map);
+        stores.exe_maps.end_run(a, 0.5);
+        let _ = stores.exes.get(a);
+        let _ = stores.markov.iter().count();
+        assert!(stores.take_changes().is_empty());
+
+        stores.maps.touch(map, 20);
+        stores.exe_maps.end_run(a, 0.5);
+        let changes = stores.take_changes();
+        assert_eq!(changes.maps, HashSet::from([map]));
+        assert_eq!(changes.exe_maps, HashSet::from([(a, map)]));
+    }
+
+    #[test]
+    fn removals_are_listed_by_key() {
+        let mut stores = Stores::default();
+        let a = stores.ensure_exe(ExeKey::new("/usr/bin/a"));
+        let b = stores.ensure_exe(ExeKey::new("/usr/bin/b"));
+        let map = stores.ensure_map(MapSegment::new("/usr/lib/a.so", 0, 4096, 10));
+        stores.ensure_markov_edge(a, b, 0, MarkovState::Neither);
+        stores.active.update([a, b], 0);
+        let _ = stores.take_changes();
+
+        stores.markov.prune_inactive(&HashSet::from([a]));
+        stores.remove_map(map);
+        stores.remove_exe(b);
+
+        let changes = stores.take_changes();
+        assert_eq!(changes.removed_edges, HashSet::from([EdgeKey::new(a, b)]));
+        assert_eq!(
+            changes.removed_maps,
+            HashSet::from([MapKey::new("/usr/lib/a.so", 0, 4096)])
+        );
+        assert_eq!(
+            changes.removed_exes,
+            HashSet::from([ExeKey::new("/usr/bin/b")])
+        );
+        assert!(changes.exes.is_empty());
+    }
+}
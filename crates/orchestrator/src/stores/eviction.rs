@@ -0,0 +1,246 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{ExeId, ExeKey, MapId, MapKey};
+use crate::stores::Stores;
+use std::collections::HashSet;
+
+/// Bounds on the size and staleness of the model. `None` disables a limit.
+///
+/// Ages are in model-time seconds. Running exes and the maps they use are
+/// never evicted, so a limit can be exceeded while they stay running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvictionLimits {
+    pub max_exes: Option<usize>,
+    pub max_maps: Option<usize>,
+    pub max_exe_age: Option<u64>,
+    pub max_map_age: Option<u64>,
+}
+
+impl EvictionLimits {
+    pub fn is_unbounded(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What a call to [`Stores::evict`] removed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Evicted {
+    pub exes: Vec<ExeKey>,
+    pub maps: Vec<MapKey>,
+}
+
+impl Stores {
+    /// Remove stale exes and maps until `limits` hold.
+    ///
+    /// Exes go first, oldest `last_seen_time` first, taking their index entries,
+    /// Markov edges and active-set membership with them. Maps left without any
+    /// exe are dropped next, then maps beyond `max_map_age`/`max_maps`, oldest
+    /// `update_time` first.
+    pub fn evict(&mut self, limits: &EvictionLimits, now: u64) -> Evicted {
+        let mut evicted = Evicted::default();
+        if limits.is_unbounded() {
+            return evicted;
+        }
+
+        // Idle exes, least recently seen first.
+        let mut idle: Vec<(u64, ExeId)> = self
+            .exes
+            .iter()
+            .filter(|(_, exe)| !exe.running)
+            .map(|(id, exe)| (exe.last_seen_time.unwrap_or(0), id))
+            .collect();
+        idle.sort_unstable();
+
+        let mut excess = limits
+            .max_exes
+            .map_or(0, |max| self.exes.len().saturating_sub(max));
+        for (last_seen, exe_id) in idle {
+            let too_old = limits
+                .max_exe_age
+                .is_some_and(|age| now.saturating_sub(last_seen) > age);
+            if !too_old && excess == 0 {
+                break;
+            }
+            excess = excess.saturating_sub(1);
+            if let Some(exe) = self.remove_exe(exe_id) {
+                evicted.exes.push(exe);
+            }
+        }
+
+        // Maps still needed by a running exe stay regardless of limits.
+        let pinned: HashSet<MapId> = self
+            .exes
+            .iter()
+            .filter(|(_, exe)| exe.running)
+            .flat_map(|(id, _)| self.exe_maps.maps_for_exe(id))
+            .collect();
+
+        let mut candidates: Vec<(bool, u64, MapId)> = self
+            .maps
+            .iter()
+            .filter(|(id, _)| !pinned.contains(id))
+            .map(|(id, map)| {
+                let attached = self.exe_maps.exes_for_map(id).next().is_some();
+                (attached, map.update_time, id)
+            })
+            .collect();
+        // Orphans first, then the least recently updated.
+        candidates.sort_unstable();
+
+        let mut excess = limits
+            .max_maps
+            .map_or(0, |max| self.maps.len().saturating_sub(max));
+        for (attached, update_time, map_id) in candidates {
+            let too_old = limits
+                .max_map_age
+                .is_some_and(|age| now.saturating_sub(update_time) > age);
+            if attached && !too_old && excess == 0 {
+                break;
+            }
+            excess = excess.saturating_sub(1);
+            if let Some(map) = self.remove_map(map_id) {
+                evicted.maps.push(map);
+            }
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MapSegment, MarkovState};
+    use proptest::prelude::*;
+
+    fn limits_strategy() -> impl Strategy<Value = EvictionLimits> {
+        (
+            prop::option::of(0usize..12),
+            prop::option::of(0usize..12),
+            prop::option::of(0u64..1_000),
+            prop::option::of(0u64..1_000),
+        )
+            .prop_map(
+                |(max_exes, max_maps, max_exe_age, max_map_age)| EvictionLimits {
+                    max_exes,
+                    max_maps,
+                    max_exe_age,
+                    max_map_age,
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn eviction_keeps_stores_consistent(
+            exes in prop::collection::vec((any::<bool>(), prop::option::of(0u64..1_000)), 0..12),
+            map_times in prop::collection::vec(0u64..1_000, 0..12),
+            attachments in prop::collection::vec((0u8..16, 0u8..16), 0..30),
+            edges in prop::collection::vec((0u8..16, 0u8..16), 0..20),
+            limits in limits_strategy(),
+            now in 0u64..2_000,
+        ) {
+            let mut stores = Stores::default();
+            let exe_ids: Vec<_> = exes
+                .iter()
+                .enumerate()
+                .map(|(i, (running, last_seen))| {
+                    let id = stores.ensure_exe(ExeKey::new(format!("/exe/{i}")));
+                    let exe = stores.exes.get_mut(id).unwrap();
+                    exe.running = *running;
+                    exe.last_seen_time = *last_seen;
+                    id
+                })
+                .collect();
+            let map_ids: Vec<_> = map_times
+                .iter()
+                .enumerate()
+                .map(|(i, time)| {
+                    stores.ensure_map(MapSegment::new(format!("/map/{i}"), 0, 4096, *time))
+                })
+                .collect();
+            if !exe_ids.is_empty() && !map_ids.is_empty() {
+                for (e, m) in attachments {
+                    stores.attach_map(
+                        exe_ids[e as usize % exe_ids.len()],
+                        map_ids[m as usize % map_ids.len()],
+                    );
+                }
+            }
+            if !exe_ids.is_empty() {
+                for (a, b) in edges {
+                    let a = exe_ids[a as usize % exe_ids.len()];
+                    let b = exe_ids[b as usize % exe_ids.len()];
+                    if a != b {
+                        stores.ensure_markov_edge(a, b, 0, MarkovState::Neither);
+                        stores.active.update([a, b], 0);
+                    }
+                }
+            }
+            let running_before: HashSet<ExeKey> = stores
+                .exes
+                .iter()
+                .filter(|(_, exe)| exe.running)
+                .map(|(_, exe)| exe.key.clone())
+                .collect();
+
+            let exes_before = stores.exes.len();
+            let maps_before = stores.maps.len();
+            let evicted = stores.evict(&limits, now);
+            prop_assert_eq!(stores.exes.len() + evicted.exes.len(), exes_before);
+            prop_assert_eq!(stores.maps.len() + evicted.maps.len(), maps_before);
+
+            // Running exes survive; everything else respects the limits.
+            for key in &running_before {
+                prop_assert!(stores.exes.id_by_key(key).is_some());
+            }
+            if let Some(max) = limits.max_exes {
+                prop_assert!(stores.exes.len() <= max.max(running_before.len()));
+            }
+            for (id, exe) in stores.exes.iter() {
+                if let (false, Some(age)) = (exe.running, limits.max_exe_age) {
+                    prop_assert!(now.saturating_sub(exe.last_seen_time.unwrap_or(0)) <= age);
+                }
+                for map_id in stores.exe_maps.maps_for_exe(id) {
+                    prop_assert!(stores.maps.get(map_id).is_some());
+                }
+            }
+            for (id, map) in stores.maps.iter() {
+                let mut owners = stores.exe_maps.exes_for_map(id).peekable();
+                if !limits.is_unbounded() {
+                    prop_assert!(owners.peek().is_some(), "orphan map survived");
+                }
+                for exe_id in owners {
+                    prop_assert!(stores.exes.get(exe_id).is_some());
+                }
+                let pinned = stores
+                    .exe_maps
+                    .exes_for_map(id)
+                    .any(|exe_id| stores.exes.get(exe_id).is_some_and(|exe| exe.running));
+                if let (false, Some(age)) = (pinned, limits.max_map_age) {
+                    prop_assert!(now.saturating_sub(map.update_time) <= age);
+                }
+            }
+            if let Some(max) = limits.max_maps {
+                let pinned = stores
+                    .maps
+                    .iter()
+                    .filter(|(id, _)| {
+                        stores
+                            .exe_maps
+                            .exes_for_map(*id)
+                            .any(|exe_id| stores.exes.get(exe_id).is_some_and(|exe| exe.running))
+                    })
+                    .count();
+                prop_assert!(stores.maps.len() <= max.max(pinned));
+            }
+            for (key, _) in stores.markov.iter() {
+                prop_assert!(stores.exes.get(key.a()).is_some());
+                prop_assert!(stores.exes.get(key.b()).is_some());
+            }
+            for exe_id in stores.active.exes() {
+                prop_assert!(stores.exes.get(exe_id).is_some());
+            }
+        }
+    }
+}
@@ -3,23 +3,90 @@
 use crate::domain::{ExeId, MapId};
 use std::collections::{HashMap, HashSet};
 
+/// Which maps each exe uses, and how likely each one is to be mapped in a run.
 #[derive(Debug, Default)]
 pub struct ExeMapIndex {
-    exe_to_maps: HashMap<ExeId, HashSet<MapId>>,
+    exe_to_maps: HashMap<ExeId, HashMap<MapId, ExeMapEntry>>,
     map_to_exes: HashMap<MapId, HashSet<ExeId>>,
+    /// Pairs added or whose probability moved since the last
+    /// [`ExeMapIndex::take_changes`]. Pairs removed with their exe or map are
+    /// not listed.
+    dirty: HashSet<(ExeId, MapId)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ExeMapEntry {
+    /// Decayed probability that the map is used when the exe runs.
+    prob: f32,
+    /// Observed during the exe's current run.
+    seen: bool,
 }
 
 impl ExeMapIndex {
+    /// Record that `exe_id` was observed using `map_id`. New pairs start out
+    /// certain; [`ExeMapIndex::end_run`] refines the probability.
     pub fn attach(&mut self, exe_id: ExeId, map_id: MapId) {
-        self.exe_to_maps.entry(exe_id).or_default().insert(map_id);
+        self.exe_to_maps
+            .entry(exe_id)
+            .or_default()
+            .entry(map_id)
+            .and_modify(|entry| entry.seen = true)
+            .or_insert_with(|| {
+                self.dirty.insert((exe_id, map_id));
+                ExeMapEntry {
+                    prob: 1.0,
+                    seen: true,
+                }
+            });
+        self.map_to_exes.entry(map_id).or_default().insert(exe_id);
+    }
+
+    /// Restore a pair with a previously learned probability.
+    pub fn attach_with_prob(&mut self, exe_id: ExeId, map_id: MapId, prob: f32) {
+        self.exe_to_maps.entry(exe_id).or_default().insert(
+            map_id,
+            ExeMapEntry {
+                prob: prob.clamp(0.0, 1.0),
+                seen: false,
+            },
+        );
         self.map_to_exes.entry(map_id).or_default().insert(exe_id);
+        self.dirty.insert((exe_id, map_id));
+    }
+
+    /// Probability that `map_id` is used when `exe_id` runs.
+    pub fn prob(&self, exe_id: ExeId, map_id: MapId) -> Option<f32> {
+        self.exe_to_maps
+            .get(&exe_id)
+            .and_then(|maps| maps.get(&map_id))
+            .map(|entry| entry.prob)
+    }
+
+    /// Fold the run of `exe_id` that just ended into its map probabilities:
+    /// `prob = mix * prob + (1 - mix) * seen`.
+    pub fn end_run(&mut self, exe_id: ExeId, mix: f32) {
+        let mix = mix.clamp(0.0, 1.0);
+        for (map_id, entry) in self
+            .exe_to_maps
+            .get_mut(&exe_id)
+            .into_iter()
+            .flat_map(|maps| maps.iter_mut())
+        {
+            let seen = if entry.seen { 1.0 } else { 0.0 };
+            let prob = (mix * entry.prob + (1.0 - mix) * seen).clamp(0.0, 1.0);
+            if prob != entry.prob {
+                entry.prob = prob;
+                self.dirty.insert((exe_id, *map_id));
+            }
+            entry.seen = false;
+        }
     }
 
     pub fn maps_for_exe(&self, exe_id: ExeId) -> impl Iterator<Item = MapId> + '_ {
         self.exe_to_maps
             .get(&exe_id)
             .into_iter()
-            .flat_map(|set| set.iter().copied())
+            .flat_map(|maps| maps.keys().copied())
     }
 
     pub fn exes_for_map(&self, map_id: MapId) -> impl Iterator<Item = ExeId> + '_ {
@@ -29,9 +96,27 @@ impl ExeMapIndex {
             .flat_map(|set| set.iter().copied())
     }
 
+    pub fn remove_map(&mut self, map_id: MapId) {
+        if let Some(exes) = self.map_to_exes.remove(&map_id) {
+            for exe_id in exes {
+                if let Some(maps) = self.exe_to_maps.get_mut(&exe_id) {
+                    maps.remove(&map_id);
+                    if maps.is_empty() {
+                        self.exe_to_maps.remove(&exe_id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pairs changed since the last call.
+    pub fn take_changes(&mut self) -> HashSet<(ExeId, MapId)> {
+        std::mem::take(&mut self.dirty)
+    }
+
     pub fn remove_exe(&mut self, exe_id: ExeId) {
         if let Some(maps) = self.exe_to_maps.remove(&exe_id) {
-            for map_id in maps {
+            for map_id in maps.into_keys() {
                 if let Some(exes) = self.map_to_exes.get_mut(&map_id) {
                     exes.remove(&exe_id);
                     if exes.is_empty() {
@@ -56,6 +141,7 @@ mod tests {
             map_count in 0usize..10,
             attachments in prop::collection::vec((0u8..20, 0u8..20), 0..50),
             removals in prop::collection::vec(0u8..20, 0..10),
+            map_removals in prop::collection::vec(0u8..20, 0..10),
         ) {
             let mut index = ExeMapIndex::default();
             let mut exe_ids = SlotMap::<ExeId, ()>::with_key();
@@ -75,10 +161,17 @@ mod tests {
                     let exe = exes[e as usize % exes.len()];
                     index.remove_exe(exe);
                 }
+
+                for m in map_removals {
+                    let map = maps[m as usize % maps.len()];
+                    index.remove_map(map);
+                    prop_assert_eq!(index.exes_for_map(map).count(), 0);
+                }
             }
 
             for (exe, maps) in index.exe_to_maps.iter() {
-                for map in maps {
+                prop_assert!(!maps.is_empty());
+                for map in maps.keys() {
                     let back = index
                         .map_to_exes
                         .get(map)
@@ -94,11 +187,36 @@ mod tests {
                     let back = index
                         .exe_to_maps
                         .get(exe)
-                        .map(|set| set.contains(map))
+                        .map(|maps| maps.contains_key(map))
                         .unwrap_or(false);
                     prop_assert!(back);
                 }
             }
         }
+
+        #[test]
+        fn run_outcomes_fold_into_bounded_probabilities(
+            runs in prop::collection::vec((any::<bool>(), 0.0f32..=1.0), 1..40),
+        ) {
+            let mut index = ExeMapIndex::default();
+            let exe = SlotMap::<ExeId, ()>::with_key().insert(());
+            let map = SlotMap::<MapId, ()>::with_key().insert(());
+            index.attach(exe, map);
+
+            for (seen, mix) in runs {
+                let before = index.prob(exe, map).unwrap();
+                if seen {
+                    index.attach(exe, map);
+                }
+                index.end_run(exe, mix);
+                let after = index.prob(exe, map).unwrap();
+                prop_assert!((0.0..=1.0).contains(&after));
+                if seen {
+                    prop_assert!(after >= before - 1e-6);
+                } else {
+                    prop_assert!(after <= before + 1e-6);
+                }
+            }
+        }
     }
 }
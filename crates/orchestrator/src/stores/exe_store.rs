@@ -2,12 +2,15 @@
 
 use crate::domain::{Exe, ExeId, ExeKey};
 use slotmap::SlotMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Default)]
 pub struct ExeStore {
     exes: SlotMap<ExeId, Exe>,
     by_key: HashMap<ExeKey, ExeId>,
+    /// Added or mutably borrowed since the last [`ExeStore::take_changes`].
+    dirty: HashSet<ExeId>,
+    removed: HashSet<ExeKey>,
 }
 
 impl ExeStore {
@@ -18,6 +21,7 @@ impl ExeStore {
         let exe = Exe::new(key.clone());
         let id = self.exes.insert(exe);
         self.by_key.insert(key, id);
+        self.dirty.insert(id);
         id
     }
 
@@ -25,8 +29,11 @@ impl ExeStore {
         self.exes.get(id)
     }
 
+    /// Mutable access; the exe counts as changed for the next save.
     pub fn get_mut(&mut self, id: ExeId) -> Option<&mut Exe> {
-        self.exes.get_mut(id)
+        let exe = self.exes.get_mut(id)?;
+        self.dirty.insert(id);
+        Some(exe)
     }
 
     pub fn id_by_key(&self, key: &ExeKey) -> Option<ExeId> {
@@ -40,4 +47,28 @@ impl ExeStore {
     pub fn keys(&self) -> impl Iterator<Item = &ExeKey> {
         self.by_key.keys()
     }
+
+    pub fn remove(&mut self, id: ExeId) -> Option<Exe> {
+        let exe = self.exes.remove(id)?;
+        self.by_key.remove(&exe.key);
+        self.dirty.remove(&id);
+        self.removed.insert(exe.key.clone());
+        Some(exe)
+    }
+
+    /// Exes changed and keys removed since the last call.
+    pub fn take_changes(&mut self) -> (HashSet<ExeId>, HashSet<ExeKey>) {
+        (
+            std::mem::take(&mut self.dirty),
+            std::mem::take(&mut self.removed),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.exes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exes.is_empty()
+    }
 }
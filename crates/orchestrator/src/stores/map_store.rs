@@ -1,13 +1,16 @@
 #![forbid(unsafe_code)]
 
-use crate::domain::{MapId, MapKey, MapSegment};
+use crate::domain::{FileIdentity, MapId, MapKey, MapSegment};
 use slotmap::SlotMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Default)]
 pub struct MapStore {
     maps: SlotMap<MapId, MapSegment>,
     by_key: HashMap<MapKey, MapId>,
+    /// Added or modified since the last [`MapStore::take_changes`].
+    dirty: HashSet<MapId>,
+    removed: HashSet<MapKey>,
 }
 
 impl MapStore {
@@ -22,6 +25,7 @@ impl MapStore {
         }
         let id = self.maps.insert(segment);
         self.by_key.insert(key, id);
+        self.dirty.insert(id);
         (id, true)
     }
 
@@ -36,4 +40,48 @@ impl MapStore {
     pub fn iter(&self) -> impl Iterator<Item = (MapId, &MapSegment)> {
         self.maps.iter()
     }
+
+    /// Record that the map was seen again at `time`.
+    pub fn touch(&mut self, id: MapId, time: u64) {
+        if let Some(map) = self.maps.get_mut(id)
+            && time > map.update_time
+        {
+            map.update_time = time;
+            self.dirty.insert(id);
+        }
+    }
+
+    /// Record which file now backs the map.
+    pub fn set_identity(&mut self, id: MapId, identity: FileIdentity) {
+        if let Some(map) = self.maps.get_mut(id)
+            && map.identity != Some(identity)
+        {
+            map.identity = Some(identity);
+            self.dirty.insert(id);
+        }
+    }
+
+    pub fn remove(&mut self, id: MapId) -> Option<MapSegment> {
+        let map = self.maps.remove(id)?;
+        self.by_key.remove(&map.key());
+        self.dirty.remove(&id);
+        self.removed.insert(map.key());
+        Some(map)
+    }
+
+    /// Maps changed and keys removed since the last call.
+    pub fn take_changes(&mut self) -> (HashSet<MapId>, HashSet<MapKey>) {
+        (
+            std::mem::take(&mut self.dirty),
+            std::mem::take(&mut self.removed),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.maps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
 }
@@ -2,11 +2,17 @@
 
 use crate::domain::{ExeId, MarkovEdge, MarkovState};
 use crate::stores::EdgeKey;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Default)]
 pub struct MarkovGraph {
     edges: HashMap<EdgeKey, MarkovEdge>,
+    /// Added or mutably borrowed since the last [`MarkovGraph::take_changes`].
+    dirty: HashSet<EdgeKey>,
+    /// Pruned between exes that still exist. Edges removed along with an exe
+    /// are not listed.
+    removed: HashSet<EdgeKey>,
 }
 
 impl MarkovGraph {
@@ -16,6 +22,7 @@ impl MarkovGraph {
             return false;
         }
         self.edges.insert(key, MarkovEdge::new(state, now));
+        self.dirty.insert(key);
         true
     }
 
@@ -23,16 +30,76 @@ impl MarkovGraph {
         self.edges.iter().map(|(k, v)| (*k, v))
     }
 
+    /// Mutable access to every edge; all of them count as changed for the
+    /// next save, so prefer [`MarkovGraph::get_mut`] for the few that change.
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (EdgeKey, &mut MarkovEdge)> {
+        self.dirty.extend(self.edges.keys().copied());
         self.edges.iter_mut().map(|(k, v)| (*k, v))
     }
 
+    pub fn get(&self, key: EdgeKey) -> Option<&MarkovEdge> {
+        self.edges.get(&key)
+    }
+
+    /// Mutable access; the edge counts as changed for the next save.
     pub fn get_mut(&mut self, key: EdgeKey) -> Option<&mut MarkovEdge> {
-        self.edges.get_mut(&key)
+        let edge = self.edges.get_mut(&key)?;
+        self.dirty.insert(key);
+        Some(edge)
     }
 
-    pub fn prune_inactive(&mut self, active: &HashSet<ExeId>) {
+    /// Drop every edge touching `exe_id`.
+    pub fn remove_exe(&mut self, exe_id: ExeId) {
         self.edges
-            .retain(|key, _| active.contains(&key.0) && active.contains(&key.1));
+            .retain(|key, _| key.0 != exe_id && key.1 != exe_id);
+    }
+
+    /// Move the edges of `from` onto `into`. Edges `into` already has win, and
+    /// the edge between the two is dropped.
+    pub fn merge_exe(&mut self, from: ExeId, into: ExeId) {
+        let moved: Vec<EdgeKey> = self
+            .edges
+            .keys()
+            .filter(|key| key.0 == from || key.1 == from)
+            .copied()
+            .collect();
+        for key in moved {
+            let Some(edge) = self.edges.remove(&key) else {
+                continue;
+            };
+            let other = if key.0 == from { key.1 } else { key.0 };
+            if other == into {
+                continue;
+            }
+            let new_key = EdgeKey::new(into, other);
+            // Keep A/B-indexed statistics attached to the right exe.
+            let edge = if (key.0 == from) == (new_key.0 == into) {
+                edge
+            } else {
+                edge.swapped()
+            };
+            if let Entry::Vacant(entry) = self.edges.entry(new_key) {
+                entry.insert(edge);
+                self.dirty.insert(new_key);
+            }
+        }
+    }
+
+    pub fn prune_inactive(&mut self, active: &HashSet<ExeId>) {
+        self.edges.retain(|key, _| {
+            let keep = active.contains(&key.0) && active.contains(&key.1);
+            if !keep {
+                self.removed.insert(*key);
+            }
+            keep
+        });
+    }
+
+    /// Edges changed and edges pruned since the last call.
+    pub fn take_changes(&mut self) -> (HashSet<EdgeKey>, HashSet<EdgeKey>) {
+        (
+            std::mem::take(&mut self.dirty),
+            std::mem::take(&mut self.removed),
+        )
     }
 }
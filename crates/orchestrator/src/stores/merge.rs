@@ -0,0 +1,192 @@
+#![forbid(unsafe_code)]
+
+use crate::domain::{ExeId, ExeIdentity, ExeKey};
+use crate::stores::Stores;
+use std::collections::HashMap;
+
+impl Stores {
+    /// Fold the history of `from` into `into` and forget `from`.
+    ///
+    /// Running time adds up, map probabilities and Markov edges `into` lacks
+    /// are taken over, and the edge between the two is dropped. Returns the
+    /// key of the removed exe.
+    pub fn merge_exe(&mut self, from: ExeId, into: ExeId) -> Option<ExeKey> {
+        if from == into || self.exes.get(into).is_none() {
+            return None;
+        }
+        let source = self.exes.get(from)?.clone();
+        if let Some(target) = self.exes.get_mut(into) {
+            target.total_running_time = target
+                .total_running_time
+                .saturating_add(source.total_running_time);
+            target.last_seen_time = target.last_seen_time.max(source.last_seen_time);
+        }
+
+        let maps: Vec<_> = self.exe_maps.maps_for_exe(from).collect();
+        for map_id in maps {
+            if self.exe_maps.prob(into, map_id).is_none() {
+                let prob = self.exe_maps.prob(from, map_id).unwrap_or(1.0);
+                self.exe_maps.attach_with_prob(into, map_id, prob);
+            }
+        }
+
+        self.markov.merge_exe(from, into);
+        if let Some(last_seen) = self.active.last_seen(from) {
+            let last_seen = last_seen.max(self.active.last_seen(into).unwrap_or(0));
+            self.active.update([into], last_seen);
+        }
+
+        self.remove_exe(from)
+    }
+
+    /// Merge exes that share an identity into the most recently seen one.
+    /// Returns `(merged, kept)` key pairs.
+    pub fn merge_same_identity(&mut self) -> Vec<(ExeKey, ExeKey)> {
+        let mut groups: HashMap<ExeIdentity, Vec<(Option<u64>, ExeId)>> = HashMap::new();
+        for (id, exe) in self.exes.iter() {
+            if let Some(identity) = &exe.identity {
+                groups
+                    .entry(identity.clone())
+                    .or_default()
+                    .push((exe.last_seen_time, id));
+            }
+        }
+
+        let mut merged = Vec::new();
+        for mut group in groups.into_values() {
+            if group.len() < 2 {
+                continue;
+            }
+            group.sort_unstable();
+            let Some((_, keep)) = group.pop() else {
+                continue;
+            };
+            for (_, from) in group {
+                if let Some(key) = self.merge_exe(from, keep)
+                    && let Some(kept) = self.exes.get(keep)
+                {
+                    merged.push((key, kept.key.clone()));
+                }
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MapSegment, MarkovEdge, MarkovState};
+    use crate::stores::EdgeKey;
+    use proptest::prelude::*;
+
+    fn edge_strategy() -> impl Strategy<Value = MarkovEdge> {
+        (
+            0usize..4,
+            prop::array::uniform4(0u64..1_000),
+            prop::array::uniform4(0f32..100f32),
+            prop::array::uniform4(prop::array::uniform4(0f32..1f32)),
+        )
+            .prop_map(|(state, left, ttl, tp)| {
+                let state = [
+                    MarkovState::Neither,
+                    MarkovState::AOnly,
+                    MarkovState::BOnly,
+                    MarkovState::Both,
+                ][state];
+                MarkovEdge {
+                    state,
+                    last_change_time: 0,
+                    state_last_left: left,
+                    time_to_leave: ttl,
+                    transition_prob: tp,
+                    both_running_time: 0,
+                }
+            })
+    }
+
+    #[test]
+    fn merged_edges_keep_their_orientation() {
+        let mut stores = Stores::default();
+        let old = stores.ensure_exe(ExeKey::new("/opt/app-1.0/bin/app"));
+        let other = stores.ensure_exe(ExeKey::new("/usr/bin/other"));
+        let new = stores.ensure_exe(ExeKey::new("/opt/app-1.1/bin/app"));
+
+        // Only `old` running, seen from whichever side `old` is on.
+        stores.ensure_markov_edge(old, other, 0, MarkovState::Neither);
+        let key = EdgeKey::new(old, other);
+        let edge = stores.markov.get_mut(key).unwrap();
+        let old_only = if key.a() == old {
+            MarkovState::AOnly
+        } else {
+            MarkovState::BOnly
+        };
+        edge.state = old_only;
+        edge.time_to_leave[old_only.index()] = 42.0;
+        stores.exes.get_mut(old).unwrap().total_running_time = 100;
+        let map = stores.ensure_map(MapSegment::new("/opt/app-1.0/lib/libapp.so", 0, 4096, 0));
+        stores.exe_maps.attach_with_prob(old, map, 0.5);
+
+        assert_eq!(
+            stores.merge_exe(old, new),
+            Some(ExeKey::new("/opt/app-1.0/bin/app"))
+        );
+
+        let key = EdgeKey::new(new, other);
+        let new_only = if key.a() == new {
+            MarkovState::AOnly
+        } else {
+            MarkovState::BOnly
+        };
+        let edge = stores.markov.iter().find(|(k, _)| *k == key).unwrap().1;
+        assert_eq!(edge.state, new_only);
+        assert_eq!(edge.time_to_leave[new_only.index()], 42.0);
+        assert_eq!(stores.exes.get(new).unwrap().total_running_time, 100);
+        assert_eq!(stores.exe_maps.prob(new, map), Some(0.5));
+        assert!(stores.exes.get(old).is_none());
+    }
+
+    #[test]
+    fn same_identity_merges_into_most_recently_seen() {
+        let mut stores = Stores::default();
+        let identity = Some(ExeIdentity::BuildId("abcd".into()));
+        let mut add = |path: &str, last_seen: u64| {
+            let id = stores.ensure_exe(ExeKey::new(path));
+            let exe = stores.exes.get_mut(id).unwrap();
+            exe.identity = identity.clone();
+            exe.last_seen_time = Some(last_seen);
+            exe.total_running_time = 10;
+        };
+        add("/usr/bin/app", 5);
+        add("/usr/libexec/app", 9);
+        add("/usr/local/bin/app", 1);
+
+        let merged = stores.merge_same_identity();
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(stores.exes.len(), 1);
+        let (_, exe) = stores.exes.iter().next().unwrap();
+        assert_eq!(exe.key, ExeKey::new("/usr/libexec/app"));
+        assert_eq!(exe.total_running_time, 30);
+    }
+
+    proptest! {
+        #[test]
+        fn swapping_twice_is_identity(edge in edge_strategy()) {
+            let twice = edge.swapped().swapped();
+            prop_assert_eq!(twice.state, edge.state);
+            prop_assert_eq!(twice.state_last_left, edge.state_last_left);
+            prop_assert_eq!(twice.time_to_leave, edge.time_to_leave);
+            prop_assert_eq!(twice.transition_prob, edge.transition_prob);
+        }
+
+        #[test]
+        fn swapping_matches_exchanging_roles(edge in edge_strategy(), a in any::<bool>(), b in any::<bool>()) {
+            let swapped = edge.swapped();
+            let seen = MarkovState::from_running(a, b).index();
+            let mirrored = MarkovState::from_running(b, a).index();
+            prop_assert_eq!(swapped.time_to_leave[mirrored], edge.time_to_leave[seen]);
+            prop_assert_eq!(swapped.state_last_left[mirrored], edge.state_last_left[seen]);
+        }
+    }
+}
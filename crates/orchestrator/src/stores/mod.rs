@@ -1,20 +1,25 @@
 #![forbid(unsafe_code)]
 
 mod active_set;
+mod changes;
 mod edge_key;
+mod eviction;
 mod exe_map_index;
 mod exe_store;
 mod map_store;
 mod markov_graph;
+mod merge;
 
 pub use active_set::ActiveSet;
+pub use changes::StoreChanges;
 pub use edge_key::EdgeKey;
+pub use eviction::{Evicted, EvictionLimits};
 pub use exe_map_index::ExeMapIndex;
 pub use exe_store::ExeStore;
 pub use map_store::MapStore;
 pub use markov_graph::MarkovGraph;
 
-use crate::domain::{ExeId, ExeKey, MapId, MapSegment, MarkovState};
+use crate::domain::{ExeId, ExeKey, MapId, MapKey, MapSegment, MarkovState};
 use std::collections::HashSet;
 
 #[derive(Debug, Default)]
@@ -52,4 +57,21 @@ impl Stores {
     pub fn active_exes(&self) -> HashSet<ExeId> {
         self.active.exes()
     }
+
+    /// Forget an exe along with its map attachments, Markov edges and
+    /// active-set membership.
+    pub fn remove_exe(&mut self, exe_id: ExeId) -> Option<ExeKey> {
+        let exe = self.exes.remove(exe_id)?;
+        self.exe_maps.remove_exe(exe_id);
+        self.markov.remove_exe(exe_id);
+        self.active.remove(exe_id);
+        Some(exe.key)
+    }
+
+    /// Forget a map and detach it from every exe.
+    pub fn remove_map(&mut self, map_id: MapId) -> Option<MapKey> {
+        let map = self.maps.remove(map_id)?;
+        self.exe_maps.remove_map(map_id);
+        Some(map.key())
+    }
 }
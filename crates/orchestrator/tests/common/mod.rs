@@ -0,0 +1,180 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+//! Builders for synthetic `/proc` trees consumed by `ProcfsScanner::with_root`.
+
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A temporary directory laid out like `/proc`.
+///
+/// `meminfo` and `vmstat` are written on creation so the scanner can always
+/// produce a `MemStat`; processes are added with [`ProcFixture::process`].
+pub struct ProcFixture {
+    dir: TempDir,
+}
+
+impl ProcFixture {
+    pub fn new() -> Self {
+        let fixture = Self {
+            dir: tempfile::tempdir().expect("create proc fixture"),
+        };
+        fixture.meminfo(1_000_000, 500_000, 250_000);
+        fixture.vmstat(0, 0);
+        fixture
+    }
+
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Write `/proc/meminfo` with the given values in kB.
+    pub fn meminfo(&self, total: u64, free: u64, cached: u64) -> &Self {
+        let mut text = format!("MemTotal: {total} kB\nMemFree: {free} kB\nCached: {cached} kB\n");
+        for field in [
+            "Buffers",
+            "SwapCached",
+            "Active",
+            "Inactive",
+            "SwapTotal",
+            "SwapFree",
+            "Dirty",
+            "Writeback",
+            "Mapped",
+            "Slab",
+            "Committed_AS",
+            "VmallocTotal",
+            "VmallocUsed",
+            "VmallocChunk",
+        ] {
+            text.push_str(&format!("{field}: 0 kB\n"));
+        }
+        fs::write(self.root().join("meminfo"), text).expect("write meminfo");
+        self
+    }
+
+    /// Write `/proc/vmstat` with the given page counters.
+    pub fn vmstat(&self, pgpgin: i64, pgpgout: i64) -> &Self {
+        let text = format!("nr_free_pages 0\npgpgin {pgpgin}\npgpgout {pgpgout}\n");
+        fs::write(self.root().join("vmstat"), text).expect("write vmstat");
+        self
+    }
+
+    /// Create `/proc/<pid>` whose `exe` links to `exe` (the target need not exist).
+    pub fn process(&self, pid: u32, exe: impl AsRef<Path>) -> ProcessFixture {
+        let dir = self.root().join(pid.to_string());
+        fs::create_dir_all(&dir).expect("create pid dir");
+        symlink(exe.as_ref(), dir.join("exe")).expect("link exe");
+        let process = ProcessFixture {
+            pid,
+            dir,
+            start_time: 100,
+            vsize: 4096,
+            maps: Vec::new(),
+        };
+        process.write_stat();
+        process.write_maps();
+        process
+    }
+
+    /// Remove `/proc/<pid>` entirely, as after the process exited.
+    pub fn exit(&self, pid: u32) -> &Self {
+        fs::remove_dir_all(self.root().join(pid.to_string())).expect("remove pid dir");
+        self
+    }
+
+    /// Create `/proc/<pid>` without any entries, like a process that exited
+    /// between the directory listing and reading its files.
+    pub fn vanished(&self, pid: u32) -> &Self {
+        fs::create_dir_all(self.root().join(pid.to_string())).expect("create pid dir");
+        self
+    }
+}
+
+/// A single synthetic `/proc/<pid>` directory.
+pub struct ProcessFixture {
+    pid: u32,
+    dir: PathBuf,
+    start_time: u64,
+    vsize: u64,
+    maps: Vec<Vec<u8>>,
+}
+
+impl ProcessFixture {
+    /// Append a file-backed mapping (`path` may carry a ` (deleted)` suffix).
+    pub fn map(mut self, path: impl AsRef<Path>, offset: u64, length: u64) -> Self {
+        let start = 0x1000_0000 + 0x100_0000 * self.maps.len() as u64;
+        let end = start + length;
+        let mut line = format!("{start:x}-{end:x} r-xp {offset:08x} fe:00 1234 ").into_bytes();
+        line.extend_from_slice(path.as_ref().as_os_str().as_bytes());
+        self.maps.push(line);
+        self.vsize += length;
+        self.write_stat();
+        self.write_maps();
+        self
+    }
+
+    /// Append an anonymous mapping, which the scanner must ignore.
+    pub fn anon_map(mut self, length: u64) -> Self {
+        let start = 0x7000_0000 + 0x100_0000 * self.maps.len() as u64;
+        let end = start + length;
+        self.maps
+            .push(format!("{start:x}-{end:x} rw-p 00000000 00:00 0 [heap]").into_bytes());
+        self.vsize += length;
+        self.write_stat();
+        self.write_maps();
+        self
+    }
+
+    /// Pretend the process was restarted under the same pid: it starts at
+    /// `start_time` without any mappings.
+    pub fn restart(mut self, start_time: u64) -> Self {
+        self.start_time = start_time;
+        self.vsize = 4096;
+        self.maps.clear();
+        self.write_stat();
+        self.write_maps();
+        self
+    }
+
+    /// Replace `maps` by a directory so reading it fails, as it does for
+    /// processes the scanner is not permitted to inspect.
+    pub fn unreadable_maps(self) -> Self {
+        let path = self.dir.join("maps");
+        fs::remove_file(&path).expect("remove maps");
+        fs::create_dir(&path).expect("create maps dir");
+        self
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn write_stat(&self) {
+        let pid = self.pid;
+        let text = format!(
+            "{pid} (fixture) S 1 {pid} {pid} 0 -1 4194304 0 0 0 0 0 0 0 0 20 0 1 0 {start} {vsize} \
+             0 18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n",
+            start = self.start_time,
+            vsize = self.vsize,
+        );
+        fs::write(self.dir.join("stat"), text).expect("write stat");
+    }
+
+    fn write_maps(&self) {
+        let path = self.dir.join("maps");
+        if path.is_dir() {
+            return;
+        }
+        let text: Vec<u8> = self
+            .maps
+            .iter()
+            .flat_map(|line| line.iter().chain(b"\n"))
+            .copied()
+            .collect();
+        fs::write(path, text).expect("write maps");
+    }
+}
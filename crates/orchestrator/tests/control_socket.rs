@@ -0,0 +1,215 @@
+#![forbid(unsafe_code)]
+
+use config::Config;
+use orchestrator::clock::SystemClock;
+use orchestrator::control::{ControlClient, ControlRequest, ControlResponse, ControlServer};
+use orchestrator::domain::MapSegment;
+use orchestrator::observation::{
+    DefaultAdmissionPolicy, DefaultModelUpdater, Observation, ObservationEvent, Scanner,
+};
+use orchestrator::persistence::NoopRepository;
+use orchestrator::prediction::{Prediction, Predictor};
+use orchestrator::prefetch::{GreedyPrefetchPlanner, NoopMapValidator, NoopPrefetcher};
+use orchestrator::stores::Stores;
+use orchestrator::{PreloadEngine, Services};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug)]
+struct StaticScanner;
+
+impl Scanner for StaticScanner {
+    fn scan(&mut self, time: u64, scan_id: u64) -> Result<Observation, orchestrator::error::Error> {
+        let mut events = vec![ObservationEvent::ObsBegin { time, scan_id }];
+        for (pid, (exe, map)) in [
+            ("/usr/bin/a", "/usr/lib/liba.so"),
+            ("/usr/bin/b", "/usr/lib/libb.so"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            events.push(ObservationEvent::ExeSeen {
+                path: exe.into(),
+                pid: pid as u32 + 1,
+            });
+            events.push(ObservationEvent::MapSeen {
+                exe_path: exe.into(),
+                map: MapSegment::new(map, 0, 4096, time),
+            });
+        }
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id,
+            warnings: Vec::new(),
+        });
+        Ok(events)
+    }
+}
+
+/// Scores everything by path length, so the ordering is known.
+#[derive(Debug)]
+struct PathLengthPredictor;
+
+impl Predictor for PathLengthPredictor {
+    fn predict(&self, stores: &Stores) -> Prediction {
+        let score = |path: &Path| 1.0 / path.as_os_str().len() as f32;
+        let mut prediction = Prediction::default();
+        for (id, exe) in stores.exes.iter() {
+            prediction.exe_scores.insert(id, score(exe.key.path()));
+        }
+        for (id, map) in stores.maps.iter() {
+            prediction.map_scores.insert(id, score(&map.path));
+        }
+        prediction
+    }
+}
+
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+}
+
+impl Client {
+    async fn connect(path: &Path) -> Self {
+        let (read, write) = UnixStream::connect(path).await.unwrap().into_split();
+        Self {
+            lines: BufReader::new(read).lines(),
+            write,
+        }
+    }
+
+    async fn send_line(&mut self, line: &str) -> Value {
+        self.write.write_all(line.as_bytes()).await.unwrap();
+        self.write.write_all(b"\n").await.unwrap();
+        let reply = self.lines.next_line().await.unwrap().expect("reply");
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    async fn request(&mut self, request: Value) -> Value {
+        self.send_line(&request.to_string()).await
+    }
+}
+
+#[tokio::test]
+async fn control_socket_answers_status_predictions_and_pause() {
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    // Only the first tick runs during the test.
+    config.model.cycle = Duration::from_secs(3600);
+    config.system.exeprefix = vec!["!/".into(), "/usr/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/usr/".into()];
+
+    let services = Services {
+        scanner: Box::new(StaticScanner),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathLengthPredictor),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config, services).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("run").join("control.sock");
+    let cancel = CancellationToken::new();
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+    let server = ControlServer::bind(&socket, control_tx).unwrap();
+    let server = tokio::spawn(server.serve(cancel.clone()));
+    let engine_cancel = cancel.clone();
+    let engine_task = tokio::spawn(async move {
+        engine.run_until(engine_cancel, control_rx).await.unwrap();
+        engine
+    });
+
+    let mut client = Client::connect(&socket).await;
+    let report = loop {
+        let reply = client.request(json!({ "command": "last_tick" })).await;
+        assert_eq!(reply["kind"], "last_tick");
+        if !reply["report"].is_null() {
+            break reply["report"].clone();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(report["scan_id"], 1);
+
+    let status = client.request(json!({ "command": "status" })).await;
+    assert_eq!(status["kind"], "status");
+    assert_eq!(status["exes"], 2);
+    assert_eq!(status["maps"], 2);
+    assert_eq!(status["paused"], false);
+
+    let top = client
+        .request(json!({ "command": "predictions", "limit": 1 }))
+        .await;
+    assert_eq!(top["kind"], "predictions");
+    assert_eq!(top["exes"].as_array().unwrap().len(), 1);
+    assert_eq!(top["exes"][0]["running"], true);
+    assert_eq!(top["maps"].as_array().unwrap().len(), 1);
+
+    let reply = client.request(json!({ "command": "pause" })).await;
+    assert_eq!(reply["kind"], "done");
+    let status = client.request(json!({ "command": "status" })).await;
+    assert_eq!(status["paused"], true);
+    let reply = client.request(json!({ "command": "resume" })).await;
+    assert_eq!(reply["kind"], "done");
+
+    // Unknown commands and a missing reload hook are errors, not hangups.
+    let reply = client.send_line("{\"command\":\"explode\"}").await;
+    assert_eq!(reply["kind"], "error");
+    let reply = client.request(json!({ "command": "reload" })).await;
+    assert_eq!(reply["kind"], "error");
+
+    // Predictors without an explanation say so.
+    let mut typed = ControlClient::connect(&socket).await.unwrap();
+    let reply = typed
+        .request(&ControlRequest::Explain {
+            path: "/usr/bin/a".into(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(reply, ControlResponse::Error { .. }));
+    let reply = typed.request(&ControlRequest::Status).await.unwrap();
+    assert!(matches!(reply, ControlResponse::Status(status) if !status.paused));
+
+    cancel.cancel();
+    let engine = engine_task.await.unwrap();
+    server.await.unwrap();
+    assert!(engine.status().scan_id >= 1);
+    assert!(!socket.exists());
+}
+
+#[tokio::test]
+async fn bind_replaces_only_stale_sockets() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let (control_tx, _control_rx) = mpsc::unbounded_channel();
+
+    // A mistyped path must not cost the user a file.
+    std::fs::write(&socket, "config").unwrap();
+    assert!(ControlServer::bind(&socket, control_tx.clone()).is_err());
+    assert_eq!(std::fs::read_to_string(&socket).unwrap(), "config");
+    std::fs::remove_file(&socket).unwrap();
+
+    // Nothing listens on a socket left by a crashed daemon.
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    let server = ControlServer::bind(&socket, control_tx.clone()).unwrap();
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+
+    // A running daemon keeps its socket.
+    assert!(ControlServer::bind(&socket, control_tx).is_err());
+    ControlClient::connect(server.path()).await.unwrap();
+}
@@ -1,20 +1,30 @@
 #![forbid(unsafe_code)]
 
-use config::{Config, MemoryPolicy, SortStrategy};
+use config::{Config, MemoryPolicy, SortStrategy, StateBackend};
+use orchestrator::StateRepository;
 use orchestrator::clock::SystemClock;
-use orchestrator::domain::{MapSegment, MemStat};
+use orchestrator::control::ControlRequest;
+use orchestrator::domain::{FileIdentity, MapSegment, MarkovState, MemStat};
 use orchestrator::observation::{
     DefaultAdmissionPolicy, DefaultModelUpdater, Observation, ObservationEvent, Scanner,
 };
-use orchestrator::persistence::{NoopRepository, SqliteRepository};
+use orchestrator::persistence::{
+    APP_VERSION, ExeMapRecord, FileRepository, NoopRepository, SnapshotDelta, SqliteRepository,
+    StoresSnapshot, import_legacy_state,
+};
 use orchestrator::prediction::{Prediction, Predictor};
 use orchestrator::prefetch::{
-    GreedyPrefetchPlanner, NoopPrefetcher, PrefetchPlan, PrefetchReport, Prefetcher,
+    FileMapValidator, GreedyPrefetchPlanner, NoopMapValidator, NoopPrefetcher, PrefetchPlan,
+    PrefetchReport, Prefetcher,
 };
-use orchestrator::{PreloadEngine, Services};
+use orchestrator::{ControlEvent, PreloadEngine, ReloadBundle, Services};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tempfile::tempdir;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 struct StaticScanner {
@@ -142,6 +152,7 @@ async fn engine_tick_flows_through_pipeline() {
             scores: vec![(map_a.clone(), 0.9), (map_b.clone(), 0.1)],
         }),
         planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
         prefetcher: Box::new(spy),
         repo: Box::new(NoopRepository),
         clock: Box::new(SystemClock),
@@ -170,6 +181,11 @@ async fn engine_persists_and_loads_state() {
     let map_a = PathBuf::from("/test/map-a");
     let map_b = PathBuf::from("/test/map-b");
 
+    let identity = FileIdentity {
+        device: 8,
+        inode: 42,
+        mtime_ns: 1_700_000_000_000_000_000,
+    };
     let observation = vec![
         ObservationEvent::ObsBegin {
             time: 10,
@@ -181,7 +197,7 @@ async fn engine_persists_and_loads_state() {
         },
         ObservationEvent::MapSeen {
             exe_path: exe_path.clone(),
-            map: MapSegment::new(map_a.clone(), 0, 2048, 10),
+            map: MapSegment::new(map_a.clone(), 0, 2048, 10).with_identity(Some(identity)),
         },
         ObservationEvent::MapSeen {
             exe_path: exe_path.clone(),
@@ -210,6 +226,7 @@ async fn engine_persists_and_loads_state() {
         updater: Box::new(DefaultModelUpdater::new(&config)),
         predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
         planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
         prefetcher: Box::new(NoopPrefetcher),
         repo: Box::new(repo),
         clock: Box::new(SystemClock),
@@ -218,8 +235,12 @@ async fn engine_persists_and_loads_state() {
     let mut engine = PreloadEngine::new(config.clone(), services).await.unwrap();
     let _ = engine.tick().await.unwrap();
     engine.save().await.unwrap();
+    // The engine's repository holds the writer lock.
+    drop(engine);
 
     let repo = SqliteRepository::new(db_path).await.unwrap();
+    let saved = repo.load().await.unwrap();
+    assert_eq!(saved.meta.app_version.as_deref(), Some(APP_VERSION));
     let services = Services {
         scanner: Box::new(StaticScanner {
             observation: Vec::new(),
@@ -228,6 +249,7 @@ async fn engine_persists_and_loads_state() {
         updater: Box::new(DefaultModelUpdater::new(&config)),
         predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
         planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
         prefetcher: Box::new(NoopPrefetcher),
         repo: Box::new(repo),
         clock: Box::new(SystemClock),
@@ -250,6 +272,700 @@ async fn engine_persists_and_loads_state() {
         .map(|map| map.path.clone())
         .collect();
 
-    let expected: std::collections::HashSet<_> = [map_a, map_b].into_iter().collect();
+    let expected: std::collections::HashSet<_> = [map_a.clone(), map_b].into_iter().collect();
     assert_eq!(map_paths, expected);
+
+    let (_, reloaded) = stores
+        .maps
+        .iter()
+        .find(|(_, map)| map.path == map_a)
+        .unwrap();
+    assert_eq!(reloaded.identity, Some(identity));
+}
+
+#[tokio::test]
+async fn replaced_map_files_are_dropped_before_planning() {
+    let dir = tempdir().unwrap();
+    let exe_path = dir.path().join("exe");
+    let kept = dir.path().join("kept.so");
+    let upgraded = dir.path().join("upgraded.so");
+    std::fs::write(&kept, b"kept").unwrap();
+    std::fs::write(&upgraded, b"old").unwrap();
+
+    let seen = |path: &PathBuf| ObservationEvent::MapSeen {
+        exe_path: exe_path.clone(),
+        map: MapSegment::new(path.clone(), 0, 4096, 0).with_identity(FileIdentity::of(path).ok()),
+    };
+    let observation = vec![
+        ObservationEvent::ObsBegin {
+            time: 0,
+            scan_id: 1,
+        },
+        ObservationEvent::ExeSeen {
+            path: exe_path.clone(),
+            pid: 1234,
+        },
+        seen(&kept),
+        seen(&upgraded),
+        ObservationEvent::MemStat {
+            mem: MemStat {
+                total: 0,
+                free: 64,
+                cached: 0,
+                pagein: 0,
+                pageout: 0,
+            },
+        },
+        ObservationEvent::ObsEnd {
+            time: 0,
+            scan_id: 1,
+            warnings: Vec::new(),
+        },
+    ];
+
+    // The package manager swaps the library after the scan.
+    let staged = dir.path().join("upgraded.so.tmp");
+    std::fs::write(&staged, b"new").unwrap();
+    std::fs::rename(&staged, &upgraded).unwrap();
+
+    let prefix = format!("{}/", dir.path().display());
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.model.memory = MemoryPolicy {
+        memtotal: 0,
+        memfree: 100,
+        memcached: 0,
+    };
+    config.system.exeprefix = vec!["!/".into(), prefix.clone()];
+    config.system.mapprefix = vec!["!/".into(), prefix];
+    config.system.sortstrategy = SortStrategy::None;
+
+    let spy = SpyPrefetcher::default();
+    let spy_handle = spy.plans.clone();
+
+    let services = Services {
+        scanner: Box::new(StaticScanner { observation }),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor {
+            scores: vec![(kept.clone(), 0.9), (upgraded.clone(), 0.9)],
+        }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(FileMapValidator),
+        prefetcher: Box::new(spy),
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+
+    let mut engine = PreloadEngine::new(config, services).await.unwrap();
+    let report = engine.tick().await.unwrap();
+
+    assert_eq!(report.validation.dropped.len(), 1);
+    assert_eq!(report.validation.dropped[0].path, upgraded);
+    let plans = SpyPrefetcher::take_inner(&spy_handle);
+    let stores = engine.stores();
+    let planned: Vec<_> = plans[0]
+        .maps
+        .iter()
+        .map(|id| stores.maps.get(*id).unwrap().path.clone())
+        .collect();
+    assert_eq!(planned, vec![kept]);
+    assert!(stores.maps.iter().all(|(_, map)| map.path != upgraded));
+}
+
+#[tokio::test]
+async fn legacy_preload_state_loads_into_engine() {
+    let state = "\
+PRELOAD\t0.6.4\t5000
+MAP\t1\t4900\t0\t8192\t-1\tfile:///usr/lib/libfoo.so.1
+EXE\t2\t4950\t1200\t-1\tfile:///usr/bin/foo
+EXE\t3\t4000\t300\t-1\tfile:///usr/bin/bar
+EXEMAP\t2\t1\t1
+MARKOV\t2\t3\t250\t10\t20\t30\t40\t4\t1\t3\t0\t0\t0\t0\t0\t0\t0\t0\t0\t0\t0\t0\t0
+";
+    let snapshot = import_legacy_state(state.as_bytes()).unwrap();
+
+    let dir = tempdir().unwrap();
+    let repo = SqliteRepository::new(dir.path().join("state.db"))
+        .await
+        .unwrap();
+    repo.save(&snapshot).await.unwrap();
+
+    let config = Config::default();
+    let services = Services {
+        scanner: Box::new(StaticScanner {
+            observation: Vec::new(),
+        }),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(repo),
+        clock: Box::new(SystemClock),
+    };
+    let engine = PreloadEngine::load(config, services).await.unwrap();
+    let stores = engine.stores();
+
+    assert_eq!(stores.model_time, 5000);
+    let (foo, exe) = stores
+        .exes
+        .iter()
+        .find(|(_, exe)| exe.key.path() == &PathBuf::from("/usr/bin/foo"))
+        .unwrap();
+    assert_eq!(exe.total_running_time, 1200);
+    assert_eq!(stores.exe_maps.maps_for_exe(foo).count(), 1);
+
+    let (key, edge) = stores.markov.iter().next().unwrap();
+    assert_eq!(edge.both_running_time, 250);
+    // The file's A is foo; the edge may hold it either way round.
+    let edge = if key.a() == foo {
+        edge.clone()
+    } else {
+        edge.swapped()
+    };
+    assert_eq!(edge.transition_prob[0], [0.0, 0.25, 0.75, 0.0]);
+    assert_eq!(edge.time_to_leave, [10.0, 20.0, 30.0, 40.0]);
+}
+
+/// Exe paths with the maps each one has mapped.
+type Running = Vec<(PathBuf, Vec<MapSegment>)>;
+
+/// Reports whichever exes the test lists as running, with their maps.
+#[derive(Debug, Clone, Default)]
+struct ScriptedScanner {
+    running: Arc<Mutex<Running>>,
+}
+
+impl Scanner for ScriptedScanner {
+    fn scan(&mut self, time: u64, scan_id: u64) -> Result<Observation, orchestrator::error::Error> {
+        let mut observation = vec![ObservationEvent::ObsBegin { time, scan_id }];
+        for (pid, (path, maps)) in self.running.lock().unwrap().iter().enumerate() {
+            observation.push(ObservationEvent::ExeSeen {
+                path: path.clone(),
+                pid: pid as u32 + 1,
+            });
+            for map in maps {
+                observation.push(ObservationEvent::MapSeen {
+                    exe_path: path.clone(),
+                    map: map.clone(),
+                });
+            }
+        }
+        observation.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id,
+            warnings: Vec::new(),
+        });
+        Ok(observation)
+    }
+}
+
+/// Counts full and incremental saves.
+struct CountingRepository {
+    inner: SqliteRepository,
+    full: Arc<AtomicUsize>,
+    deltas: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl StateRepository for CountingRepository {
+    async fn load(&self) -> Result<StoresSnapshot, orchestrator::error::Error> {
+        self.inner.load().await
+    }
+
+    async fn save(&self, snapshot: &StoresSnapshot) -> Result<(), orchestrator::error::Error> {
+        self.full.fetch_add(1, AtomicOrdering::SeqCst);
+        self.inner.save(snapshot).await
+    }
+
+    async fn save_delta(&self, delta: &SnapshotDelta) -> Result<bool, orchestrator::error::Error> {
+        self.deltas.fetch_add(1, AtomicOrdering::SeqCst);
+        self.inner.save_delta(delta).await
+    }
+}
+
+#[tokio::test]
+async fn incremental_saves_match_the_model() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    let map = |name: &str| MapSegment::new(format!("/test/{name}.so"), 0, 4096, 0);
+    let exe = |name: &str, maps: &[&str]| {
+        (
+            PathBuf::from(format!("/test/{name}")),
+            maps.iter().map(|name| map(name)).collect::<Vec<_>>(),
+        )
+    };
+
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.model.active_window = std::time::Duration::from_secs(30);
+    config.model.eviction.max_exes = Some(3);
+    config.system.exeprefix = vec!["!/".into(), "/test/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/test/".into()];
+    config.system.dopredict = false;
+
+    let scanner = ScriptedScanner::default();
+    let running = scanner.running.clone();
+    let (full, deltas) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let services = Services {
+        scanner: Box::new(scanner),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(CountingRepository {
+            inner: SqliteRepository::new(db_path.clone()).await.unwrap(),
+            full: full.clone(),
+            deltas: deltas.clone(),
+        }),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config, services).await.unwrap();
+
+    // Exes start and stop, maps come and go with them, edges are created and
+    // pruned, and the fourth exe evicts the least recently seen one.
+    let script = [
+        vec![exe("a", &["x", "y"]), exe("b", &["y"])],
+        vec![exe("b", &["y", "z"]), exe("c", &["z"])],
+        vec![exe("c", &["z"])],
+        vec![exe("c", &["z"]), exe("d", &["w"])],
+        vec![exe("a", &["x"]), exe("d", &["w"])],
+    ];
+    for step in script {
+        *running.lock().unwrap() = step;
+        engine.tick().await.unwrap();
+        engine.tick().await.unwrap();
+        engine.save().await.unwrap();
+    }
+    assert_eq!(full.load(AtomicOrdering::SeqCst), 1);
+    assert_eq!(deltas.load(AtomicOrdering::SeqCst), 4);
+
+    let saved = SqliteRepository::open_read_only(db_path)
+        .await
+        .unwrap()
+        .load()
+        .await
+        .unwrap();
+    let stores = engine.stores();
+    assert_eq!(saved.state.model_time, stores.model_time);
+
+    let mut exes: Vec<_> = saved
+        .state
+        .exes
+        .iter()
+        .map(|exe| (exe.path.clone(), exe.total_running_time, exe.last_seen_time))
+        .collect();
+    exes.sort();
+    let mut expected: Vec<_> = stores
+        .exes
+        .iter()
+        .map(|(_, exe)| {
+            (
+                exe.key.path().clone(),
+                exe.total_running_time,
+                exe.last_seen_time,
+            )
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(exes, expected);
+
+    let mut maps: Vec<_> = saved
+        .state
+        .maps
+        .iter()
+        .map(|map| (map.path.clone(), map.update_time))
+        .collect();
+    maps.sort();
+    let mut expected: Vec<_> = stores
+        .maps
+        .iter()
+        .map(|(_, map)| (map.path.clone(), map.update_time))
+        .collect();
+    expected.sort();
+    assert_eq!(maps, expected);
+
+    let mut links: Vec<_> = saved
+        .state
+        .exe_maps
+        .iter()
+        .map(|link| (link.exe_path.clone(), link.map_key.path.clone(), link.prob))
+        .collect();
+    links.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut expected: Vec<_> = stores
+        .exes
+        .iter()
+        .flat_map(|(exe_id, exe)| {
+            stores.exe_maps.maps_for_exe(exe_id).map(move |map_id| {
+                (
+                    exe.key.path().clone(),
+                    stores.maps.get(map_id).unwrap().path.clone(),
+                    stores.exe_maps.prob(exe_id, map_id).unwrap(),
+                )
+            })
+        })
+        .collect();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(links, expected);
+
+    let mut edges: Vec<_> = saved
+        .state
+        .markov_edges
+        .iter()
+        .map(|edge| {
+            (
+                edge.exe_a.clone(),
+                edge.exe_b.clone(),
+                edge.both_running_time,
+            )
+        })
+        .collect();
+    edges.sort();
+    let mut expected: Vec<_> = stores
+        .markov
+        .iter()
+        .map(|(key, edge)| {
+            (
+                stores.exes.get(key.a()).unwrap().key.path().clone(),
+                stores.exes.get(key.b()).unwrap().key.path().clone(),
+                edge.both_running_time,
+            )
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(edges, expected);
+}
+
+/// Path, running flag and change time.
+type ExeState = (PathBuf, bool, u64);
+/// Endpoints in path order, state index, last change, states last left and
+/// time to leave.
+type EdgeState = (PathBuf, PathBuf, usize, u64, [u64; 4], [f32; 4]);
+
+/// Exe running state and every edge's statistics, keyed by path.
+fn learned_state(stores: &orchestrator::Stores) -> (Vec<ExeState>, Vec<EdgeState>) {
+    let path = |id| stores.exes.get(id).unwrap().key.path().clone();
+    let mut exes: Vec<_> = stores
+        .exes
+        .iter()
+        .map(|(_, exe)| (exe.key.path().clone(), exe.running, exe.change_time))
+        .collect();
+    exes.sort();
+    let mut edges: Vec<_> = stores
+        .markov
+        .iter()
+        .map(|(key, edge)| {
+            let (a, b) = (path(key.a()), path(key.b()));
+            let (a, b, edge) = if a < b {
+                (a, b, edge.clone())
+            } else {
+                (b, a, edge.swapped())
+            };
+            (
+                a,
+                b,
+                edge.state.index(),
+                edge.last_change_time,
+                edge.state_last_left,
+                edge.time_to_leave,
+            )
+        })
+        .collect();
+    edges.sort_by(|x, y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+    (exes, edges)
+}
+
+#[tokio::test]
+async fn markov_state_survives_restart() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    let exe = |name: &str| {
+        (
+            PathBuf::from(format!("/test/{name}")),
+            vec![MapSegment::new(format!("/test/{name}.so"), 0, 4096, 0)],
+        )
+    };
+
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.system.exeprefix = vec!["!/".into(), "/test/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/test/".into()];
+    config.system.dopredict = false;
+
+    let services = |scanner: ScriptedScanner, repo: SqliteRepository| Services {
+        scanner: Box::new(scanner),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(repo),
+        clock: Box::new(SystemClock),
+    };
+
+    let scanner = ScriptedScanner::default();
+    let running = scanner.running.clone();
+    let repo = SqliteRepository::new(db_path.clone()).await.unwrap();
+    let mut engine = PreloadEngine::new(config.clone(), services(scanner, repo))
+        .await
+        .unwrap();
+    // Leave every edge in a state other than the one it starts in, with a
+    // dwell time on record.
+    for step in [
+        vec![exe("a"), exe("b"), exe("c")],
+        vec![exe("a")],
+        vec![exe("a"), exe("c")],
+    ] {
+        *running.lock().unwrap() = step;
+        engine.tick().await.unwrap();
+        engine.tick().await.unwrap();
+    }
+    engine.save().await.unwrap();
+    let before = learned_state(engine.stores());
+    assert_eq!(before.1.len(), 3);
+    assert!(
+        before
+            .1
+            .iter()
+            .any(|edge| edge.2 == MarkovState::Both.index())
+    );
+    drop(engine);
+
+    let repo = SqliteRepository::new(db_path).await.unwrap();
+    let engine = PreloadEngine::load(config.clone(), services(ScriptedScanner::default(), repo))
+        .await
+        .unwrap();
+    assert_eq!(learned_state(engine.stores()), before);
+}
+
+#[tokio::test]
+async fn inconsistent_state_is_repaired_on_load() {
+    // An exe-map link to an exe the file does not list.
+    let state = "\
+PRELOAD\t0.6.4\t100
+MAP\t1\t90\t0\t4096\t-1\tfile:///usr/lib/libfoo.so
+EXE\t2\t90\t50\t-1\tfile:///usr/bin/foo
+EXEMAP\t2\t1\t1
+";
+    let mut snapshot = import_legacy_state(state.as_bytes()).unwrap();
+    snapshot.state.exe_maps.push(ExeMapRecord {
+        exe_path: PathBuf::from("/usr/bin/gone"),
+        ..snapshot.state.exe_maps[0].clone()
+    });
+
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    SqliteRepository::new(db_path.clone())
+        .await
+        .unwrap()
+        .save(&snapshot)
+        .await
+        .unwrap();
+
+    let services = |repo: SqliteRepository, config: &Config| Services {
+        scanner: Box::new(StaticScanner {
+            observation: Vec::new(),
+        }),
+        admission: Box::new(DefaultAdmissionPolicy::new(config)),
+        updater: Box::new(DefaultModelUpdater::new(config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(repo),
+        clock: Box::new(SystemClock),
+    };
+
+    let mut config = Config::default();
+    config.persistence.repair = false;
+    let repo = SqliteRepository::new(db_path.clone()).await.unwrap();
+    assert!(
+        PreloadEngine::load(config.clone(), services(repo, &config))
+            .await
+            .is_err()
+    );
+
+    config.persistence.repair = true;
+    let repo = SqliteRepository::new(db_path).await.unwrap();
+    let engine = PreloadEngine::load(config.clone(), services(repo, &config))
+        .await
+        .unwrap();
+    let stores = engine.stores();
+    assert_eq!(stores.exes.iter().count(), 1);
+    assert_eq!(stores.model_time, 100);
+}
+
+/// A reload that names a new state location saves the model there, in the
+/// new backend's format, and removes the old state when asked. A location
+/// that cannot be opened leaves the state where it is.
+#[tokio::test]
+async fn reload_moves_state_to_the_new_location() {
+    let exe_path = PathBuf::from("/test/exe");
+    let observation = vec![
+        ObservationEvent::ObsBegin {
+            time: 10,
+            scan_id: 1,
+        },
+        ObservationEvent::ExeSeen {
+            path: exe_path.clone(),
+            pid: 1234,
+        },
+        ObservationEvent::MapSeen {
+            exe_path: exe_path.clone(),
+            map: MapSegment::new("/test/map", 0, 2048, 10),
+        },
+        ObservationEvent::ObsEnd {
+            time: 10,
+            scan_id: 1,
+            warnings: Vec::new(),
+        },
+    ];
+
+    let dir = tempdir().unwrap();
+    let old_path = dir.path().join("state.db");
+    let new_path = dir.path().join("state.bin");
+
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.model.cycle = Duration::from_secs(3600);
+    config.system.exeprefix = vec!["!/".into(), "/test/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/test/".into()];
+    config.system.dopredict = false;
+    config.persistence.state_path = Some(old_path.clone());
+
+    let services = Services {
+        scanner: Box::new(StaticScanner { observation }),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(SqliteRepository::new(old_path.clone()).await.unwrap()),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config.clone(), services).await.unwrap();
+    engine.tick().await.unwrap();
+    engine.save().await.unwrap();
+
+    let bundle = |config: &Config| {
+        Box::new(ReloadBundle {
+            config: config.clone(),
+            admission: Box::new(DefaultAdmissionPolicy::new(config)),
+            updater: Box::new(DefaultModelUpdater::new(config)),
+            predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+            planner: Box::new(GreedyPrefetchPlanner::new(config)),
+            prefetcher: Box::new(NoopPrefetcher),
+        })
+    };
+    let mut moved = config.clone();
+    moved.persistence.state_path = Some(new_path.clone());
+    moved.persistence.backend = StateBackend::File;
+    moved.persistence.remove_old_state = true;
+    let mut unreachable = moved.clone();
+    unreachable.persistence.url = Some("postgres://db/state".into());
+
+    let cancel = CancellationToken::new();
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    control_tx
+        .send(ControlEvent::Reload(bundle(&moved)))
+        .unwrap();
+    control_tx
+        .send(ControlEvent::Reload(bundle(&unreachable)))
+        .unwrap();
+    control_tx
+        .send(ControlEvent::Request(ControlRequest::Status, reply_tx))
+        .unwrap();
+    let engine_cancel = cancel.clone();
+    let engine_task = tokio::spawn(async move {
+        engine.run_until(engine_cancel, control_rx).await.unwrap();
+        engine
+    });
+    reply_rx.await.unwrap();
+    cancel.cancel();
+    let engine = engine_task.await.unwrap();
+
+    assert_eq!(
+        engine.config().persistence.state_url(),
+        moved.persistence.state_url()
+    );
+    drop(engine);
+    for suffix in ["", "-wal", "-shm", ".lock", ".1"] {
+        let mut old = old_path.clone().into_os_string();
+        old.push(suffix);
+        assert!(!PathBuf::from(old).exists(), "{suffix}");
+    }
+
+    let saved = FileRepository::open_read_only(new_path)
+        .unwrap()
+        .load()
+        .await
+        .unwrap();
+    assert!(saved.state.exes.iter().any(|exe| exe.path == exe_path));
+}
+
+/// An engine that keeps its model in memory only ignores state locations
+/// named by reloads.
+#[tokio::test]
+async fn reload_leaves_state_alone_without_persistence() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.model.cycle = Duration::from_secs(3600);
+    config.system.doscan = false;
+    config.system.dopredict = false;
+    config.persistence.state_path = Some(dir.path().join("state.db"));
+
+    let services = Services {
+        scanner: Box::new(StaticScanner {
+            observation: Vec::new(),
+        }),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config.clone(), services)
+        .await
+        .unwrap()
+        .without_persistence();
+
+    let mut moved = config.clone();
+    moved.persistence.state_path = Some(dir.path().join("moved.bin"));
+    moved.persistence.backend = StateBackend::File;
+    let bundle = Box::new(ReloadBundle {
+        config: moved.clone(),
+        admission: Box::new(DefaultAdmissionPolicy::new(&moved)),
+        updater: Box::new(DefaultModelUpdater::new(&moved)),
+        predictor: Box::new(PathScorePredictor { scores: Vec::new() }),
+        planner: Box::new(GreedyPrefetchPlanner::new(&moved)),
+        prefetcher: Box::new(NoopPrefetcher),
+    });
+
+    let cancel = CancellationToken::new();
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    control_tx.send(ControlEvent::Reload(bundle)).unwrap();
+    control_tx
+        .send(ControlEvent::Request(ControlRequest::Status, reply_tx))
+        .unwrap();
+    let engine_cancel = cancel.clone();
+    let engine_task = tokio::spawn(async move {
+        engine.run_until(engine_cancel, control_rx).await.unwrap();
+        engine
+    });
+    reply_rx.await.unwrap();
+    cancel.cancel();
+    engine_task.await.unwrap();
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
 }
@@ -0,0 +1,101 @@
+#![forbid(unsafe_code)]
+
+use config::Config;
+use orchestrator::clock::SystemClock;
+use orchestrator::domain::MapSegment;
+use orchestrator::evaluation::{EvaluationOptions, Evaluator};
+use orchestrator::observation::{
+    DefaultAdmissionPolicy, DefaultModelUpdater, Observation, ObservationEvent, ReplayScanner,
+    TraceWriter,
+};
+use orchestrator::persistence::NoopRepository;
+use orchestrator::prediction::MarkovPredictor;
+use orchestrator::prefetch::{GreedyPrefetchPlanner, NoopMapValidator, NoopPrefetcher};
+use orchestrator::{PreloadEngine, Services};
+use std::path::Path;
+
+fn observation(running: &[(&str, &str, u64)]) -> Observation {
+    let mut events = vec![ObservationEvent::ObsBegin {
+        time: 0,
+        scan_id: 0,
+    }];
+    for (pid, (exe, map, length)) in running.iter().enumerate() {
+        events.push(ObservationEvent::ExeSeen {
+            path: exe.into(),
+            pid: pid as u32 + 1,
+        });
+        events.push(ObservationEvent::MapSeen {
+            exe_path: exe.into(),
+            map: MapSegment::new(*map, 0, *length, 0),
+        });
+    }
+    events.push(ObservationEvent::ObsEnd {
+        time: 0,
+        scan_id: 0,
+        warnings: Vec::new(),
+    });
+    events
+}
+
+#[tokio::test]
+async fn evaluation_scores_predictions_against_next_cycle_starts() {
+    let a = ("/usr/bin/a", "/usr/lib/liba.so", 8192);
+    let b = ("/usr/bin/b", "/usr/lib/libb.so", 4096);
+
+    // `a` starts on every odd tick; `b` only runs on the first one.
+    let dir = tempfile::tempdir().unwrap();
+    let trace_path = dir.path().join("trace.jsonl");
+    let mut writer = TraceWriter::create(&trace_path).unwrap();
+    writer.write(&observation(&[a, b])).unwrap();
+    for tick in 2..=10 {
+        let running: &[_] = if tick % 2 == 1 { &[a] } else { &[] };
+        writer.write(&observation(running)).unwrap();
+    }
+    drop(writer);
+
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.system.exeprefix = vec!["!/".into(), "/usr/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/usr/".into()];
+
+    let services = Services {
+        scanner: Box::new(ReplayScanner::open(Path::new(&trace_path)).unwrap()),
+        admission: Box::new(DefaultAdmissionPolicy::new(&config)),
+        updater: Box::new(DefaultModelUpdater::new(&config)),
+        predictor: Box::new(MarkovPredictor::new(&config)),
+        planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+    let mut engine = PreloadEngine::new(config, services).await.unwrap();
+
+    let options = EvaluationOptions {
+        thresholds: vec![0.0, 1.1],
+        budgets: vec![0, 1 << 20],
+    };
+    let report = Evaluator::new(options).run(&mut engine).await.unwrap();
+
+    assert_eq!(report.ticks, 9);
+    assert_eq!(report.starts, 4);
+    assert_eq!(report.unseen_starts, 0);
+    // `a` is idle on 4 scored ticks, `b` on all 9 after the first.
+    assert_eq!(report.samples, 4 + 8);
+    let auc = report.auc.expect("both outcomes observed");
+    assert!((0.0..=1.0).contains(&auc));
+
+    let everything = &report.thresholds[0];
+    assert_eq!(everything.recall(), Some(1.0));
+    assert_eq!(everything.precision(), Some(4.0 / 12.0));
+    let nothing = &report.thresholds[1];
+    assert_eq!(nothing.precision(), None);
+    assert_eq!(nothing.false_negatives, 4);
+
+    let empty = &report.budgets[0];
+    assert_eq!((empty.useful_bytes, empty.wasted_bytes), (0, 0));
+    assert_eq!(empty.missed_bytes, 4 * 8192);
+    for budget in &report.budgets {
+        assert_eq!(budget.useful_bytes + budget.missed_bytes, 4 * 8192);
+    }
+}
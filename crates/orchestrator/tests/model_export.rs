@@ -0,0 +1,174 @@
+#![forbid(unsafe_code)]
+
+use orchestrator::StateRepository;
+use orchestrator::domain::{ExeIdentity, FileIdentity, MapKey, MarkovState};
+use orchestrator::error::Error;
+use orchestrator::persistence::{
+    EXPORT_FORMAT, ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION,
+    SnapshotMeta, SqliteRepository, StateSnapshot, StoresSnapshot, export_json, import_json,
+};
+use std::time::{Duration, SystemTime};
+
+fn sample() -> StoresSnapshot {
+    let exe = |path: &str, running| ExeRecord {
+        path: path.into(),
+        total_running_time: running,
+        last_seen_time: Some(running),
+        running: false,
+        change_time: running,
+        identity: None,
+    };
+    StoresSnapshot {
+        meta: SnapshotMeta {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            app_version: Some("0.1.0".into()),
+            created_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        },
+        state: StateSnapshot {
+            model_time: 100,
+            last_accounting_time: 90,
+            exes: vec![
+                exe("/usr/bin/b", 20),
+                ExeRecord {
+                    identity: Some(ExeIdentity::BuildId("abcd".into())),
+                    ..exe("/usr/bin/a", 40)
+                },
+            ],
+            maps: vec![MapRecord {
+                path: "/usr/lib/liba.so".into(),
+                offset: 0,
+                length: 8192,
+                update_time: 80,
+                identity: Some(FileIdentity {
+                    device: 1,
+                    inode: 2,
+                    mtime_ns: 3,
+                }),
+            }],
+            exe_maps: vec![
+                ExeMapRecord {
+                    exe_path: "/usr/bin/b".into(),
+                    map_key: MapKey::new("/usr/lib/liba.so", 0, 8192),
+                    prob: 0.5,
+                },
+                ExeMapRecord {
+                    exe_path: "/usr/bin/a".into(),
+                    map_key: MapKey::new("/usr/lib/liba.so", 0, 8192),
+                    prob: 1.0,
+                },
+            ],
+            markov_edges: vec![MarkovRecord {
+                exe_a: "/usr/bin/a".into(),
+                exe_b: "/usr/bin/b".into(),
+                state: MarkovState::BOnly,
+                last_change_time: 95,
+                state_last_left: [60, 70, 80, 90],
+                time_to_leave: [1.0, 2.0, 3.0, 4.0],
+                transition_prob: [[0.25; 4]; 4],
+                both_running_time: 15,
+            }],
+        },
+    }
+}
+
+fn export(snapshot: &StoresSnapshot) -> String {
+    let mut out = Vec::new();
+    export_json(snapshot, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[tokio::test]
+async fn export_imports_into_another_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = SqliteRepository::new(dir.path().join("a.db"))
+        .await
+        .unwrap();
+    source.save(&sample()).await.unwrap();
+
+    let json = export(&source.load().await.unwrap());
+    assert!(json.contains(EXPORT_FORMAT));
+    assert!(json.contains("\"created_at\": 1700000000"));
+    assert!(json.contains("build-id:abcd"));
+
+    let imported = import_json(json.as_bytes()).unwrap();
+    let target = SqliteRepository::new(dir.path().join("b.db"))
+        .await
+        .unwrap();
+    target.save(&imported).await.unwrap();
+
+    // Exporting the copy yields the same document.
+    assert_eq!(export(&target.load().await.unwrap()), json);
+}
+
+#[test]
+fn export_is_independent_of_record_order() {
+    let mut shuffled = sample();
+    shuffled.state.exes.reverse();
+    shuffled.state.exe_maps.reverse();
+    assert_eq!(export(&shuffled), export(&sample()));
+}
+
+#[test]
+fn import_rejects_dangling_references_and_unknown_versions() {
+    let mut dangling = sample();
+    dangling.state.exe_maps[0].exe_path = "/usr/bin/gone".into();
+    let err = import_json(export(&dangling).as_bytes()).unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidSnapshot(message) if message.contains("/usr/bin/gone")),
+        "{err}"
+    );
+
+    let mut dangling = sample();
+    dangling.state.markov_edges[0].exe_b = "/usr/bin/gone".into();
+    assert!(matches!(
+        import_json(export(&dangling).as_bytes()),
+        Err(Error::InvalidSnapshot(_))
+    ));
+
+    let mut newer = sample();
+    newer.meta.schema_version = SNAPSHOT_SCHEMA_VERSION + 1;
+    assert!(matches!(
+        import_json(export(&newer).as_bytes()),
+        Err(Error::UnsupportedSnapshotVersion { .. })
+    ));
+
+    let other = export(&sample()).replace(EXPORT_FORMAT, "something-else");
+    assert!(matches!(
+        import_json(other.as_bytes()),
+        Err(Error::InvalidSnapshot(_))
+    ));
+}
+
+#[test]
+fn non_utf8_paths_survive_export() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    let exe = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/app"));
+    let lib = PathBuf::from(OsStr::from_bytes(b"/opt/100%/lib\xff.so"));
+    let mut snapshot = sample();
+    snapshot.state.exes[1].path = exe.clone();
+    snapshot.state.maps[0].path = lib.clone();
+    for exe_map in &mut snapshot.state.exe_maps {
+        exe_map.map_key = MapKey::new(lib.clone(), 0, 8192);
+    }
+    snapshot.state.exe_maps[1].exe_path = exe.clone();
+    snapshot.state.markov_edges[0].exe_a = exe.clone();
+
+    let json = export(&snapshot);
+    assert!(json.contains("/opt/caf%E9/app"), "{json}");
+    assert!(json.contains("/opt/100%25/lib%FF.so"), "{json}");
+
+    let state = import_json(json.as_bytes()).unwrap().state;
+    assert!(state.exes.iter().any(|record| record.path == exe));
+    assert_eq!(state.maps[0].path, lib);
+    assert!(
+        state
+            .exe_maps
+            .iter()
+            .all(|record| record.map_key.path == lib)
+    );
+    assert!(state.exe_maps.iter().any(|record| record.exe_path == exe));
+    assert_eq!(state.markov_edges[0].exe_a, exe);
+}
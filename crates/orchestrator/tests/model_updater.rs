@@ -1,9 +1,9 @@
 #![forbid(unsafe_code)]
 
-use config::Config;
+use config::{Config, IdentityMode, PathRewrite};
 use orchestrator::{
     ModelUpdater,
-    domain::MapSegment,
+    domain::{ExeIdentity, ExeKey, MapKey, MapSegment},
     observation::{DefaultAdmissionPolicy, DefaultModelUpdater, ObservationEvent},
     stores::Stores,
 };
@@ -47,3 +47,278 @@ fn admits_exe_and_maps() {
     assert_eq!(stores.exes.iter().count(), 1);
     assert_eq!(stores.maps.iter().count(), 1);
 }
+
+#[test]
+fn evicts_exes_unseen_for_longer_than_max_age() {
+    let mut config = Config::default();
+    config.model.eviction.max_exe_age = Some(std::time::Duration::from_secs(50));
+    let policy = DefaultAdmissionPolicy::new(&config);
+    let mut updater = DefaultModelUpdater::new(&config);
+    let mut stores = Stores::default();
+
+    let scan = |time: u64, exes: &[&str]| {
+        let mut events = vec![ObservationEvent::ObsBegin { time, scan_id: 1 }];
+        for (pid, exe) in exes.iter().enumerate() {
+            events.push(ObservationEvent::ExeSeen {
+                path: PathBuf::from(exe),
+                pid: pid as u32 + 1,
+            });
+            events.push(ObservationEvent::MapSeen {
+                exe_path: PathBuf::from(exe),
+                map: MapSegment::new(format!("{exe}.so"), 0, config.model.minsize, time),
+            });
+        }
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id: 1,
+            warnings: Vec::new(),
+        });
+        events
+    };
+
+    updater
+        .apply(
+            &mut stores,
+            &scan(0, &["/usr/bin/old", "/usr/bin/kept"]),
+            &policy,
+        )
+        .unwrap();
+    let delta = updater
+        .apply(&mut stores, &scan(40, &["/usr/bin/kept"]), &policy)
+        .unwrap();
+    assert!(delta.evicted_exes.is_empty(), "delta: {:?}", delta);
+    assert_eq!(stores.markov.iter().count(), 1);
+
+    let delta = updater
+        .apply(&mut stores, &scan(100, &["/usr/bin/kept"]), &policy)
+        .unwrap();
+    let evicted: Vec<_> = delta.evicted_exes.iter().map(|key| key.path()).collect();
+    assert_eq!(evicted, [&PathBuf::from("/usr/bin/old")]);
+    assert_eq!(delta.evicted_maps.len(), 1);
+    assert_eq!(delta.evicted_maps[0].path, PathBuf::from("/usr/bin/old.so"));
+    assert_eq!(stores.exes.len(), 1);
+    assert_eq!(stores.maps.len(), 1);
+    assert_eq!(stores.markov.iter().count(), 0);
+}
+
+#[test]
+fn learns_how_often_each_map_is_used_per_run() {
+    let config = Config::default();
+    let policy = DefaultAdmissionPolicy::new(&config);
+    let mut updater = DefaultModelUpdater::new(&config);
+    let mut stores = Stores::default();
+
+    let exe = "/usr/bin/editor";
+    let scan = |time: u64, maps: &[&str]| {
+        let mut events = vec![ObservationEvent::ObsBegin { time, scan_id: 1 }];
+        if !maps.is_empty() {
+            events.push(ObservationEvent::ExeSeen {
+                path: PathBuf::from(exe),
+                pid: 1,
+            });
+        }
+        for map in maps {
+            events.push(ObservationEvent::MapSeen {
+                exe_path: PathBuf::from(exe),
+                map: MapSegment::new(*map, 0, config.model.minsize, time),
+            });
+        }
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id: 1,
+            warnings: Vec::new(),
+        });
+        events
+    };
+    let lib = "/usr/lib/libedit.so";
+    let plugin = "/usr/lib/editor/spell.so";
+
+    // First run loads the plugin, the next two do not.
+    for (start, maps) in [(0, vec![lib, plugin]), (100, vec![lib]), (200, vec![lib])] {
+        updater
+            .apply(&mut stores, &scan(start, &maps), &policy)
+            .unwrap();
+        updater
+            .apply(&mut stores, &scan(start + 50, &[]), &policy)
+            .unwrap();
+    }
+
+    let exe_id = stores.exes.id_by_key(&ExeKey::new(exe)).unwrap();
+    let prob = |path: &str| {
+        let map_id = stores
+            .maps
+            .id_by_key(&MapKey::new(path, 0, config.model.minsize))
+            .unwrap();
+        stores.exe_maps.prob(exe_id, map_id).unwrap()
+    };
+    assert!((prob(lib) - 1.0).abs() < 1e-6);
+    assert!(prob(plugin) < 0.5, "plugin prob: {}", prob(plugin));
+}
+
+#[test]
+fn upgraded_exe_inherits_history_of_its_old_path() {
+    let mut config = Config::default();
+    config.model.identity.mode = IdentityMode::Normalize;
+    config.model.identity.normalize = vec![PathRewrite {
+        pattern: r"^/usr/lib/app-[0-9.]+/".into(),
+        replace: "/usr/lib/app/".into(),
+    }];
+    let policy = DefaultAdmissionPolicy::new(&config);
+    let mut updater = DefaultModelUpdater::new(&config);
+    let mut stores = Stores::default();
+
+    let scan = |time: u64, exes: &[&str]| {
+        let mut events = vec![ObservationEvent::ObsBegin { time, scan_id: 1 }];
+        for (pid, exe) in exes.iter().enumerate() {
+            events.push(ObservationEvent::ExeSeen {
+                path: PathBuf::from(exe),
+                pid: pid as u32 + 1,
+            });
+            events.push(ObservationEvent::MapSeen {
+                exe_path: PathBuf::from(exe),
+                map: MapSegment::new(format!("{exe}.so"), 0, config.model.minsize, time),
+            });
+        }
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id: 1,
+            warnings: Vec::new(),
+        });
+        events
+    };
+    let old = "/usr/lib/app-1.0/app";
+    let new = "/usr/lib/app-1.1/app";
+    let shell = "/usr/bin/shell";
+
+    updater
+        .apply(&mut stores, &scan(0, &[old, shell]), &policy)
+        .unwrap();
+    updater
+        .apply(&mut stores, &scan(50, &[old, shell]), &policy)
+        .unwrap();
+    updater
+        .apply(&mut stores, &scan(100, &[shell]), &policy)
+        .unwrap();
+    let delta = updater
+        .apply(&mut stores, &scan(150, &[new, shell]), &policy)
+        .unwrap();
+
+    assert_eq!(
+        delta.merged_exes,
+        vec![(ExeKey::new(old), ExeKey::new(new))]
+    );
+    assert!(stores.exes.id_by_key(&ExeKey::new(old)).is_none());
+    let new_id = stores.exes.id_by_key(&ExeKey::new(new)).unwrap();
+    let shell_id = stores.exes.id_by_key(&ExeKey::new(shell)).unwrap();
+    assert_eq!(stores.exes.get(new_id).unwrap().total_running_time, 100);
+    let (_, edge) = stores
+        .markov
+        .iter()
+        .find(|(key, _)| key.a() == new_id.min(shell_id) && key.b() == new_id.max(shell_id))
+        .expect("edge carried over");
+    assert_eq!(edge.both_running_time, 100);
+    // The old version's library is still known until eviction drops it.
+    assert_eq!(stores.exe_maps.maps_for_exe(new_id).count(), 2);
+}
+
+/// A minimal 64-bit ELF image whose only note is a GNU build-id.
+fn elf_with_build_id(id: &[u8]) -> Vec<u8> {
+    const PT_NOTE: u32 = 4;
+    const NT_GNU_BUILD_ID: u32 = 3;
+
+    let mut note = Vec::new();
+    note.extend_from_slice(&4u32.to_le_bytes());
+    note.extend_from_slice(&(id.len() as u32).to_le_bytes());
+    note.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+    note.extend_from_slice(b"GNU\0");
+    note.extend_from_slice(id);
+    note.resize(note.len().div_ceil(4) * 4, 0);
+
+    let phoff = 64usize;
+    let note_offset = phoff + 56;
+    let mut image = vec![0u8; note_offset];
+    image[..4].copy_from_slice(b"\x7fELF");
+    image[4] = 2;
+    image[5] = 1;
+    image[32..40].copy_from_slice(&(phoff as u64).to_le_bytes());
+    image[54..56].copy_from_slice(&56u16.to_le_bytes());
+    image[56..58].copy_from_slice(&1u16.to_le_bytes());
+    image[phoff..phoff + 4].copy_from_slice(&PT_NOTE.to_le_bytes());
+    image[phoff + 8..phoff + 16].copy_from_slice(&(note_offset as u64).to_le_bytes());
+    image[phoff + 32..phoff + 40].copy_from_slice(&(note.len() as u64).to_le_bytes());
+    image[phoff + 48..phoff + 56].copy_from_slice(&4u64.to_le_bytes());
+    image.extend_from_slice(&note);
+    image
+}
+
+#[test]
+fn replaced_binary_takes_its_new_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let install = |name: &str, id: &[u8]| {
+        // Package managers install through a rename, like this.
+        let staged = dir.path().join(format!("{name}.new"));
+        std::fs::write(&staged, elf_with_build_id(id)).unwrap();
+        std::fs::rename(&staged, dir.path().join(name)).unwrap();
+        dir.path().join(name)
+    };
+    let old = install("old", &[0x01]);
+    let app = install("app", &[0x02]);
+
+    let mut config = Config::default();
+    config.model.identity.mode = IdentityMode::BuildId;
+    config.system.exeprefix = Vec::new();
+    config.system.mapprefix = Vec::new();
+    let policy = DefaultAdmissionPolicy::new(&config);
+    let mut updater = DefaultModelUpdater::new(&config);
+    let mut stores = Stores::default();
+
+    let scan = |time: u64, exes: &[&PathBuf]| {
+        let mut events = vec![ObservationEvent::ObsBegin { time, scan_id: 1 }];
+        for (pid, exe) in exes.iter().enumerate() {
+            events.push(ObservationEvent::ExeSeen {
+                path: exe.to_path_buf(),
+                pid: pid as u32 + 1,
+            });
+            events.push(ObservationEvent::MapSeen {
+                exe_path: exe.to_path_buf(),
+                map: MapSegment::new(exe.with_extension("so"), 0, config.model.minsize, time),
+            });
+        }
+        events.push(ObservationEvent::ObsEnd {
+            time,
+            scan_id: 1,
+            warnings: Vec::new(),
+        });
+        events
+    };
+    let identity = |stores: &Stores, path: &PathBuf| {
+        let id = stores.exes.id_by_key(&ExeKey::new(path.clone())).unwrap();
+        stores.exes.get(id).unwrap().identity.clone()
+    };
+
+    updater
+        .apply(&mut stores, &scan(0, &[&old, &app]), &policy)
+        .unwrap();
+    updater
+        .apply(&mut stores, &scan(50, &[&app]), &policy)
+        .unwrap();
+    assert_eq!(
+        identity(&stores, &app),
+        Some(ExeIdentity::BuildId("02".into()))
+    );
+
+    // `app` is upgraded in place to the build `old` was running.
+    install("app", &[0x01]);
+    let delta = updater
+        .apply(&mut stores, &scan(100, &[&app]), &policy)
+        .unwrap();
+    assert_eq!(
+        identity(&stores, &app),
+        Some(ExeIdentity::BuildId("01".into()))
+    );
+    assert_eq!(
+        delta.merged_exes,
+        vec![(ExeKey::new(old.clone()), ExeKey::new(app.clone()))]
+    );
+    assert!(stores.exes.id_by_key(&ExeKey::new(old)).is_none());
+}
@@ -0,0 +1,63 @@
+#![forbid(unsafe_code)]
+
+mod common;
+
+use common::ProcFixture;
+use orchestrator::observation::{NetlinkScanner, ObservationEvent, Scanner};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+#[test]
+fn netlink_scanner_reports_short_lived_process() {
+    // The proc connector needs CAP_NET_ADMIN; nothing to check without it.
+    let Ok(mut scanner) = NetlinkScanner::new() else {
+        return;
+    };
+    scanner.scan(0, 1).expect("initial scan");
+
+    let mut child = Command::new("sleep")
+        .arg("0.2")
+        .spawn()
+        .expect("spawn sleep");
+    let pid = child.id();
+    child.wait().expect("wait for sleep");
+    sleep(Duration::from_millis(300));
+
+    let observation = scanner.scan(1, 2).expect("scan");
+    let seen = observation
+        .iter()
+        .any(|event| matches!(event, ObservationEvent::ExeSeen { pid: seen, .. } if *seen == pid));
+    assert!(seen, "expected exited process {pid} to be reported");
+
+    let observation = scanner.scan(2, 3).expect("scan");
+    let seen_again = observation
+        .iter()
+        .any(|event| matches!(event, ObservationEvent::ExeSeen { pid: seen, .. } if *seen == pid));
+    assert!(!seen_again, "exited process must only be reported once");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn netlink_scanner_reads_the_given_proc_root() {
+    let fixture = ProcFixture::new();
+    fixture.meminfo(8_000, 4_000, 2_000);
+    fixture
+        .process(4_000_000, "/usr/bin/app")
+        .map("/usr/lib/libfoo.so", 0, 4096);
+    let Ok(mut scanner) = NetlinkScanner::with_root(fixture.root()) else {
+        return;
+    };
+
+    let observation = scanner.scan(0, 1).expect("scan");
+    assert!(
+        observation
+            .iter()
+            .any(|event| matches!(event, ObservationEvent::ExeSeen { pid: 4_000_000, .. }))
+    );
+    assert!(observation.iter().any(|event| matches!(
+        event,
+        ObservationEvent::MemStat { mem } if mem.total == 8_000
+    )));
+}
@@ -0,0 +1,304 @@
+#![forbid(unsafe_code)]
+
+mod common;
+
+use common::ProcFixture;
+use config::Config;
+use orchestrator::domain::{FileIdentity, MapSegment};
+use orchestrator::observation::{
+    Observation, ObservationEvent, ProcfsScanner, ScanWarning, Scanner,
+};
+use orchestrator::prediction::Prediction;
+use orchestrator::prefetch::{GreedyPrefetchPlanner, PrefetchPlanner};
+use orchestrator::stores::Stores;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+fn exes(observation: &Observation) -> Vec<(PathBuf, u32)> {
+    observation
+        .iter()
+        .filter_map(|event| match event {
+            ObservationEvent::ExeSeen { path, pid } => Some((path.clone(), *pid)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn maps(observation: &Observation) -> Vec<(PathBuf, MapSegment)> {
+    observation
+        .iter()
+        .filter_map(|event| match event {
+            ObservationEvent::MapSeen { exe_path, map } => Some((exe_path.clone(), map.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn warnings(observation: &Observation) -> Vec<ScanWarning> {
+    observation
+        .iter()
+        .find_map(|event| match event {
+            ObservationEvent::ObsEnd { warnings, .. } => Some(warnings.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn fixture_process_is_observed_with_maps_and_memstat() {
+    let fixture = ProcFixture::new();
+    fixture.meminfo(8_000, 4_000, 2_000).vmstat(10, 20);
+    fixture
+        .process(42, "/usr/bin/app")
+        .map("/usr/lib/libfoo.so", 0, 8192)
+        .map("/usr/bin/app", 4096, 4096)
+        .anon_map(4096);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(7, 1).unwrap();
+
+    assert!(matches!(
+        observation.first(),
+        Some(ObservationEvent::ObsBegin {
+            time: 7,
+            scan_id: 1
+        })
+    ));
+    assert!(matches!(
+        observation.last(),
+        Some(ObservationEvent::ObsEnd {
+            time: 7,
+            scan_id: 1,
+            ..
+        })
+    ));
+    assert_eq!(
+        exes(&observation),
+        vec![(PathBuf::from("/usr/bin/app"), 42)]
+    );
+
+    let maps = maps(&observation);
+    assert_eq!(
+        maps,
+        vec![
+            (
+                PathBuf::from("/usr/bin/app"),
+                MapSegment::new("/usr/lib/libfoo.so", 0, 8192, 7)
+            ),
+            (
+                PathBuf::from("/usr/bin/app"),
+                MapSegment::new("/usr/bin/app", 4096, 4096, 7)
+            ),
+        ]
+    );
+
+    let page_kb = procfs::page_size() as i64 / 1024;
+    let mem = observation
+        .iter()
+        .find_map(|event| match event {
+            ObservationEvent::MemStat { mem } => Some(*mem),
+            _ => None,
+        })
+        .expect("memstat");
+    assert_eq!((mem.total, mem.free, mem.cached), (8_000, 4_000, 2_000));
+    assert_eq!((mem.pagein, mem.pageout), (10 * page_kb, 20 * page_kb));
+}
+
+#[test]
+fn prefetch_budget_follows_meminfo_in_kilobytes() {
+    let fixture = ProcFixture::new();
+    fixture.meminfo(8_000, 4_000, 2_000).vmstat(0, 0);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let mem = scanner
+        .scan(0, 1)
+        .unwrap()
+        .iter()
+        .find_map(|event| match event {
+            ObservationEvent::MemStat { mem } => Some(*mem),
+            _ => None,
+        })
+        .expect("memstat");
+
+    // Default policy: -10% of total + 50% of free + 0% of cached.
+    let planner = GreedyPrefetchPlanner::new(&Config::default());
+    let plan = planner.plan(&Prediction::default(), &Stores::default(), &mem);
+    assert_eq!(plan.budget_bytes, 1_200 * 1024);
+}
+
+#[test]
+fn deleted_binaries_and_maps_are_skipped() {
+    let fixture = ProcFixture::new();
+    fixture
+        .process(1, "/usr/bin/gone (deleted)")
+        .map("/usr/lib/libgone.so", 0, 4096);
+    fixture
+        .process(2, "/usr/bin/app")
+        .map("/usr/lib/libold.so (deleted)", 0, 4096)
+        .map("/usr/lib/libnew.so", 0, 4096);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(0, 1).unwrap();
+
+    assert_eq!(exes(&observation), vec![(PathBuf::from("/usr/bin/app"), 2)]);
+    let paths: Vec<_> = maps(&observation)
+        .into_iter()
+        .map(|(_, map)| map.path)
+        .collect();
+    assert_eq!(paths, vec![PathBuf::from("/usr/lib/libnew.so")]);
+}
+
+#[test]
+fn non_utf8_map_paths_are_kept_exactly() {
+    let latin1 = PathBuf::from(OsStr::from_bytes(b"/usr/lib/caf\xe9.so"));
+    let fixture = ProcFixture::new();
+    fixture
+        .process(7, "/usr/bin/app")
+        .map(&latin1, 0, 4096)
+        .map("/usr/lib/libfoo.so", 0, 4096);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(0, 1).unwrap();
+
+    assert!(warnings(&observation).is_empty());
+    let paths: Vec<_> = maps(&observation)
+        .into_iter()
+        .map(|(_, map)| map.path)
+        .collect();
+    assert_eq!(paths, vec![latin1, PathBuf::from("/usr/lib/libfoo.so")]);
+}
+
+#[test]
+fn prelink_suffixes_are_trimmed() {
+    let fixture = ProcFixture::new();
+    fixture.process(5, "/usr/bin/app.#prelink#.a1b2c3").map(
+        "/usr/lib/libfoo.so.#prelink#.d4e5f6",
+        0,
+        4096,
+    );
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(0, 1).unwrap();
+
+    assert_eq!(exes(&observation), vec![(PathBuf::from("/usr/bin/app"), 5)]);
+    assert_eq!(
+        maps(&observation),
+        vec![(
+            PathBuf::from("/usr/bin/app"),
+            MapSegment::new("/usr/lib/libfoo.so", 0, 4096, 0)
+        )]
+    );
+}
+
+#[test]
+fn unreadable_maps_are_reported_as_warnings() {
+    let fixture = ProcFixture::new();
+    fixture
+        .process(9, "/usr/bin/secret")
+        .map("/usr/lib/libfoo.so", 0, 4096)
+        .unreadable_maps();
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(0, 1).unwrap();
+
+    assert_eq!(
+        exes(&observation),
+        vec![(PathBuf::from("/usr/bin/secret"), 9)]
+    );
+    assert!(maps(&observation).is_empty());
+    let warnings = warnings(&observation);
+    assert!(matches!(
+        warnings.as_slice(),
+        [ScanWarning::MapScanFailed { pid: 9, .. }]
+    ));
+}
+
+#[test]
+fn vanished_pids_are_skipped() {
+    let fixture = ProcFixture::new();
+    fixture.vanished(3);
+    fixture
+        .process(4, "/usr/bin/app")
+        .map("/usr/lib/libfoo.so", 0, 4096);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let observation = scanner.scan(0, 1).unwrap();
+
+    assert_eq!(exes(&observation), vec![(PathBuf::from("/usr/bin/app"), 4)]);
+    assert!(warnings(&observation).is_empty());
+}
+
+#[test]
+fn changed_mappings_are_reread() {
+    let fixture = ProcFixture::new();
+    let process = fixture
+        .process(11, "/usr/bin/app")
+        .map("/usr/lib/libfoo.so", 0, 4096);
+
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    assert_eq!(maps(&scanner.scan(0, 1).unwrap()).len(), 1);
+
+    // dlopen() grows the address space, which invalidates the cached maps.
+    let process = process.map("/usr/lib/libplugin.so", 0, 4096);
+    assert_eq!(maps(&scanner.scan(1, 2).unwrap()).len(), 2);
+
+    // A restarted process with the same pid is parsed from scratch, even
+    // when its address space happens to be the same size.
+    process.restart(500).map("/usr/lib/libnew.so", 0, 8192);
+    let paths: Vec<PathBuf> = maps(&scanner.scan(2, 3).unwrap())
+        .into_iter()
+        .map(|(_, map)| map.path)
+        .collect();
+    assert_eq!(paths, [PathBuf::from("/usr/lib/libnew.so")]);
+}
+
+#[test]
+fn replaced_libraries_report_their_new_identity() {
+    let fixture = ProcFixture::new();
+    let files = tempfile::tempdir().unwrap();
+    let library = files.path().join("libfoo.so");
+    std::fs::write(&library, b"old").unwrap();
+    let _process = fixture.process(12, "/usr/bin/app").map(&library, 0, 4096);
+
+    let identity = |observation: &Observation| {
+        let maps = maps(observation);
+        assert_eq!(maps.len(), 1);
+        maps[0].1.identity
+    };
+    let mut scanner = ProcfsScanner::with_root(fixture.root());
+    let before = identity(&scanner.scan(0, 1).unwrap());
+    assert_eq!(before, FileIdentity::of(&library).ok());
+
+    // An upgrade renames a new file over the old one; the process keeps
+    // running with the same maps, so they are served from the cache.
+    let staged = files.path().join("libfoo.so.new");
+    std::fs::write(&staged, b"new").unwrap();
+    std::fs::rename(&staged, &library).unwrap();
+    let after = identity(&scanner.scan(1, 2).unwrap());
+    assert_ne!(after, before);
+    assert_eq!(after, FileIdentity::of(&library).ok());
+}
+
+#[test]
+fn owner_filter_skips_other_users() {
+    use std::os::unix::fs::MetadataExt;
+
+    let fixture = ProcFixture::new();
+    fixture.meminfo(8_000, 4_000, 2_000).vmstat(10, 20);
+    fixture
+        .process(42, "/usr/bin/app")
+        .map("/usr/lib/libfoo.so", 0, 8192);
+    let uid = std::fs::metadata(fixture.root()).unwrap().uid();
+
+    let mut own = ProcfsScanner::with_root(fixture.root()).with_owner(uid);
+    assert_eq!(
+        exes(&own.scan(1, 1).unwrap()),
+        vec![(PathBuf::from("/usr/bin/app"), 42)]
+    );
+
+    let mut other = ProcfsScanner::with_root(fixture.root()).with_owner(uid + 1);
+    let observation = other.scan(1, 1).unwrap();
+    assert!(exes(&observation).is_empty());
+    assert!(maps(&observation).is_empty());
+}
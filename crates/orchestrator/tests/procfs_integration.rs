@@ -5,7 +5,7 @@ use orchestrator::clock::SystemClock;
 use orchestrator::observation::{DefaultAdmissionPolicy, DefaultModelUpdater, ProcfsScanner};
 use orchestrator::persistence::NoopRepository;
 use orchestrator::prediction::MarkovPredictor;
-use orchestrator::prefetch::{GreedyPrefetchPlanner, NoopPrefetcher};
+use orchestrator::prefetch::{GreedyPrefetchPlanner, NoopMapValidator, NoopPrefetcher};
 use orchestrator::{PreloadEngine, Services};
 
 #[cfg(target_os = "linux")]
@@ -21,11 +21,12 @@ async fn procfs_scanner_observes_current_exe() {
     config.system.dopredict = false;
 
     let services = Services {
-        scanner: Box::new(ProcfsScanner),
+        scanner: Box::new(ProcfsScanner::default()),
         admission: Box::new(DefaultAdmissionPolicy::new(&config)),
         updater: Box::new(DefaultModelUpdater::new(&config)),
         predictor: Box::new(MarkovPredictor::new(&config)),
         planner: Box::new(GreedyPrefetchPlanner::new(&config)),
+        validator: Box::new(NoopMapValidator),
         prefetcher: Box::new(NoopPrefetcher),
         repo: Box::new(NoopRepository),
         clock: Box::new(SystemClock),
@@ -1,12 +1,14 @@
 #![forbid(unsafe_code)]
 
 use orchestrator::StateRepository;
-use orchestrator::domain::MapKey;
+use orchestrator::domain::{ExeIdentity, MapKey, MarkovState};
+use orchestrator::error::Error;
 use orchestrator::persistence::{
-    ExeMapRecord, ExeRecord, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION, SnapshotMeta,
-    SqliteRepository, StateSnapshot, StoresSnapshot,
+    ExeMapRecord, ExeRecord, FileRepository, MapRecord, MarkovRecord, SNAPSHOT_SCHEMA_VERSION,
+    SnapshotDelta, SnapshotMeta, SqliteRepository, StateSnapshot, StoresSnapshot,
 };
 use std::path::PathBuf;
+use std::time::Duration;
 use tempfile::tempdir;
 
 #[tokio::test]
@@ -27,12 +29,16 @@ async fn sqlite_roundtrip_snapshot() {
                 path: PathBuf::from("/usr/bin/app"),
                 total_running_time: 42,
                 last_seen_time: Some(9),
+                running: true,
+                change_time: 8,
+                identity: None,
             }],
             maps: vec![MapRecord {
                 path: PathBuf::from("/usr/lib/libfoo.so"),
                 offset: 0,
                 length: 4096,
                 update_time: 10,
+                identity: None,
             }],
             exe_maps: vec![ExeMapRecord {
                 exe_path: PathBuf::from("/usr/bin/app"),
@@ -42,6 +48,9 @@ async fn sqlite_roundtrip_snapshot() {
             markov_edges: vec![MarkovRecord {
                 exe_a: PathBuf::from("/usr/bin/app"),
                 exe_b: PathBuf::from("/usr/bin/app2"),
+                state: MarkovState::AOnly,
+                last_change_time: 8,
+                state_last_left: [1, 2, 3, 4],
                 time_to_leave: [0.0; 4],
                 transition_prob: [[0.0; 4]; 4],
                 both_running_time: 0,
@@ -58,4 +67,507 @@ async fn sqlite_roundtrip_snapshot() {
     assert_eq!(loaded.state.exe_maps.len(), 1);
     assert_eq!(loaded.state.markov_edges.len(), 1);
     assert_eq!(loaded.state.model_time, 10);
+
+    let exe = &loaded.state.exes[0];
+    assert!(exe.running);
+    assert_eq!(exe.change_time, 8);
+    let edge = &loaded.state.markov_edges[0];
+    assert_eq!(edge.state, MarkovState::AOnly);
+    assert_eq!(edge.last_change_time, 8);
+    assert_eq!(edge.state_last_left, [1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn read_only_repository_never_writes() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+
+    let writer = SqliteRepository::new(db_path.clone()).await.unwrap();
+    let mut snapshot = writer.load().await.unwrap();
+    snapshot.state.model_time = 7;
+    writer.save(&snapshot).await.unwrap();
+
+    let reader = SqliteRepository::open_read_only(db_path.clone())
+        .await
+        .unwrap();
+    assert_eq!(reader.load().await.unwrap().state.model_time, 7);
+    snapshot.state.model_time = 8;
+    assert!(reader.save(&snapshot).await.is_err());
+    assert_eq!(writer.load().await.unwrap().state.model_time, 7);
+
+    let missing = dir.path().join("missing.db");
+    assert!(
+        SqliteRepository::open_read_only(missing.clone())
+            .await
+            .is_err()
+    );
+    assert!(!missing.exists());
+}
+
+#[tokio::test]
+async fn newer_databases_are_refused() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+
+    let repo = SqliteRepository::new(db_path.clone()).await.unwrap();
+    let mut snapshot = repo.load().await.unwrap();
+    snapshot.meta.schema_version = SNAPSHOT_SCHEMA_VERSION + 1;
+    repo.save(&snapshot).await.unwrap();
+
+    let unsupported = |err: Error| {
+        matches!(
+            err,
+            Error::UnsupportedSnapshotVersion { found, supported }
+                if found == SNAPSHOT_SCHEMA_VERSION + 1 && supported == SNAPSHOT_SCHEMA_VERSION
+        )
+    };
+    assert!(unsupported(repo.load().await.unwrap_err()));
+    drop(repo);
+    assert!(unsupported(
+        SqliteRepository::new(db_path.clone()).await.unwrap_err()
+    ));
+    let reader = SqliteRepository::open_read_only(db_path).await.unwrap();
+    assert!(unsupported(reader.load().await.unwrap_err()));
+}
+
+/// A model with one exe and `model_time` set, to tell saves apart.
+fn small_snapshot(model_time: u64) -> StoresSnapshot {
+    StoresSnapshot {
+        meta: SnapshotMeta {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            app_version: None,
+            created_at: None,
+        },
+        state: StateSnapshot {
+            model_time,
+            last_accounting_time: model_time,
+            exes: vec![ExeRecord {
+                path: PathBuf::from("/usr/bin/app"),
+                total_running_time: model_time,
+                last_seen_time: Some(model_time),
+                running: false,
+                change_time: 0,
+                identity: None,
+            }],
+            maps: Vec::new(),
+            exe_maps: Vec::new(),
+            markov_edges: Vec::new(),
+        },
+    }
+}
+
+#[tokio::test]
+async fn damaged_database_is_restored_from_backup() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+
+    let repo = SqliteRepository::new(db_path.clone())
+        .await
+        .unwrap()
+        .with_backups(2);
+    for model_time in [10, 20, 30] {
+        repo.save(&small_snapshot(model_time)).await.unwrap();
+    }
+    repo.close().await;
+    drop(repo);
+    assert!(dir.path().join("state.db.2").exists());
+    assert!(!dir.path().join("state.db.3").exists());
+
+    let set_aside = || {
+        std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with("state.db.corrupt-")
+            })
+            .count()
+    };
+
+    std::fs::write(&db_path, vec![0xA5; 8192]).unwrap();
+    let repo = SqliteRepository::new(db_path.clone()).await.unwrap();
+    assert_eq!(repo.load().await.unwrap().state.model_time, 30);
+    assert_eq!(set_aside(), 1);
+    repo.close().await;
+    drop(repo);
+
+    // A second failure keeps the evidence of the first.
+    std::fs::write(&db_path, vec![0x5A; 8192]).unwrap();
+    let repo = SqliteRepository::new(db_path).await.unwrap();
+    assert_eq!(repo.load().await.unwrap().state.model_time, 30);
+    assert_eq!(set_aside(), 2);
+}
+
+#[tokio::test]
+async fn only_damage_is_restored_and_only_when_repairing() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    let repo = SqliteRepository::new(db_path.clone())
+        .await
+        .unwrap()
+        .with_backups(1);
+    repo.save(&small_snapshot(10)).await.unwrap();
+    repo.close().await;
+    drop(repo);
+    let untouched = || {
+        std::fs::read_dir(dir.path()).unwrap().all(|entry| {
+            let name = entry.unwrap().file_name();
+            !name.to_string_lossy().contains("corrupt")
+        })
+    };
+
+    // Strict loading reports the damage and leaves the file alone.
+    std::fs::write(&db_path, vec![0xA5; 8192]).unwrap();
+    let err = SqliteRepository::new_strict(db_path.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidSnapshot(_)), "{err}");
+    assert!(untouched());
+    assert_eq!(std::fs::read(&db_path).unwrap(), vec![0xA5; 8192]);
+
+    // A `file` backend state is a mistake in the URL, not damage.
+    let file_repo = FileRepository::new(db_path.with_extension("bin")).unwrap();
+    file_repo.save(&small_snapshot(20)).await.unwrap();
+    drop(file_repo);
+    std::fs::rename(db_path.with_extension("bin"), &db_path).unwrap();
+    let err = SqliteRepository::new(db_path.clone()).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidSnapshot(_)), "{err}");
+    assert!(untouched());
+}
+
+#[tokio::test]
+async fn backups_follow_their_interval() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    let backup = dir.path().join("state.db.1");
+    let backed_up_time = || async {
+        let reader = SqliteRepository::open_read_only(backup.clone())
+            .await
+            .unwrap();
+        reader.load().await.unwrap().state.model_time
+    };
+
+    let repo = SqliteRepository::new(db_path.clone())
+        .await
+        .unwrap()
+        .with_backups(2)
+        .with_backup_interval(Duration::from_secs(3600));
+    repo.save(&small_snapshot(10)).await.unwrap();
+    repo.save(&small_snapshot(20)).await.unwrap();
+    assert_eq!(backed_up_time().await, 10);
+    assert!(!dir.path().join("state.db.2").exists());
+    repo.close().await;
+    drop(repo);
+
+    // Incremental saves refresh them too.
+    let repo = SqliteRepository::new(db_path)
+        .await
+        .unwrap()
+        .with_backups(2);
+    let snapshot = small_snapshot(30);
+    let delta = SnapshotDelta {
+        meta: snapshot.meta,
+        changed: snapshot.state,
+        removed_exes: Vec::new(),
+        removed_maps: Vec::new(),
+        removed_edges: Vec::new(),
+    };
+    assert!(repo.save_delta(&delta).await.unwrap());
+    assert_eq!(backed_up_time().await, 30);
+}
+
+#[tokio::test]
+async fn unreadable_rows_fall_back_to_backup() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+
+    let repo = SqliteRepository::new(db_path.clone())
+        .await
+        .unwrap()
+        .with_backups(1);
+    let mut snapshot = small_snapshot(10);
+    snapshot.state.exes.push(ExeRecord {
+        path: PathBuf::from("/usr/bin/other"),
+        ..snapshot.state.exes[0].clone()
+    });
+    snapshot.state.markov_edges.push(MarkovRecord {
+        exe_a: PathBuf::from("/usr/bin/app"),
+        exe_b: PathBuf::from("/usr/bin/other"),
+        state: MarkovState::Neither,
+        last_change_time: 10,
+        state_last_left: [10; 4],
+        time_to_leave: [1.0; 4],
+        transition_prob: [[0.0; 4]; 4],
+        both_running_time: 5,
+    });
+    repo.save(&snapshot).await.unwrap();
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", db_path.display()))
+        .await
+        .unwrap();
+    sqlx::query("UPDATE markovs SET time_to_leave = x'00'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+
+    let loaded = repo.load().await.unwrap();
+    assert_eq!(loaded.state.markov_edges.len(), 1);
+    assert_eq!(loaded.state.markov_edges[0].both_running_time, 5);
+}
+
+#[test]
+fn repair_drops_what_validate_rejects() {
+    let mut snapshot = small_snapshot(10);
+    let state = &mut snapshot.state;
+    state.exes.push(state.exes[0].clone());
+    state.maps.push(MapRecord {
+        path: PathBuf::from("/usr/lib/libfoo.so"),
+        offset: 0,
+        length: 4096,
+        update_time: 10,
+        identity: None,
+    });
+    state.exe_maps.push(ExeMapRecord {
+        exe_path: PathBuf::from("/usr/bin/app"),
+        map_key: MapKey::new("/usr/lib/libfoo.so", 0, 4096),
+        prob: 0.5,
+    });
+    state.exe_maps.push(ExeMapRecord {
+        exe_path: PathBuf::from("/usr/bin/gone"),
+        map_key: MapKey::new("/usr/lib/libfoo.so", 0, 4096),
+        prob: 0.5,
+    });
+    state.markov_edges.push(MarkovRecord {
+        exe_a: PathBuf::from("/usr/bin/app"),
+        exe_b: PathBuf::from("/usr/bin/gone"),
+        state: MarkovState::Neither,
+        last_change_time: 0,
+        state_last_left: [0; 4],
+        time_to_leave: [0.0; 4],
+        transition_prob: [[0.0; 4]; 4],
+        both_running_time: 0,
+    });
+    assert!(snapshot.validate().is_err());
+
+    let dropped = snapshot.repair();
+    assert_eq!(dropped.len(), 3, "{dropped:?}");
+    snapshot.validate().unwrap();
+    assert_eq!(snapshot.state.exes.len(), 1);
+    assert_eq!(snapshot.state.exe_maps.len(), 1);
+    assert!(snapshot.state.markov_edges.is_empty());
+}
+
+#[tokio::test]
+async fn non_utf8_paths_round_trip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempdir().unwrap();
+    let repo = SqliteRepository::new(dir.path().join("state.db"))
+        .await
+        .unwrap();
+
+    let exe = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/app"));
+    let lib = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/lib\xff.so"));
+    let mut snapshot = small_snapshot(10);
+    snapshot.state.exes[0].path = exe.clone();
+    snapshot.state.exes[0].identity = Some(ExeIdentity::Path(exe.clone()));
+    snapshot.state.maps.push(MapRecord {
+        path: lib.clone(),
+        offset: 0,
+        length: 4096,
+        update_time: 10,
+        identity: None,
+    });
+    snapshot.state.exe_maps.push(ExeMapRecord {
+        exe_path: exe.clone(),
+        map_key: MapKey::new(lib.clone(), 0, 4096),
+        prob: 1.0,
+    });
+    repo.save(&snapshot).await.unwrap();
+
+    let loaded = repo.load().await.unwrap();
+    loaded.validate().unwrap();
+    assert_eq!(loaded.state.exes[0].path, exe);
+    assert_eq!(
+        loaded.state.exes[0].identity,
+        Some(ExeIdentity::Path(exe.clone()))
+    );
+    assert_eq!(loaded.state.maps[0].path, lib);
+    assert_eq!(loaded.state.exe_maps[0].map_key.path, lib);
+}
+
+#[tokio::test]
+async fn file_repository_roundtrip() {
+    use orchestrator::domain::{ExeIdentity, FileIdentity};
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.bin");
+    let repo = FileRepository::new(path.clone()).unwrap();
+    assert!(repo.load().await.unwrap().state.exes.is_empty());
+
+    let lib = PathBuf::from(OsStr::from_bytes(b"/opt/caf\xe9/lib.so"));
+    let mut snapshot = small_snapshot(10);
+    snapshot.meta.app_version = Some("test".into());
+    snapshot.state.exes[0].identity = Some(ExeIdentity::BuildId("abcd".into()));
+    snapshot.state.exes.push(ExeRecord {
+        path: PathBuf::from("/usr/bin/other"),
+        ..snapshot.state.exes[0].clone()
+    });
+    snapshot.state.maps.push(MapRecord {
+        path: lib.clone(),
+        offset: 4096,
+        length: 8192,
+        update_time: 9,
+        identity: Some(FileIdentity {
+            device: 1,
+            inode: 2,
+            mtime_ns: -3,
+        }),
+    });
+    snapshot.state.exe_maps.push(ExeMapRecord {
+        exe_path: PathBuf::from("/usr/bin/app"),
+        map_key: MapKey::new(lib.clone(), 4096, 8192),
+        prob: 0.5,
+    });
+    snapshot.state.markov_edges.push(MarkovRecord {
+        exe_a: PathBuf::from("/usr/bin/app"),
+        exe_b: PathBuf::from("/usr/bin/other"),
+        state: MarkovState::BOnly,
+        last_change_time: 8,
+        state_last_left: [1, 2, 3, 4],
+        time_to_leave: [0.5; 4],
+        transition_prob: [[0.25; 4]; 4],
+        both_running_time: 6,
+    });
+    repo.save(&snapshot).await.unwrap();
+    assert!(!dir.path().join("state.bin.tmp").exists());
+
+    let loaded = FileRepository::open_read_only(path)
+        .unwrap()
+        .load()
+        .await
+        .unwrap();
+    loaded.validate().unwrap();
+    assert_eq!(loaded.meta.app_version.as_deref(), Some("test"));
+    assert_eq!(loaded.state.model_time, 10);
+    assert_eq!(
+        loaded.state.exes[0].identity,
+        Some(ExeIdentity::BuildId("abcd".into()))
+    );
+    let map = &loaded.state.maps[0];
+    assert_eq!(map.path, lib);
+    assert_eq!(map.identity.map(|id| id.mtime_ns), Some(-3));
+    assert_eq!(loaded.state.exe_maps[0].prob, 0.5);
+    let edge = &loaded.state.markov_edges[0];
+    assert_eq!(edge.state, MarkovState::BOnly);
+    assert_eq!(edge.state_last_left, [1, 2, 3, 4]);
+    assert_eq!(edge.transition_prob, [[0.25; 4]; 4]);
+    assert_eq!(edge.both_running_time, 6);
+}
+
+#[tokio::test]
+async fn damaged_state_file_is_read_from_backup() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.bin");
+
+    let repo = FileRepository::new(path.clone()).unwrap().with_backups(2);
+    for model_time in [10, 20] {
+        repo.save(&small_snapshot(model_time)).await.unwrap();
+    }
+    let mut contents = std::fs::read(&path).unwrap();
+    let last = contents.len() - 1;
+    contents[last] ^= 0xff;
+    std::fs::write(&path, contents).unwrap();
+
+    assert_eq!(repo.load().await.unwrap().state.model_time, 20);
+
+    let reader = FileRepository::open_read_only(path).unwrap();
+    assert!(reader.save(&small_snapshot(30)).await.is_err());
+    assert!(FileRepository::open_read_only(dir.path().join("missing.bin")).is_err());
+}
+
+#[tokio::test]
+async fn second_writer_is_refused() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    let bin_path = dir.path().join("state.bin");
+    let locked = |err: Error| matches!(err, Error::StateLocked { pid, .. } if pid == Some(std::process::id()));
+
+    let writer = SqliteRepository::new(db_path.clone()).await.unwrap();
+    assert!(locked(
+        SqliteRepository::new(db_path.clone()).await.unwrap_err()
+    ));
+    let reader = SqliteRepository::open_read_only(db_path.clone())
+        .await
+        .unwrap();
+    reader.load().await.unwrap();
+    drop(writer);
+    SqliteRepository::new(db_path).await.unwrap();
+
+    let writer = FileRepository::new(bin_path.clone()).unwrap();
+    writer.save(&small_snapshot(10)).await.unwrap();
+    assert!(locked(FileRepository::new(bin_path.clone()).unwrap_err()));
+    let reader = FileRepository::open_read_only(bin_path.clone()).unwrap();
+    assert_eq!(reader.load().await.unwrap().state.model_time, 10);
+    drop(writer);
+    FileRepository::new(bin_path).unwrap();
+}
+
+/// A database written before any later migration, with one exe.
+async fn baseline_database(path: &std::path::Path) {
+    let migrations = tempdir().unwrap();
+    std::fs::copy(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/migrations/20260131000000_init.sql"
+        ),
+        migrations.path().join("20260131000000_init.sql"),
+    )
+    .unwrap();
+    let options = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+    sqlx::migrate::Migrator::new(migrations.path())
+        .await
+        .unwrap()
+        .run(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO state (id, schema_version, model_time, last_accounting_time) \
+         VALUES (1, 1, 42, 40)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO exes VALUES ('/usr/bin/app', 30, 40)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+}
+
+#[tokio::test]
+async fn older_databases_open_read_only() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("state.db");
+    baseline_database(&db_path).await;
+    let before = std::fs::read(&db_path).unwrap();
+
+    let reader = SqliteRepository::open_read_only(db_path.clone())
+        .await
+        .unwrap();
+    let loaded = reader.load().await.unwrap();
+    assert_eq!(loaded.meta.schema_version, SNAPSHOT_SCHEMA_VERSION);
+    assert_eq!(loaded.state.model_time, 42);
+    assert_eq!(loaded.state.exes.len(), 1);
+    assert_eq!(loaded.state.exes[0].path, PathBuf::from("/usr/bin/app"));
+    assert!(reader.save(&loaded).await.is_err());
+    drop(reader);
+
+    assert_eq!(std::fs::read(&db_path).unwrap(), before);
 }
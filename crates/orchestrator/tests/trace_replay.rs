@@ -0,0 +1,136 @@
+#![forbid(unsafe_code)]
+
+mod common;
+
+use common::ProcFixture;
+use config::Config;
+use orchestrator::clock::SystemClock;
+use orchestrator::error::Error;
+use orchestrator::observation::{
+    DefaultAdmissionPolicy, DefaultModelUpdater, ObservationEvent, ProcfsScanner, RecordingScanner,
+    ReplayScanner, Scanner, TraceReader,
+};
+use orchestrator::persistence::NoopRepository;
+use orchestrator::prediction::MarkovPredictor;
+use orchestrator::prefetch::{GreedyPrefetchPlanner, NoopMapValidator, NoopPrefetcher};
+use orchestrator::{PreloadEngine, Services};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+fn config() -> Config {
+    let mut config = Config::default();
+    config.model.minsize = 1;
+    config.system.exeprefix = vec!["!/".into(), "/usr/".into()];
+    config.system.mapprefix = vec!["!/".into(), "/usr/".into()];
+    config
+}
+
+async fn engine(config: &Config, scanner: Box<dyn Scanner + Send + Sync>) -> PreloadEngine {
+    let services = Services {
+        scanner,
+        admission: Box::new(DefaultAdmissionPolicy::new(config)),
+        updater: Box::new(DefaultModelUpdater::new(config)),
+        predictor: Box::new(MarkovPredictor::new(config)),
+        planner: Box::new(GreedyPrefetchPlanner::new(config)),
+        validator: Box::new(NoopMapValidator),
+        prefetcher: Box::new(NoopPrefetcher),
+        repo: Box::new(NoopRepository),
+        clock: Box::new(SystemClock),
+    };
+    PreloadEngine::new(config.clone(), services).await.unwrap()
+}
+
+/// Exe path -> (total running time, number of attached maps).
+fn summary(engine: &PreloadEngine) -> BTreeMap<PathBuf, (u64, usize)> {
+    let stores = engine.stores();
+    stores
+        .exes
+        .iter()
+        .map(|(id, exe)| {
+            (
+                exe.key.path().to_path_buf(),
+                (
+                    exe.total_running_time,
+                    stores.exe_maps.maps_for_exe(id).count(),
+                ),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn replayed_trace_rebuilds_the_recorded_model() {
+    let fixture = ProcFixture::new();
+    let dir = tempfile::tempdir().unwrap();
+    let trace_path = dir.path().join("trace.jsonl");
+    let config = config();
+
+    let scanner = RecordingScanner::create(
+        Box::new(ProcfsScanner::with_root(fixture.root())),
+        &trace_path,
+    )
+    .unwrap();
+    let mut live = engine(&config, Box::new(scanner)).await;
+
+    fixture
+        .process(10, "/usr/bin/editor")
+        .map("/usr/lib/libedit.so", 0, 8192);
+    live.tick().await.unwrap();
+    fixture
+        .process(11, "/usr/bin/shell")
+        .map("/usr/lib/libc.so", 0, 4096);
+    live.tick().await.unwrap();
+    fixture.exit(10);
+    live.tick().await.unwrap();
+
+    let mut replay = engine(&config, Box::new(ReplayScanner::open(&trace_path).unwrap())).await;
+    for _ in 0..3 {
+        replay.tick().await.unwrap();
+    }
+    assert!(matches!(replay.tick().await, Err(Error::TraceExhausted)));
+
+    assert_eq!(summary(&replay), summary(&live));
+    assert_eq!(summary(&live).len(), 2);
+    assert_eq!(
+        replay.stores().markov.iter().count(),
+        live.stores().markov.iter().count()
+    );
+}
+
+#[test]
+fn replay_restamps_times_and_scan_ids() {
+    let fixture = ProcFixture::new();
+    fixture
+        .process(10, "/usr/bin/editor")
+        .map("/usr/lib/libedit.so", 0, 8192);
+    let dir = tempfile::tempdir().unwrap();
+    let trace_path = dir.path().join("trace.jsonl");
+
+    let mut recorder = RecordingScanner::create(
+        Box::new(ProcfsScanner::with_root(fixture.root())),
+        &trace_path,
+    )
+    .unwrap();
+    let recorded = recorder.scan(100, 7).unwrap();
+    drop(recorder);
+
+    let reader = TraceReader::open(&trace_path).unwrap();
+    assert_eq!(reader.count(), 1);
+
+    let mut replay = ReplayScanner::open(&trace_path).unwrap();
+    let replayed = replay.scan(5, 1).unwrap();
+    assert_eq!(replay.replayed(), 1);
+    assert_eq!(replayed.len(), recorded.len());
+
+    for event in &replayed {
+        match event {
+            ObservationEvent::ObsBegin { time, scan_id }
+            | ObservationEvent::ObsEnd { time, scan_id, .. } => {
+                assert_eq!((*time, *scan_id), (5, 1));
+            }
+            ObservationEvent::MapSeen { map, .. } => assert_eq!(map.update_time, 5),
+            ObservationEvent::ExeSeen { .. } | ObservationEvent::MemStat { .. } => {}
+        }
+    }
+    assert!(matches!(replay.scan(10, 2), Err(Error::TraceExhausted)));
+}